@@ -1,8 +1,8 @@
 use std::fmt;
 use std::sync::Arc;
 use types::{Params, Value, Error};
-use futures::{Future, IntoFuture};
-use BoxFuture;
+use futures::{Future, IntoFuture, Stream};
+use {BoxFuture, BoxStream};
 
 /// Metadata trait
 pub trait Metadata: Clone + Send + 'static {}
@@ -25,6 +25,33 @@ pub trait RpcMethod<T: Metadata>: Send + Sync + 'static {
 	fn call(&self, params: Params, meta: T) -> BoxFuture<Value>;
 }
 
+/// Result of a passthrough method.
+#[derive(Debug, PartialEq, Clone)]
+pub enum HandlerResult {
+	/// Result to be wrapped in a regular response.
+	Value(Value),
+	/// Complete serialized JSON-RPC response.
+	Passthrough(String),
+}
+
+/// Asynchronous Method with Metadata that can return complete responses
+pub trait RpcPassthroughMethod<T: Metadata>: Send + Sync + 'static {
+	/// Call method
+	fn call(&self, params: Params, meta: T) -> BoxFuture<HandlerResult>;
+}
+
+/// Asynchronous Method with Metadata streaming multiple results of a single call
+pub trait RpcStreamingMethod<T: Metadata>: Send + Sync + 'static {
+	/// Call method
+	fn call(&self, params: Params, meta: T) -> BoxStream<Value>;
+}
+
+/// Asynchronous Method with Metadata handling calls of methods that are not registered
+pub trait RpcFallbackMethod<T: Metadata>: Send + Sync + 'static {
+	/// Call method of given name
+	fn call(&self, method: String, params: Params, meta: T) -> BoxFuture<Value>;
+}
+
 /// Notification
 pub trait RpcNotificationSimple: Send + Sync + 'static {
 	/// Execute notification
@@ -89,6 +116,38 @@ impl<F: Send + Sync + 'static, X: Send + 'static, T, I> RpcMethod<T> for F where
 	}
 }
 
+impl<F: Send + Sync + 'static, X: Send + 'static, T, I> RpcPassthroughMethod<T> for F where
+	T: Metadata,
+	F: Fn(Params, T) -> I,
+	I: IntoFuture<Item = HandlerResult, Error = Error, Future = X>,
+	X: Future<Item = HandlerResult, Error = Error>,
+{
+	fn call(&self, params: Params, meta: T) -> BoxFuture<HandlerResult> {
+		Box::new(self(params, meta).into_future())
+	}
+}
+
+impl<F: Send + Sync + 'static, X: Send + 'static, T> RpcStreamingMethod<T> for F where
+	T: Metadata,
+	F: Fn(Params, T) -> X,
+	X: Stream<Item = Value, Error = Error>,
+{
+	fn call(&self, params: Params, meta: T) -> BoxStream<Value> {
+		Box::new(self(params, meta))
+	}
+}
+
+impl<F: Send + Sync + 'static, X: Send + 'static, T, I> RpcFallbackMethod<T> for F where
+	T: Metadata,
+	F: Fn(String, Params, T) -> I,
+	I: IntoFuture<Item = Value, Error = Error, Future = X>,
+	X: Future<Item = Value, Error = Error>,
+{
+	fn call(&self, method: String, params: Params, meta: T) -> BoxFuture<Value> {
+		Box::new(self(method, params, meta).into_future())
+	}
+}
+
 impl<F: Send + Sync + 'static, T> RpcNotification<T> for F where
 	T: Metadata,
 	F: Fn(Params, T),
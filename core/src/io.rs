@@ -1,14 +1,22 @@
-use std::sync::Arc;
-use std::collections::HashMap;
+use std::{error, fmt};
+use std::sync::{Arc, Mutex};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::{Duration, Instant};
 use std::ops::{Deref, DerefMut};
 
+use serde::Serialize;
+use serde::de::DeserializeOwned;
 use serde_json;
-use futures::{self, future, Future};
+use futures::{self, future, Future, IntoFuture, Stream};
 
 use calls::{RemoteProcedure, Metadata, RpcMethodSimple, RpcMethod, RpcNotificationSimple, RpcNotification};
+use calls::{HandlerResult, RpcFallbackMethod, RpcPassthroughMethod, RpcStreamingMethod};
 use middleware::{self, Middleware};
-use types::{Error, ErrorCode, Version};
-use types::{Request, Response, Call, Output};
+use types::{Error, ErrorCode, Params, Value, Version};
+use types::{Request, Response, Call, MethodCall, Output};
+use {BoxFuture, BoxStream};
+
+const LOCK_PROOF: &'static str = "Locks are never held across method calls and holders don't panic; qed";
 
 /// A type representing middleware or RPC response before serialization.
 pub type FutureResponse = Box<Future<Item=Option<Response>, Error=()> + Send>;
@@ -25,6 +33,17 @@ pub type FutureOutput = future::Either<
 	future::FutureResult<Option<Output>, ()>,
 >;
 
+/// A type representing a streaming call passed through the middleware.
+pub type FutureStreamingCall = Box<Future<Item=StreamingCall, Error=()> + Send>;
+
+/// Outcome of a call of a streaming method passed through the middleware.
+pub enum StreamingCall {
+	/// The method was called and its results are streamed.
+	Results(BoxStream<Value>),
+	/// The call was answered with a regular response (e.g. rejected by the middleware).
+	Response(Option<Response>),
+}
+
 /// `IoHandler` json-rpc protocol compatibility
 #[derive(Debug, Clone, Copy)]
 pub enum Compatibility {
@@ -33,6 +52,9 @@ pub enum Compatibility {
 	/// Compatible only with JSON-RPC 2.0
 	V2,
 	/// Compatible with both
+	///
+	/// The version is detected per call: calls without `jsonrpc` field get 1.x outputs
+	/// and calls with `"jsonrpc": "2.0"` get 2.0 outputs.
 	Both,
 }
 
@@ -60,14 +82,95 @@ impl Compatibility {
 	}
 }
 
+/// Error returned when registering a method under a name that is already taken.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DuplicateMethod(pub String);
+
+impl fmt::Display for DuplicateMethod {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "Method {} is already registered.", self.0)
+	}
+}
+
+impl error::Error for DuplicateMethod {
+	fn description(&self) -> &str {
+		"method is already registered"
+	}
+}
+
+/// Error returned when a method can't be registered with `try_add_method`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RegistrationError {
+	/// Method with the same name is already registered.
+	Duplicate(DuplicateMethod),
+	/// Maximal number of methods (see `MetaIoHandler::set_max_methods`) is already registered.
+	TooManyMethods(usize),
+}
+
+impl From<DuplicateMethod> for RegistrationError {
+	fn from(err: DuplicateMethod) -> Self {
+		RegistrationError::Duplicate(err)
+	}
+}
+
+impl fmt::Display for RegistrationError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			RegistrationError::Duplicate(ref err) => fmt::Display::fmt(err, f),
+			RegistrationError::TooManyMethods(limit) => write!(f, "Maximal number of {} methods is already registered.", limit),
+		}
+	}
+}
+
+impl error::Error for RegistrationError {
+	fn description(&self) -> &str {
+		match *self {
+			RegistrationError::Duplicate(ref err) => error::Error::description(err),
+			RegistrationError::TooManyMethods(_) => "too many methods registered",
+		}
+	}
+}
+
+/// Access classification of a method, e.g. to route read-only calls to replicas.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MethodAccess {
+	/// Method doesn't modify any state.
+	ReadOnly,
+	/// Method may modify state.
+	Mutating,
+}
+
 /// Request handler
 ///
 /// By default compatible only with jsonrpc v2
-#[derive(Debug)]
+#[derive(Clone)]
 pub struct MetaIoHandler<T: Metadata, S: Middleware<T> = middleware::Noop> {
 	middleware: S,
 	compatibility: Compatibility,
 	methods: HashMap<String, RemoteProcedure<T>>,
+	access: HashMap<String, MethodAccess>,
+	passthrough: HashMap<String, Arc<RpcPassthroughMethod<T>>>,
+	streaming: HashMap<String, Arc<RpcStreamingMethod<T>>>,
+	// Methods parsing their params right away when called (see `add_method_typed`).
+	typed: HashSet<String>,
+	fallback: Option<Arc<RpcFallbackMethod<T>>>,
+	max_methods: Option<usize>,
+}
+
+impl<T: Metadata, S: Middleware<T> + fmt::Debug> fmt::Debug for MetaIoHandler<T, S> {
+	fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+		fmt.debug_struct("MetaIoHandler")
+			.field("middleware", &self.middleware)
+			.field("compatibility", &self.compatibility)
+			.field("methods", &self.methods)
+			.field("access", &self.access)
+			.field("passthrough", &self.passthrough.keys().collect::<Vec<_>>())
+			.field("streaming", &self.streaming.keys().collect::<Vec<_>>())
+			.field("typed", &self.typed)
+			.field("fallback", &self.fallback.is_some())
+			.field("max_methods", &self.max_methods)
+			.finish()
+	}
 }
 
 impl<T: Metadata> Default for MetaIoHandler<T> {
@@ -83,8 +186,19 @@ impl<T: Metadata> MetaIoHandler<T> {
 			compatibility: compatibility,
 			middleware: Default::default(),
 			methods: Default::default(),
+			access: Default::default(),
+			passthrough: Default::default(),
+			streaming: Default::default(),
+			typed: Default::default(),
+			fallback: None,
+			max_methods: None,
 		}
 	}
+
+	/// Converts this handler into `IoHandler`, which provides default metadata to each request.
+	pub fn into_io_handler(self) -> IoHandler<T> {
+		IoHandler(self)
+	}
 }
 
 
@@ -95,6 +209,12 @@ impl<T: Metadata, S: Middleware<T>> MetaIoHandler<T, S> {
 			compatibility: compatibility,
 			middleware: middleware,
 			methods: Default::default(),
+			access: Default::default(),
+			passthrough: Default::default(),
+			streaming: Default::default(),
+			typed: Default::default(),
+			fallback: None,
+			max_methods: None,
 		}
 	}
 
@@ -104,10 +224,25 @@ impl<T: Metadata, S: Middleware<T>> MetaIoHandler<T, S> {
 			compatibility: Default::default(),
 			middleware: middleware,
 			methods: Default::default(),
+			access: Default::default(),
+			passthrough: Default::default(),
+			streaming: Default::default(),
+			typed: Default::default(),
+			fallback: None,
+			max_methods: None,
 		}
 	}
 
+	/// Limits the number of methods, notifications and aliases that can be registered
+	/// with `try_add_method`.
+	pub fn set_max_methods(&mut self, limit: usize) {
+		self.max_methods = Some(limit);
+	}
+
 	/// Adds an alias to a method.
+	///
+	/// The alias is resolved when called, so the method can be registered after the alias.
+	/// Calls of an alias to a method that is not registered fail with `Method not found` error.
 	pub fn add_alias(&mut self, alias: &str, other: &str) {
 		self.methods.insert(
 			alias.into(),
@@ -124,6 +259,164 @@ impl<T: Metadata, S: Middleware<T>> MetaIoHandler<T, S> {
 		})
 	}
 
+	/// Adds all given methods at once, e.g. from a `HashMap` of method names to handlers.
+	pub fn add_methods<I, F>(&mut self, methods: I) where
+		I: IntoIterator<Item = (String, F)>,
+		F: RpcMethodSimple,
+	{
+		for (name, method) in methods {
+			self.add_method(&name, method);
+		}
+	}
+
+	/// Adds new supported asynchronous method that is only available if `gate` returns `true`
+	/// for the request metadata. Otherwise the method behaves as if it wasn't registered.
+	pub fn add_method_gated<G, F>(&mut self, name: &str, gate: G, method: F) where
+		G: Fn(&T) -> bool + Send + Sync + 'static,
+		F: RpcMethodSimple,
+	{
+		self.add_method_with_meta(name, move |params, meta| {
+			if gate(&meta) {
+				future::Either::A(method.call(params))
+			} else {
+				future::Either::B(future::err(Error::method_not_found()))
+			}
+		})
+	}
+
+	/// Adds new supported asynchronous method that can be called at most `limit` times
+	/// within `window` for each quota key derived from the request metadata.
+	///
+	/// Calls over the quota fail with `-32000` error containing number of seconds
+	/// after which the quota is renewed in `retry_after` field of error data.
+	pub fn add_method_with_quota<K, F>(&mut self, name: &str, key: K, limit: usize, window: Duration, method: F) where
+		K: Fn(&T) -> String + Send + Sync + 'static,
+		F: RpcMethodSimple,
+	{
+		// Windows have the same length, so quotas expire in the order they were started.
+		let quotas = Mutex::new(Expiring::new(window, usize::max_value()));
+		self.add_method_with_meta(name, move |params, meta| {
+			let key = key(&meta);
+			let now = Instant::now();
+			// The lock is released before the method is called.
+			let retry_after = {
+				let mut quotas = quotas.lock().expect(LOCK_PROOF);
+				if quotas.get(&key, now).is_none() {
+					quotas.insert(key.clone(), 0, now);
+				}
+				let &mut (start, ref mut calls) = quotas.get(&key, now).expect("Quota was inserted above; qed");
+				if *calls < limit {
+					*calls += 1;
+					None
+				} else {
+					Some(window - now.duration_since(start))
+				}
+			};
+
+			match retry_after {
+				None => future::Either::A(method.call(params)),
+				Some(retry_after) => future::Either::B(future::err(quota_exceeded(retry_after))),
+			}
+		})
+	}
+
+	/// Adds new supported asynchronous method with results cached for `ttl`.
+	///
+	/// Results are cached for each distinct value of parameters; errors are never cached.
+	/// At most `capacity` results are kept, evicting the oldest ones first.
+	/// Use only for idempotent methods, since cached calls don't invoke the method at all.
+	pub fn add_cached_method<F>(&mut self, name: &str, ttl: Duration, capacity: usize, method: F) where
+		F: RpcMethodSimple,
+	{
+		let cache = Arc::new(Mutex::new(Expiring::new(ttl, capacity)));
+		self.add_method_with_meta(name, move |params: Params, _meta| {
+			let key = serde_json::to_string(&params).expect("Serialization of params is infallible; qed");
+			if let Some(&mut (_, ref value)) = cache.lock().expect(LOCK_PROOF).get(&key, Instant::now()) {
+				return future::Either::A(future::ok(value.clone()));
+			}
+
+			let cache = cache.clone();
+			future::Either::B(method.call(params).map(move |value: Value| {
+				cache.lock().expect(LOCK_PROOF).insert(key, value.clone(), Instant::now());
+				value
+			}))
+		})
+	}
+
+	/// Adds new supported asynchronous method that takes no parameters.
+	///
+	/// Calls with parameters other than none or an empty array fail with `InvalidParams` error
+	/// without running the method at all.
+	pub fn add_method_no_params<F>(&mut self, name: &str, method: F) where
+		F: RpcMethodSimple,
+	{
+		self.add_method_with_meta(name, move |params: Params, _meta| match params {
+			Params::None => future::Either::A(method.call(Params::None)),
+			Params::Array(ref values) if values.is_empty() => future::Either::A(method.call(Params::None)),
+			_ => future::Either::B(future::err(Error::invalid_params("Invalid params: method takes no parameters."))),
+		})
+	}
+
+	/// Adds new supported asynchronous method with typed parameters and result.
+	///
+	/// Parameters are deserialized by `handle_call` before the call is dispatched
+	/// (e.g. on the thread reading the request rather than a thread pool running the method),
+	/// so calls with invalid parameters fail with `InvalidParams` error without running the method at all.
+	pub fn add_method_typed<P, R, F, I>(&mut self, name: &str, method: F) where
+		P: DeserializeOwned + Send + 'static,
+		R: Serialize + 'static,
+		F: Fn(P) -> I + Send + Sync + 'static,
+		I: IntoFuture<Item = R, Error = Error>,
+		I::Future: Send + 'static,
+	{
+		let method = Arc::new(method);
+		self.add_method_with_meta(name, move |params: Params, _meta| match params.parse::<P>() {
+			Ok(params) => {
+				let method = method.clone();
+				future::Either::A(futures::lazy(move || method(params).into_future()).and_then(|result| {
+					serde_json::to_value(result).map_err(|_| Error::internal_error())
+				}))
+			},
+			Err(error) => future::Either::B(future::err(error)),
+		});
+		self.typed.insert(name.into());
+	}
+
+	/// Adds new supported asynchronous method.
+	/// Unlike `add_method` fails if method, notification or alias with the same name is already registered
+	/// or if the maximal number of methods is reached.
+	pub fn try_add_method<F>(&mut self, name: &str, method: F) -> Result<(), RegistrationError> where
+		F: RpcMethodSimple,
+	{
+		if self.has_method(name) {
+			return Err(DuplicateMethod(name.into()).into());
+		}
+		if let Some(limit) = self.max_methods {
+			if self.methods.len() >= limit {
+				return Err(RegistrationError::TooManyMethods(limit));
+			}
+		}
+
+		self.add_method(name, method);
+		Ok(())
+	}
+
+	/// Adds new supported asynchronous method classified as `MethodAccess::ReadOnly`.
+	pub fn add_method_ro<F>(&mut self, name: &str, method: F) where
+		F: RpcMethodSimple,
+	{
+		self.add_method(name, method);
+		self.access.insert(name.into(), MethodAccess::ReadOnly);
+	}
+
+	/// Adds new supported asynchronous method classified as `MethodAccess::Mutating`.
+	pub fn add_method_rw<F>(&mut self, name: &str, method: F) where
+		F: RpcMethodSimple,
+	{
+		self.add_method(name, method);
+		self.access.insert(name.into(), MethodAccess::Mutating);
+	}
+
 	/// Adds new supported notification
 	pub fn add_notification<F>(&mut self, name: &str, notification: F) where
 		F: RpcNotificationSimple,
@@ -131,20 +424,90 @@ impl<T: Metadata, S: Middleware<T>> MetaIoHandler<T, S> {
 		self.add_notification_with_meta(name, move |params, _meta| notification.execute(params))
 	}
 
+	/// Sets a handler of calls to methods that are not registered.
+	///
+	/// The handler receives the name of the called method and its parameters.
+	/// Without a fallback such calls fail with `Method not found` error.
+	pub fn set_fallback<F, X, I>(&mut self, fallback: F) where
+		F: Fn(String, Params) -> I + Send + Sync + 'static,
+		I: IntoFuture<Item = Value, Error = Error, Future = X>,
+		X: Future<Item = Value, Error = Error> + Send + 'static,
+	{
+		self.set_fallback_with_meta(move |method: String, params: Params, _meta: T| fallback(method, params))
+	}
+
+	/// Sets a handler of calls to methods that are not registered, with metadata support.
+	pub fn set_fallback_with_meta<F>(&mut self, fallback: F) where
+		F: RpcFallbackMethod<T>,
+	{
+		self.fallback = Some(Arc::new(fallback));
+	}
+
 	/// Adds new supported asynchronous method with metadata support.
 	pub fn add_method_with_meta<F>(&mut self, name: &str, method: F) where
 		F: RpcMethod<T>,
 	{
+		self.access.remove(name);
+		self.passthrough.remove(name);
+		self.streaming.remove(name);
+		self.typed.remove(name);
 		self.methods.insert(
 			name.into(),
 			RemoteProcedure::Method(Arc::new(method)),
 		);
 	}
 
+	/// Adds new supported asynchronous method with metadata support
+	/// that can return a complete, already serialized response (`HandlerResult::Passthrough`).
+	///
+	/// Calls go through the middleware as usual. Passthrough responses are kept in outputs
+	/// of the calls (see `Output::passthrough`) and written verbatim by `Response::write_json`.
+	pub fn add_passthrough_method_with_meta<F>(&mut self, name: &str, method: F) where
+		F: RpcPassthroughMethod<T>,
+	{
+		let method = Arc::new(method);
+		let wrapped = method.clone();
+		self.add_method_with_meta(name, move |params, meta| {
+			wrapped.call(params, meta).and_then(|result| match result {
+				HandlerResult::Value(value) => Ok(value),
+				HandlerResult::Passthrough(response) => read_passthrough(&response),
+			})
+		});
+		self.passthrough.insert(name.into(), method);
+	}
+
+	/// Adds new supported method streaming multiple results of a single call,
+	/// e.g. for long-running subscriptions.
+	pub fn add_subscription_method<F, X>(&mut self, name: &str, method: F) where
+		F: Fn(Params) -> X + Send + Sync + 'static,
+		X: Stream<Item = Value, Error = Error> + Send + 'static,
+	{
+		self.add_subscription_method_with_meta(name, move |params, _meta: T| method(params))
+	}
+
+	/// Adds new supported method with metadata support streaming multiple results of a single call.
+	///
+	/// Transports able to stream the results call the method with `handle_streaming_call`.
+	/// Otherwise calls are handled as usual and return an array of all results once the stream ends.
+	pub fn add_subscription_method_with_meta<F>(&mut self, name: &str, method: F) where
+		F: RpcStreamingMethod<T>,
+	{
+		let method = Arc::new(method);
+		let wrapped = method.clone();
+		self.add_method_with_meta(name, move |params, meta| {
+			wrapped.call(params, meta).collect().map(Value::Array)
+		});
+		self.streaming.insert(name.into(), method);
+	}
+
 	/// Adds new supported notification with metadata support.
 	pub fn add_notification_with_meta<F>(&mut self, name: &str, notification: F) where
 		F: RpcNotification<T>,
 	{
+		self.access.remove(name);
+		self.passthrough.remove(name);
+		self.streaming.remove(name);
+		self.typed.remove(name);
 		self.methods.insert(
 			name.into(),
 			RemoteProcedure::Notification(Arc::new(notification)),
@@ -158,6 +521,58 @@ impl<T: Metadata, S: Middleware<T>> MetaIoHandler<T, S> {
 		self.methods.extend(methods.into())
 	}
 
+	/// Returns `true` if a method, notification or alias with given name is registered.
+	pub fn has_method(&self, name: &str) -> bool {
+		self.methods.contains_key(name)
+	}
+
+	/// Returns names of all registered methods and aliases of methods in alphabetical order,
+	/// e.g. to implement a discovery method.
+	///
+	/// Notifications and aliases of notifications or unknown methods are not included.
+	pub fn methods(&self) -> Vec<String> {
+		let mut names: Vec<_> = self.methods.iter().filter(|&(_, procedure)| match *procedure {
+			RemoteProcedure::Method(_) => true,
+			RemoteProcedure::Alias(ref alias) => match self.methods.get(alias) {
+				Some(&RemoteProcedure::Method(_)) => true,
+				_ => false,
+			},
+			RemoteProcedure::Notification(_) => false,
+		}).map(|(name, _)| name.clone()).collect();
+		names.sort();
+		names
+	}
+
+	/// Returns access classification of a method registered with `add_method_ro` or `add_method_rw`.
+	///
+	/// Aliases are resolved to the methods they point to.
+	/// Returns `None` for unclassified or unknown methods.
+	pub fn method_access(&self, name: &str) -> Option<MethodAccess> {
+		match self.methods.get(name) {
+			Some(&RemoteProcedure::Alias(ref alias)) => self.access.get(alias).cloned(),
+			Some(_) => self.access.get(name).cloned(),
+			None => None,
+		}
+	}
+
+	/// Returns a method registered with `add_subscription_method`, resolving aliases.
+	pub fn streaming_method(&self, name: &str) -> Option<Arc<RpcStreamingMethod<T>>> {
+		match self.methods.get(name) {
+			Some(&RemoteProcedure::Alias(ref alias)) => self.streaming.get(alias).cloned(),
+			Some(_) => self.streaming.get(name).cloned(),
+			None => None,
+		}
+	}
+
+	/// Removes a method, notification or alias with given name.
+	pub fn remove_method(&mut self, name: &str) -> Option<RemoteProcedure<T>> {
+		self.access.remove(name);
+		self.passthrough.remove(name);
+		self.streaming.remove(name);
+		self.typed.remove(name);
+		self.methods.remove(name)
+	}
+
 	/// Handle given request synchronously - will block until response is available.
 	/// If you have any asynchronous methods in your RPC it is much wiser to use
 	/// `handle_request` instead and deal with asynchronous requests in a non-blocking fashion.
@@ -187,17 +602,85 @@ impl<T: Metadata, S: Middleware<T>> MetaIoHandler<T, S> {
 		result.map(as_string)
 	}
 
+	fn passthrough_method(&self, call: &MethodCall) -> Option<Arc<RpcPassthroughMethod<T>>> {
+		if !self.compatibility.is_version_valid(call.jsonrpc) {
+			return None;
+		}
+
+		match self.methods.get(&call.method) {
+			Some(&RemoteProcedure::Method(_)) => self.passthrough.get(&call.method).cloned(),
+			Some(&RemoteProcedure::Alias(ref alias)) => self.passthrough.get(alias).cloned(),
+			_ => None,
+		}
+	}
+
+	/// Handle a stream of string requests asynchronously.
+	///
+	/// Requests are processed one after another and responses are returned in the same order.
+	/// Notifications don't produce any items.
+	pub fn handle_stream<'a, R>(&'a self, requests: R, meta: T) -> Box<Stream<Item=String, Error=R::Error> + 'a> where
+		R: Stream<Item=String> + 'a,
+	{
+		Box::new(requests
+			.and_then(move |request| {
+				self.handle_request(&request, meta.clone()).then(|response| Ok(response.unwrap_or(None)))
+			})
+			.filter_map(|response| response))
+	}
+
 	/// Handle deserialized RPC request.
 	pub fn handle_rpc_request(&self, request: Request, meta: T) -> S::Future {
+		self.middleware.on_request(request, meta, |request, meta| self.process_request(request, meta))
+	}
+
+	/// Handle a call of a streaming method asynchronously, passing it through the middleware first.
+	///
+	/// The middleware sees the call as any other, except that its response is `None`
+	/// once the method was called, since the results are streamed instead.
+	pub fn handle_streaming_call(&self, call: MethodCall, meta: T) -> FutureStreamingCall {
 		use self::future::Either::{A, B};
 
-		self.middleware.on_request(request, meta, |request, meta| match request {
+		let results = Arc::new(Mutex::new(None));
+		let started = results.clone();
+		let response = self.middleware.on_request(Request::Single(Call::MethodCall(call)), meta, move |request, meta| {
+			let method = match request {
+				Request::Single(Call::MethodCall(ref call)) => self.streaming_method(&call.method),
+				_ => None,
+			};
+			match (method, request) {
+				(Some(method), Request::Single(Call::MethodCall(call))) => {
+					*started.lock().expect(LOCK_PROOF) = Some(method.call(call.params, meta));
+					A(future::ok(None))
+				},
+				// The middleware replaced the call.
+				(_, request) => B(self.process_request(request, meta)),
+			}
+		});
+
+		Box::new(response.map(move |response| match results.lock().expect(LOCK_PROOF).take() {
+			Some(results) => StreamingCall::Results(results),
+			None => StreamingCall::Response(response),
+		}))
+	}
+
+	/// Handle a request that already passed the middleware.
+	fn process_request(&self, request: Request, meta: T) -> FutureResponse {
+		match request {
 			Request::Single(call) => {
-				A(self.handle_call(call, meta).map(|output| output.map(Response::Single)))
+				Box::new(self.handle_call(call, meta).map(|output| output.map(Response::Single)))
 			},
 			Request::Batch(calls) => {
-				let futures: Vec<_> = calls.into_iter().map(move |call| self.handle_call(call, meta.clone())).collect();
-				B(futures::future::join_all(futures).map(|outs| {
+				let futures: Vec<_> = calls.into_iter().enumerate().map(move |(index, call)| {
+					// Errors of invalid calls include their index, errors returned by methods are left as they are.
+					let is_invalid = match call {
+						Call::Invalid { .. } => true,
+						_ => false,
+					};
+					self.handle_call(call, meta.clone()).map(move |output| output.map(|output| {
+						if is_invalid { output.with_batch_index(index) } else { output }
+					}))
+				}).collect();
+				Box::new(futures::future::join_all(futures).map(|outs| {
 					let outs: Vec<_> = outs.into_iter().filter_map(|v| v).collect();
 					if outs.is_empty() {
 						None
@@ -206,7 +689,7 @@ impl<T: Metadata, S: Middleware<T>> MetaIoHandler<T, S> {
 					}
 				}))
 			},
-		})
+		}
 	}
 
 	/// Handle single call asynchronously.
@@ -215,21 +698,45 @@ impl<T: Metadata, S: Middleware<T>> MetaIoHandler<T, S> {
 
 		match call {
 			Call::MethodCall(method) => {
+				if let Some(passthrough) = self.passthrough_method(&method) {
+					let MethodCall { params, id, jsonrpc, .. } = method;
+					return A(Box::new(futures::lazy(move || passthrough.call(params, meta)).then(move |result| {
+						futures::finished(Some(match result {
+							Ok(HandlerResult::Passthrough(response)) => Output::passthrough(response, id, jsonrpc),
+							Ok(HandlerResult::Value(value)) => Output::from(Ok(value), id, jsonrpc),
+							Err(error) => Output::from(Err(error), id, jsonrpc),
+						}))
+					})));
+				}
+
 				let params = method.params;
 				let id = method.id;
 				let jsonrpc = method.jsonrpc;
 				let valid_version = self.compatibility.is_version_valid(jsonrpc);
 
-				let call_method = |method: &Arc<RpcMethod<T>>| {
+				// Calls of unknown methods are passed to the fallback (if any).
+				if let (true, false, Some(fallback)) = (valid_version, self.methods.contains_key(&method.method), self.fallback.clone()) {
+					let name = method.method;
+					return A(Box::new(
+						futures::lazy(move || fallback.call(name, params, meta))
+							.then(move |result| futures::finished(Some(Output::from(result, id, jsonrpc))))
+					));
+				}
+
+				let call_method = |name: &str, method: &Arc<RpcMethod<T>>| -> BoxFuture<Value> {
+					// Typed methods parse params right away and defer only the method itself.
+					if self.typed.contains(name) {
+						return method.call(params, meta);
+					}
 					let method = method.clone();
-					futures::lazy(move || method.call(params, meta))
+					Box::new(futures::lazy(move || method.call(params, meta)))
 				};
 
 				let result = match (valid_version, self.methods.get(&method.method)) {
 					(false, _) => Err(Error::invalid_version()),
-					(true, Some(&RemoteProcedure::Method(ref method))) => Ok(call_method(method)),
+					(true, Some(&RemoteProcedure::Method(ref procedure))) => Ok(call_method(&method.method, procedure)),
 					(true, Some(&RemoteProcedure::Alias(ref alias))) => match self.methods.get(alias) {
-						Some(&RemoteProcedure::Method(ref method)) => Ok(call_method(method)),
+						Some(&RemoteProcedure::Method(ref procedure)) => Ok(call_method(alias, procedure)),
 						_ => Err(Error::method_not_found()),
 					},
 					(true, _) => Err(Error::method_not_found()),
@@ -271,8 +778,14 @@ impl<T: Metadata, S: Middleware<T>> MetaIoHandler<T, S> {
 }
 
 /// Simplified `IoHandler` with no `Metadata` associated with each request.
-#[derive(Debug, Default)]
-pub struct IoHandler<M: Metadata = ()>(MetaIoHandler<M>);
+#[derive(Debug)]
+pub struct IoHandler<M: Metadata = (), S: Middleware<M> = middleware::Noop>(MetaIoHandler<M, S>);
+
+impl<M: Metadata> Default for IoHandler<M> {
+	fn default() -> Self {
+		IoHandler(MetaIoHandler::default())
+	}
+}
 
 // Type inference helper
 impl IoHandler {
@@ -287,17 +800,34 @@ impl IoHandler {
 	}
 }
 
-impl<M: Metadata + Default> IoHandler<M> {
+impl<S: Middleware<()>> IoHandler<(), S> {
+	/// Creates new `IoHandler` passing each request through given middleware.
+	///
+	/// The middleware observes each parsed request and can respond on its own
+	/// or inspect the response returned by the handler.
+	pub fn with_middleware(middleware: S) -> Self {
+		IoHandler(MetaIoHandler::new(Default::default(), middleware))
+	}
+}
+
+impl<M: Metadata + Default, S: Middleware<M>> IoHandler<M, S> {
 	/// Handle given string request asynchronously.
-	pub fn handle_request(&self, request: &str) -> FutureResult<FutureResponse> {
+	pub fn handle_request(&self, request: &str) -> FutureResult<S::Future> {
 		self.0.handle_request(request, M::default())
 	}
 
 	/// Handle deserialized RPC request asynchronously.
-	pub fn handle_rpc_request(&self, request: Request) -> FutureResponse {
+	pub fn handle_rpc_request(&self, request: Request) -> S::Future {
 		self.0.handle_rpc_request(request, M::default())
 	}
 
+	/// Handle a stream of string requests asynchronously.
+	pub fn handle_stream<'a, R>(&'a self, requests: R) -> Box<Stream<Item=String, Error=R::Error> + 'a> where
+		R: Stream<Item=String> + 'a,
+	{
+		self.0.handle_stream(requests, M::default())
+	}
+
 	/// Handle single Call asynchronously.
 	pub fn handle_call(&self, call: Call) -> FutureOutput {
 		self.0.handle_call(call, M::default())
@@ -311,40 +841,126 @@ impl<M: Metadata + Default> IoHandler<M> {
 	}
 }
 
-impl<M: Metadata> Deref for IoHandler<M> {
-	type Target = MetaIoHandler<M>;
+impl<M: Metadata, S: Middleware<M>> Deref for IoHandler<M, S> {
+	type Target = MetaIoHandler<M, S>;
 
 	fn deref(&self) -> &Self::Target {
 		&self.0
 	}
 }
 
-impl<M: Metadata> DerefMut for IoHandler<M> {
+impl<M: Metadata, S: Middleware<M>> DerefMut for IoHandler<M, S> {
 	fn deref_mut(&mut self) -> &mut Self::Target {
 		&mut self.0
 	}
 }
 
-impl From<IoHandler> for MetaIoHandler<()> {
-	fn from(io: IoHandler) -> Self {
+impl<M: Metadata, S: Middleware<M>> From<IoHandler<M, S>> for MetaIoHandler<M, S> {
+	fn from(io: IoHandler<M, S>) -> Self {
 		io.0
 	}
 }
 
+impl<M: Metadata> From<MetaIoHandler<M>> for IoHandler<M> {
+	fn from(io: MetaIoHandler<M>) -> Self {
+		io.into_io_handler()
+	}
+}
+
+/// Map of values expiring `ttl` after insertion.
+///
+/// Expired values are evicted lazily from the oldest ones, so lookups don't scan the whole map.
+struct Expiring<V> {
+	ttl: Duration,
+	capacity: usize,
+	values: HashMap<String, (Instant, V)>,
+	/// Keys in order of insertion.
+	keys: VecDeque<String>,
+}
+
+impl<V> Expiring<V> {
+	fn new(ttl: Duration, capacity: usize) -> Self {
+		assert!(capacity > 0, "Capacity must be positive.");
+		Expiring {
+			ttl,
+			capacity,
+			values: HashMap::new(),
+			keys: VecDeque::new(),
+		}
+	}
+
+	/// Returns insertion time and value of given key, unless it's expired.
+	fn get(&mut self, key: &str, now: Instant) -> Option<&mut (Instant, V)> {
+		self.remove_expired(now);
+		self.values.get_mut(key)
+	}
+
+	/// Inserts a value unless the key is already present, evicting the oldest values over capacity.
+	fn insert(&mut self, key: String, value: V, now: Instant) {
+		self.remove_expired(now);
+		if self.values.contains_key(&key) {
+			return;
+		}
+
+		while self.keys.len() >= self.capacity {
+			if let Some(oldest) = self.keys.pop_front() {
+				self.values.remove(&oldest);
+			}
+		}
+		self.keys.push_back(key.clone());
+		self.values.insert(key, (now, value));
+	}
+
+	fn remove_expired(&mut self, now: Instant) {
+		while let Some(oldest) = self.keys.pop_front() {
+			let expired = self.values.get(&oldest)
+				.map(|&(inserted, _)| now.duration_since(inserted) >= self.ttl)
+				.unwrap_or(true);
+			if expired {
+				self.values.remove(&oldest);
+			} else {
+				self.keys.push_front(oldest);
+				break;
+			}
+		}
+	}
+}
+
+fn quota_exceeded(retry_after: Duration) -> Error {
+	let secs = retry_after.as_secs() + if retry_after.subsec_nanos() > 0 { 1 } else { 0 };
+	let mut data = serde_json::Map::new();
+	data.insert("retry_after".into(), Value::from(secs));
+	Error {
+		code: ErrorCode::ServerError(-32000),
+		message: "Quota exceeded".into(),
+		data: Some(Value::Object(data)),
+	}
+}
+
 fn read_request(request_str: &str) -> Result<Request, Error> {
 	serde_json::from_str(request_str).map_err(|_| Error::new(ErrorCode::ParseError))
 }
 
+fn read_passthrough(response: &str) -> ::Result<Value> {
+	match serde_json::from_str(response) {
+		Ok(Output::Success(success)) => Ok(success.result),
+		Ok(Output::Failure(failure)) => Err(failure.error),
+		Err(err) => {
+			warn!(target: "rpc", "Invalid passthrough response: {:?}", err);
+			Err(Error::internal_error())
+		},
+	}
+}
+
 fn write_response(response: Response) -> String {
-	// this should never fail
-	serde_json::to_string(&response).unwrap()
+	response.to_json()
 }
 
 #[cfg(test)]
 mod tests {
 	use futures;
-	use types::{Value};
-	use super::{IoHandler, Compatibility};
+	use types::{Params, Value};
+	use super::{IoHandler, Compatibility, DuplicateMethod, MethodAccess, RegistrationError};
 
 	#[test]
 	fn test_io_handler() {
@@ -406,6 +1022,115 @@ mod tests {
 		assert_eq!(called.load(atomic::Ordering::SeqCst), true);
 	}
 
+	#[test]
+	fn test_io_handler_with_middleware() {
+		use std::sync::Arc;
+		use std::sync::atomic::{AtomicUsize, Ordering};
+		use futures::{self, Future};
+		use middleware::Middleware;
+		use types::{Call, Request, Response};
+		use super::FutureResponse;
+
+		struct Blocker(Arc<AtomicUsize>);
+		impl Middleware<()> for Blocker {
+			type Future = FutureResponse;
+
+			fn on_request<F, X>(&self, request: Request, meta: (), next: F) -> Self::Future where
+				F: FnOnce(Request, ()) -> X + Send,
+				X: Future<Item=Option<Response>, Error=()> + Send + 'static,
+			{
+				self.0.fetch_add(1, Ordering::SeqCst);
+				match request {
+					Request::Single(Call::MethodCall(ref call)) if call.method == "blocked" => {
+						return Box::new(futures::finished(None));
+					},
+					_ => {},
+				}
+				Box::new(next(request, meta))
+			}
+		}
+
+		let requests = Arc::new(AtomicUsize::new(0));
+		let mut io = IoHandler::with_middleware(Blocker(requests.clone()));
+		io.add_method("say_hello", |_| Ok(Value::String("hello".into())));
+		io.add_method("blocked", |_| Ok(Value::String("hello".into())));
+
+		let request = r#"{"jsonrpc": "2.0", "method": "say_hello", "id": 1}"#;
+		assert_eq!(io.handle_request_sync(request), Some(r#"{"jsonrpc":"2.0","result":"hello","id":1}"#.to_owned()));
+		let request = r#"{"jsonrpc": "2.0", "method": "blocked", "id": 1}"#;
+		assert_eq!(io.handle_request_sync(request), None);
+		assert_eq!(requests.load(Ordering::SeqCst), 2);
+	}
+
+	#[test]
+	fn test_method_with_named_params() {
+		#[derive(Deserialize)]
+		struct Hello {
+			name: String,
+		}
+
+		let mut io = IoHandler::new();
+		io.add_method("hello", |params: Params| {
+			let hello: Hello = params.parse_named()?;
+			Ok(Value::String(format!("hello {}", hello.name)))
+		});
+
+		let request = r#"{"jsonrpc":"2.0","id":1,"method":"hello","params":{"name":"world"}}"#;
+		assert_eq!(io.handle_request_sync(request), Some(r#"{"jsonrpc":"2.0","result":"hello world","id":1}"#.to_owned()));
+		let request = r#"{"jsonrpc":"2.0","id":1,"method":"hello","params":{}}"#;
+		let response = r#"{"jsonrpc":"2.0","error":{"code":-32602,"message":"Invalid params: missing field `name`."},"id":1}"#;
+		assert_eq!(io.handle_request_sync(request), Some(response.to_owned()));
+	}
+
+	#[test]
+	fn test_invalid_params_with_details() {
+		use types::Error;
+
+		#[derive(Debug, Serialize)]
+		struct Details {
+			field: &'static str,
+			reason: &'static str,
+		}
+
+		let mut io = IoHandler::new();
+		io.add_method("hello", |_: Params| {
+			Err(Error::invalid_params_with_details("Invalid name", Details { field: "name", reason: "too long" }))
+		});
+
+		let request = r#"{"jsonrpc":"2.0","id":1,"method":"hello","params":{"name":"world"}}"#;
+		let response = r#"{"jsonrpc":"2.0","error":{"code":-32602,"message":"Invalid name","data":{"field":"name","reason":"too long"}},"id":1}"#;
+		assert_eq!(io.handle_request_sync(request), Some(response.to_owned()));
+	}
+
+	#[test]
+	fn test_invalid_params_with_unserializable_details() {
+		use std::collections::BTreeMap;
+		use types::{Error, ErrorCode};
+
+		// Maps with non-string keys can't be serialized to JSON.
+		let mut details = BTreeMap::new();
+		details.insert((1, 2), "too far");
+
+		let error = Error::invalid_params_with_details("Invalid point", details);
+
+		assert_eq!(error.code, ErrorCode::InvalidParams);
+		assert_eq!(error.data, Some(Value::String(r#"{(1, 2): "too far"}"#.into())));
+	}
+
+	#[test]
+	fn test_method_no_params() {
+		let mut io = IoHandler::new();
+		io.add_method_no_params("hello", |_: Params| Ok(Value::String("world".into())));
+
+		let response = r#"{"jsonrpc":"2.0","result":"world","id":1}"#;
+		assert_eq!(io.handle_request_sync(r#"{"jsonrpc":"2.0","id":1,"method":"hello"}"#), Some(response.to_owned()));
+		assert_eq!(io.handle_request_sync(r#"{"jsonrpc":"2.0","id":1,"method":"hello","params":[]}"#), Some(response.to_owned()));
+
+		let response = r#"{"jsonrpc":"2.0","error":{"code":-32602,"message":"Invalid params: method takes no parameters."},"id":1}"#;
+		assert_eq!(io.handle_request_sync(r#"{"jsonrpc":"2.0","id":1,"method":"hello","params":[1]}"#), Some(response.to_owned()));
+		assert_eq!(io.handle_request_sync(r#"{"jsonrpc":"2.0","id":1,"method":"hello","params":{}}"#), Some(response.to_owned()));
+	}
+
 	#[test]
 	fn test_method_not_found() {
 		let io = IoHandler::new();
@@ -416,6 +1141,23 @@ mod tests {
 		assert_eq!(io.handle_request_sync(request), Some(response.to_string()));
 	}
 
+	#[test]
+	fn test_fallback_handles_unknown_methods() {
+		let mut io = IoHandler::new();
+		io.set_fallback(|method: String, params: Params| {
+			let params = match params {
+				Params::Array(values) => Value::Array(values),
+				_ => Value::Null,
+			};
+			Ok(Value::Array(vec![Value::String(method), params]))
+		});
+
+		let request = r#"{"jsonrpc": "2.0", "method": "any_method", "params": [42, 23], "id": 1}"#;
+		let response = r#"{"jsonrpc":"2.0","result":["any_method",[42,23]],"id":1}"#;
+
+		assert_eq!(io.handle_request_sync(request), Some(response.to_string()));
+	}
+
 	#[test]
 	fn test_method_alias() {
 		let mut io = IoHandler::new();
@@ -431,6 +1173,23 @@ mod tests {
 		assert_eq!(io.handle_request_sync(request), Some(response.to_string()));
 	}
 
+	#[test]
+	fn test_method_alias_resolved_at_call_time() {
+		let mut io = IoHandler::new();
+		io.add_alias("hi", "hello");
+		io.add_alias("bye", "goodbye");
+		io.add_method("hello", |_| Ok(Value::String("world".to_string())));
+
+		let hi = r#"{"jsonrpc": "2.0", "method": "hi", "id": 1}"#;
+		let bye = r#"{"jsonrpc": "2.0", "method": "bye", "id": 1}"#;
+
+		assert_eq!(io.handle_request_sync(hi), Some(r#"{"jsonrpc":"2.0","result":"world","id":1}"#.to_string()));
+		assert_eq!(
+			io.handle_request_sync(bye),
+			Some(r#"{"jsonrpc":"2.0","error":{"code":-32601,"message":"Method not found"},"id":1}"#.to_string())
+		);
+	}
+
 	#[test]
 	fn test_notification_alias() {
 		use std::sync::Arc;
@@ -450,6 +1209,470 @@ mod tests {
 		assert_eq!(called.load(atomic::Ordering::SeqCst), true);
 	}
 
+	#[test]
+	fn test_try_add_method_rejects_methods_over_limit() {
+		let mut io = IoHandler::new();
+		io.set_max_methods(2);
+
+		assert_eq!(io.try_add_method("hello", |_| Ok(Value::String("hello".into()))), Ok(()));
+		assert_eq!(io.try_add_method("hello2", |_| Ok(Value::String("hello".into()))), Ok(()));
+		assert_eq!(
+			io.try_add_method("hello3", |_| Ok(Value::String("hello".into()))),
+			Err(RegistrationError::TooManyMethods(2))
+		);
+		assert_eq!(io.handle_request_sync(r#"{"jsonrpc": "2.0", "method": "hello3", "id": 1}"#),
+			Some(r#"{"jsonrpc":"2.0","error":{"code":-32601,"message":"Method not found"},"id":1}"#.to_owned()));
+	}
+
+	#[test]
+	fn test_try_add_method_rejects_duplicates() {
+		let mut io = IoHandler::new();
+
+		assert_eq!(io.try_add_method("hello", |_| Ok(Value::String("hello".into()))), Ok(()));
+		assert_eq!(
+			io.try_add_method("hello", |_| Ok(Value::String("hello again".into()))),
+			Err(RegistrationError::Duplicate(DuplicateMethod("hello".into())))
+		);
+
+		let request = r#"{"jsonrpc": "2.0", "method": "hello", "id": 1}"#;
+		let response = r#"{"jsonrpc":"2.0","result":"hello","id":1}"#;
+		assert_eq!(io.handle_request_sync(request), Some(response.to_string()));
+	}
+
+	#[test]
+	fn test_methods() {
+		let mut io = IoHandler::new();
+		io.add_method("hello_async", |_| futures::finished(Value::String("hello".into())));
+		io.add_method("hello", |_| Ok(Value::String("hello".into())));
+		io.add_notification("notify", |_| {});
+
+		assert_eq!(io.methods(), vec!["hello".to_owned(), "hello_async".to_owned()]);
+	}
+
+	#[test]
+	fn test_methods_include_aliases_of_methods() {
+		let mut io = IoHandler::new();
+		io.add_method("say_hello", |_| Ok(Value::String("hello".into())));
+		io.add_alias("say_hi", "say_hello");
+		io.add_notification("notify", |_| {});
+		io.add_alias("ping", "notify");
+		io.add_alias("unknown", "missing");
+
+		assert_eq!(io.methods(), vec!["say_hello".to_owned(), "say_hi".to_owned()]);
+	}
+
+	#[test]
+	fn test_method_access() {
+		let mut io = IoHandler::new();
+		io.add_method_ro("get_balance", |_| Ok(Value::String("0".into())));
+		io.add_method_rw("transfer", |_| Ok(Value::Bool(true)));
+		io.add_method("say_hello", |_| Ok(Value::String("hello".into())));
+		io.add_alias("balance", "get_balance");
+
+		assert_eq!(io.method_access("get_balance"), Some(MethodAccess::ReadOnly));
+		assert_eq!(io.method_access("balance"), Some(MethodAccess::ReadOnly));
+		assert_eq!(io.method_access("transfer"), Some(MethodAccess::Mutating));
+		assert_eq!(io.method_access("say_hello"), None);
+		assert_eq!(io.method_access("unknown"), None);
+	}
+
+	#[test]
+	fn test_add_methods() {
+		use std::collections::HashMap;
+		use Result;
+
+		fn hello(_: Params) -> Result<Value> {
+			Ok(Value::String("hello".into()))
+		}
+		fn world(_: Params) -> Result<Value> {
+			Ok(Value::String("world".into()))
+		}
+
+		let mut methods = HashMap::new();
+		methods.insert("say_hello".to_owned(), hello as fn(Params) -> Result<Value>);
+		methods.insert("say_world".to_owned(), world);
+
+		let mut io = IoHandler::new();
+		io.add_methods(methods);
+
+		let request = r#"{"jsonrpc": "2.0", "method": "say_hello", "id": 1}"#;
+		assert_eq!(io.handle_request_sync(request), Some(r#"{"jsonrpc":"2.0","result":"hello","id":1}"#.to_owned()));
+		let request = r#"{"jsonrpc": "2.0", "method": "say_world", "id": 2}"#;
+		assert_eq!(io.handle_request_sync(request), Some(r#"{"jsonrpc":"2.0","result":"world","id":2}"#.to_owned()));
+	}
+
+	#[test]
+	fn test_meta_io_handler_into_io_handler() {
+		use super::MetaIoHandler;
+
+		let mut io = MetaIoHandler::<()>::default();
+		io.add_method("say_hello", |_| Ok(Value::String("hello".into())));
+		let io: IoHandler = io.into_io_handler();
+
+		let request = r#"{"jsonrpc": "2.0", "method": "say_hello", "id": 1}"#;
+		assert_eq!(io.handle_request_sync(request), Some(r#"{"jsonrpc":"2.0","result":"hello","id":1}"#.to_owned()));
+	}
+
+	#[test]
+	fn test_cached_method() {
+		use std::sync::Arc;
+		use std::sync::atomic::{AtomicUsize, Ordering};
+		use std::thread;
+		use std::time::Duration;
+
+		let calls = Arc::new(AtomicUsize::new(0));
+		let mut io = IoHandler::new();
+		{
+			let calls = calls.clone();
+			io.add_cached_method("expensive", Duration::from_millis(200), 16, move |params: Params| {
+				calls.fetch_add(1, Ordering::SeqCst);
+				params.parse::<Value>()
+			});
+		}
+
+		let request = r#"{"jsonrpc": "2.0", "method": "expensive", "params": [1], "id": 1}"#;
+		let response = r#"{"jsonrpc":"2.0","result":[1],"id":1}"#;
+		assert_eq!(io.handle_request_sync(request), Some(response.to_owned()));
+		assert_eq!(io.handle_request_sync(request), Some(response.to_owned()));
+		assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+		let request = r#"{"jsonrpc": "2.0", "method": "expensive", "params": [2], "id": 1}"#;
+		assert_eq!(io.handle_request_sync(request), Some(r#"{"jsonrpc":"2.0","result":[2],"id":1}"#.to_owned()));
+		assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+		thread::sleep(Duration::from_millis(250));
+		assert_eq!(io.handle_request_sync(request), Some(r#"{"jsonrpc":"2.0","result":[2],"id":1}"#.to_owned()));
+		assert_eq!(calls.load(Ordering::SeqCst), 3);
+	}
+
+	#[test]
+	fn test_method_with_quota() {
+		use std::time::Duration;
+		use super::MetaIoHandler;
+		use Metadata;
+
+		#[derive(Clone, Default)]
+		struct ApiKey(String);
+		impl Metadata for ApiKey {}
+
+		let mut io = MetaIoHandler::<ApiKey>::default();
+		io.add_method_with_quota("search", |meta: &ApiKey| meta.0.clone(), 2, Duration::from_secs(60), |_| {
+			Ok(Value::String("found".into()))
+		});
+
+		let request = r#"{"jsonrpc": "2.0", "method": "search", "id": 1}"#;
+		let found = r#"{"jsonrpc":"2.0","result":"found","id":1}"#;
+		let exceeded = r#"{"jsonrpc":"2.0","error":{"code":-32000,"message":"Quota exceeded","data":{"retry_after":60}},"id":1}"#;
+
+		assert_eq!(io.handle_request_sync(request, ApiKey("a".into())), Some(found.to_owned()));
+		assert_eq!(io.handle_request_sync(request, ApiKey("a".into())), Some(found.to_owned()));
+		assert_eq!(io.handle_request_sync(request, ApiKey("a".into())), Some(exceeded.to_owned()));
+		assert_eq!(io.handle_request_sync(request, ApiKey("b".into())), Some(found.to_owned()));
+	}
+
+	#[test]
+	fn test_method_with_quota_renews_after_window() {
+		use std::thread;
+		use std::time::Duration;
+		use super::MetaIoHandler;
+		use Metadata;
+
+		#[derive(Clone, Default)]
+		struct ApiKey(String);
+		impl Metadata for ApiKey {}
+
+		let mut io = MetaIoHandler::<ApiKey>::default();
+		io.add_method_with_quota("search", |meta: &ApiKey| meta.0.clone(), 1, Duration::from_millis(50), |_| {
+			Ok(Value::String("found".into()))
+		});
+
+		let request = r#"{"jsonrpc": "2.0", "method": "search", "id": 1}"#;
+		let found = r#"{"jsonrpc":"2.0","result":"found","id":1}"#;
+
+		assert_eq!(io.handle_request_sync(request, ApiKey("a".into())), Some(found.to_owned()));
+		assert!(io.handle_request_sync(request, ApiKey("a".into())) != Some(found.to_owned()));
+		thread::sleep(Duration::from_millis(60));
+		assert_eq!(io.handle_request_sync(request, ApiKey("a".into())), Some(found.to_owned()));
+	}
+
+	#[test]
+	fn test_expiring_evicts_expired_values_lazily() {
+		use std::time::{Duration, Instant};
+		use super::Expiring;
+
+		let now = Instant::now();
+		let mut map = Expiring::new(Duration::from_secs(10), usize::max_value());
+		map.insert("a".into(), 1, now);
+		map.insert("b".into(), 2, now + Duration::from_secs(5));
+
+		assert_eq!(map.get("a", now + Duration::from_secs(9)).map(|v| v.1), Some(1));
+		assert_eq!(map.get("b", now + Duration::from_secs(10)).map(|v| v.1), Some(2));
+		assert_eq!(map.values.len(), 1);
+		assert_eq!(map.keys.len(), 1);
+		assert!(map.get("b", now + Duration::from_secs(15)).is_none());
+		assert!(map.values.is_empty());
+	}
+
+	#[test]
+	fn test_expiring_evicts_oldest_values_over_capacity() {
+		use std::time::{Duration, Instant};
+		use super::Expiring;
+
+		let now = Instant::now();
+		let mut map = Expiring::new(Duration::from_secs(10), 2);
+		map.insert("a".into(), 1, now);
+		map.insert("b".into(), 2, now);
+		map.insert("c".into(), 3, now);
+
+		assert!(map.get("a", now).is_none());
+		assert_eq!(map.get("b", now).map(|v| v.1), Some(2));
+		assert_eq!(map.get("c", now).map(|v| v.1), Some(3));
+	}
+
+	#[test]
+	fn test_notification_replacing_method_has_no_access() {
+		let mut io = IoHandler::new();
+		io.add_method_ro("get_balance", |_| Ok(Value::String("0".into())));
+		io.add_notification("get_balance", |_| {});
+
+		assert_eq!(io.method_access("get_balance"), None);
+	}
+
+	#[test]
+	fn test_handle_stream() {
+		use futures::{stream, Future, Stream};
+
+		let mut io = IoHandler::new();
+		io.add_method("say_hello", |_| Ok(Value::String("hello".into())));
+		io.add_notification("notify", |_| {});
+
+		let requests = stream::iter_ok::<_, ()>(vec![
+			r#"{"jsonrpc": "2.0", "method": "say_hello", "id": 1}"#.to_owned(),
+			r#"{"jsonrpc": "2.0", "method": "notify"}"#.to_owned(),
+			r#"{"jsonrpc": "2.0", "method": "say_hello", "id": 2}"#.to_owned(),
+		]);
+		let responses = io.handle_stream(requests).collect().wait().unwrap();
+
+		assert_eq!(responses, vec![
+			r#"{"jsonrpc":"2.0","result":"hello","id":1}"#.to_owned(),
+			r#"{"jsonrpc":"2.0","result":"hello","id":2}"#.to_owned(),
+		]);
+	}
+
+	#[test]
+	fn test_passthrough_method() {
+		use futures::Future;
+		use serde_json;
+		use super::HandlerResult;
+
+		let mut io = IoHandler::new();
+		io.add_passthrough_method_with_meta("proxy", |_params: Params, _meta: ()| {
+			Ok(HandlerResult::Passthrough(r#"{"id":1, "result": {"b": 1, "a": 2}, "jsonrpc": "2.0"}"#.into()))
+		});
+
+		let request = r#"{"jsonrpc": "2.0", "method": "proxy", "id": 1}"#;
+		let response = r#"{"id":1, "result": {"b": 1, "a": 2}, "jsonrpc": "2.0"}"#;
+		assert_eq!(io.handle_request_sync(request), Some(response.to_string()));
+
+		let request = r#"[{"jsonrpc": "2.0", "method": "proxy", "id": 1}]"#;
+		let response = r#"[{"id":1, "result": {"b": 1, "a": 2}, "jsonrpc": "2.0"}]"#;
+		assert_eq!(io.handle_request_sync(request), Some(response.to_string()));
+
+		let request = serde_json::from_str(r#"{"jsonrpc": "2.0", "method": "proxy", "id": 1}"#).unwrap();
+		let response = io.handle_rpc_request(request).wait().unwrap().unwrap();
+		assert_eq!(response.to_json(), r#"{"id":1, "result": {"b": 1, "a": 2}, "jsonrpc": "2.0"}"#);
+	}
+
+	#[test]
+	fn test_passthrough_method_with_middleware() {
+		use futures::{self, Future};
+		use middleware::Middleware;
+		use types::{Request, Response};
+		use super::{FutureResponse, HandlerResult};
+
+		struct Blocker;
+		impl Middleware<()> for Blocker {
+			type Future = FutureResponse;
+
+			fn on_request<F, X>(&self, _request: Request, _meta: (), _next: F) -> Self::Future where
+				F: FnOnce(Request, ()) -> X + Send,
+				X: Future<Item=Option<Response>, Error=()> + Send + 'static,
+			{
+				Box::new(futures::finished(None))
+			}
+		}
+
+		let mut io = IoHandler::with_middleware(Blocker);
+		io.add_passthrough_method_with_meta("proxy", |_params: Params, _meta: ()| {
+			Ok(HandlerResult::Passthrough(r#"{"id":1, "result": 1, "jsonrpc": "2.0"}"#.into()))
+		});
+
+		let request = r#"{"jsonrpc": "2.0", "method": "proxy", "id": 1}"#;
+		assert_eq!(io.handle_request_sync(request), None);
+	}
+
+	#[test]
+	fn test_subscription_method() {
+		use futures::{Future, Stream};
+
+		let mut io = IoHandler::new();
+		io.add_subscription_method("count", |_params: Params| {
+			futures::stream::iter_ok(vec![Value::from(1), Value::from(2)])
+		});
+		io.add_alias("numbers", "count");
+
+		let results = io.streaming_method("numbers").unwrap().call(Params::None, ()).collect().wait();
+		assert_eq!(results, Ok(vec![Value::from(1), Value::from(2)]));
+
+		let request = r#"{"jsonrpc": "2.0", "method": "count", "id": 1}"#;
+		let response = r#"{"jsonrpc":"2.0","result":[1,2],"id":1}"#;
+		assert_eq!(io.handle_request_sync(request), Some(response.to_string()));
+
+		io.add_method("count", |_| Ok(Value::from(0)));
+		assert!(io.streaming_method("count").is_none());
+	}
+
+	#[test]
+	fn test_streaming_call_with_middleware() {
+		use futures::{self, Future, Stream};
+		use middleware::Middleware;
+		use types::{Call, Id, MethodCall, Request, Response, Version};
+		use super::{FutureResponse, StreamingCall};
+
+		struct Blocker;
+		impl Middleware<()> for Blocker {
+			type Future = FutureResponse;
+
+			fn on_request<F, X>(&self, request: Request, meta: (), next: F) -> Self::Future where
+				F: FnOnce(Request, ()) -> X + Send,
+				X: Future<Item=Option<Response>, Error=()> + Send + 'static,
+			{
+				if let Request::Single(Call::MethodCall(ref call)) = request {
+					if call.method == "blocked" {
+						return Box::new(futures::finished(None));
+					}
+				}
+				Box::new(next(request, meta))
+			}
+		}
+
+		let mut io = IoHandler::with_middleware(Blocker);
+		io.add_subscription_method("count", |_params: Params| {
+			futures::stream::iter_ok(vec![Value::from(1), Value::from(2)])
+		});
+		io.add_alias("blocked", "count");
+		let call = |method: &str| MethodCall {
+			jsonrpc: Some(Version::V2),
+			method: method.into(),
+			params: Params::None,
+			id: Id::Num(1),
+		};
+
+		match io.handle_streaming_call(call("count"), ()).wait() {
+			Ok(StreamingCall::Results(results)) => assert_eq!(results.collect().wait(), Ok(vec![Value::from(1), Value::from(2)])),
+			_ => panic!("Expected results of the call."),
+		}
+		match io.handle_streaming_call(call("blocked"), ()).wait() {
+			Ok(StreamingCall::Response(None)) => {},
+			_ => panic!("Expected the call to be blocked."),
+		}
+	}
+
+	#[test]
+	fn test_batch_index_of_invalid_calls() {
+		let mut io = IoHandler::new();
+		io.add_method("say_hello", |_| Ok(Value::String("hello".into())));
+
+		let request = r#"[
+			{"jsonrpc": "2.0", "method": "say_hello", "id": 1},
+			{"jsonrpc": "2.0", "method": "say_hello", "id": 2},
+			{"jsonrpc": "2.0", "id": 3}
+		]"#;
+		let response = r#"[{"jsonrpc":"2.0","result":"hello","id":1},{"jsonrpc":"2.0","result":"hello","id":2},{"jsonrpc":"2.0","error":{"code":-32600,"message":"Invalid request","data":{"batch_index":2}},"id":3}]"#;
+
+		assert_eq!(io.handle_request_sync(request), Some(response.to_string()));
+	}
+
+	#[test]
+	fn test_batch_index_is_not_added_to_errors_of_methods() {
+		use types::Error;
+
+		let mut io = IoHandler::new();
+		io.add_method("reject", |_| Err(Error::invalid_request()));
+
+		let request = r#"[{"jsonrpc": "2.0", "method": "reject", "id": 1}, {"jsonrpc": "2.0", "id": 2}]"#;
+		let response = r#"[{"jsonrpc":"2.0","error":{"code":-32600,"message":"Invalid request"},"id":1},{"jsonrpc":"2.0","error":{"code":-32600,"message":"Invalid request","data":{"batch_index":1}},"id":2}]"#;
+
+		assert_eq!(io.handle_request_sync(request), Some(response.to_string()));
+	}
+
+	#[test]
+	fn test_typed_method_rejects_invalid_params() {
+		use std::sync::Arc;
+		use std::sync::atomic;
+
+		let mut io = IoHandler::new();
+		let called = Arc::new(atomic::AtomicBool::new(false));
+		let c = called.clone();
+		io.add_method_typed("add", move |(a, b): (u64, u64)| {
+			c.store(true, atomic::Ordering::SeqCst);
+			Ok(a + b)
+		});
+
+		let request = r#"{"jsonrpc": "2.0", "method": "add", "params": ["1", 2], "id": 1}"#;
+		let response = r#"{"jsonrpc":"2.0","error":{"code":-32602,"message":"Invalid params: invalid type: string "1", expected u64."},"id":1}"#;
+		assert_eq!(io.handle_request_sync(request), Some(response.to_string()));
+		assert_eq!(called.load(atomic::Ordering::SeqCst), false);
+
+		let request = r#"{"jsonrpc": "2.0", "method": "add", "params": [1, 2], "id": 1}"#;
+		let response = r#"{"jsonrpc":"2.0","result":3,"id":1}"#;
+		assert_eq!(io.handle_request_sync(request), Some(response.to_string()));
+		assert_eq!(called.load(atomic::Ordering::SeqCst), true);
+	}
+
+	#[test]
+	fn test_typed_method_parses_params_before_dispatch() {
+		use std::cell::Cell;
+		use std::sync::{Arc, Mutex};
+		use std::thread;
+		use futures::Future;
+		use serde::{Deserialize, Deserializer};
+		use serde_json;
+		use types::Call;
+
+		thread_local!(static PARSED: Cell<bool> = Cell::new(false));
+		struct Recorded;
+		impl<'de> Deserialize<'de> for Recorded {
+			fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+				Value::deserialize(deserializer)?;
+				PARSED.with(|parsed| parsed.set(true));
+				Ok(Recorded)
+			}
+		}
+
+		let mut io = IoHandler::new();
+		let called_on = Arc::new(Mutex::new(None));
+		let c = called_on.clone();
+		io.add_method_typed("record", move |_params: Recorded| {
+			*c.lock().unwrap() = Some(thread::current().id());
+			Ok(true)
+		});
+
+		// Params are parsed on the thread handling the call...
+		let call: Call = serde_json::from_str(r#"{"jsonrpc": "2.0", "method": "record", "params": [], "id": 1}"#).unwrap();
+		let output = io.handle_call(call);
+		assert_eq!(PARSED.with(|parsed| parsed.get()), true);
+		assert_eq!(*called_on.lock().unwrap(), None);
+
+		// ...while the method runs on the thread executing the call.
+		let (parsed_on_executor, executor) = thread::spawn(move || {
+			output.wait().unwrap();
+			(PARSED.with(|parsed| parsed.get()), thread::current().id())
+		}).join().unwrap();
+		assert_eq!(parsed_on_executor, false);
+		assert_eq!(*called_on.lock().unwrap(), Some(executor));
+	}
+
 	#[test]
 	fn test_send_sync() {
 		fn is_send_sync<T>(_obj: T) -> bool where
@@ -36,15 +36,22 @@ mod calls;
 mod io;
 
 mod middleware;
+mod shared;
 pub mod types;
 
 /// A `Future` trait object.
 pub type BoxFuture<T> = Box<futures::Future<Item = T, Error = Error> + Send>;
 
+/// A `Stream` trait object.
+pub type BoxStream<T> = Box<futures::Stream<Item = T, Error = Error> + Send>;
+
 /// A Result type.
 pub type Result<T> = ::std::result::Result<T, Error>;
 
 pub use calls::{RemoteProcedure, Metadata, RpcMethodSimple, RpcMethod, RpcNotificationSimple, RpcNotification};
-pub use io::{Compatibility, IoHandler, MetaIoHandler, FutureResponse, FutureResult};
+pub use calls::{HandlerResult, RpcFallbackMethod, RpcPassthroughMethod, RpcStreamingMethod};
+pub use io::{Compatibility, DuplicateMethod, IoHandler, MethodAccess, MetaIoHandler, RegistrationError, FutureResponse, FutureResult};
+pub use io::{FutureStreamingCall, StreamingCall};
 pub use middleware::{Middleware, Noop as NoopMiddleware};
+pub use shared::SharedIoHandler;
 pub use types::*;
@@ -0,0 +1,311 @@
+//! `IoHandler` that can be modified while it's being used.
+
+use std::sync::{Arc, Mutex, RwLock};
+
+use calls::{Metadata, RemoteProcedure, RpcMethodSimple, RpcMethod, RpcNotificationSimple, RpcNotification};
+use futures::{self, future, Future};
+use io::MetaIoHandler;
+use middleware::Middleware;
+use types::{Call, Request, Response};
+
+const LOCK_PROOF: &'static str = "Shared handler lock is never poisoned.";
+
+/// Thread-safe registry of methods that can be modified at runtime.
+///
+/// Use it as a middleware of `MetaIoHandler`: calls to methods registered
+/// in `SharedIoHandler` are dispatched by it, all other calls are handled
+/// by the `MetaIoHandler` itself.
+///
+/// ```rust
+/// extern crate jsonrpc_core;
+///
+/// use jsonrpc_core::*;
+/// use jsonrpc_core::futures::Future;
+///
+/// fn main() {
+/// 	let shared = SharedIoHandler::default();
+/// 	let io = MetaIoHandler::<(), _>::with_middleware(shared.clone());
+///
+/// 	shared.add_method("say_hello", |_| Ok(Value::String("Hello World!".into())));
+///
+/// 	let request = r#"{"jsonrpc": "2.0", "method": "say_hello", "params": [42, 23], "id": 1}"#;
+/// 	let response = r#"{"jsonrpc":"2.0","result":"Hello World!","id":1}"#;
+///
+/// 	assert_eq!(io.handle_request(request, ()).wait().unwrap(), Some(response.to_string()));
+/// }
+/// ```
+///
+/// Calls are dispatched by a snapshot of registered methods, modifications
+/// replace the snapshot, so they never wait for calls in progress or block them.
+#[derive(Debug)]
+pub struct SharedIoHandler<T: Metadata = ()> {
+	handler: Arc<RwLock<Arc<MetaIoHandler<T>>>>,
+	// Serializes modifications, so that none of them is lost.
+	writer: Arc<Mutex<()>>,
+}
+
+impl<T: Metadata> Default for SharedIoHandler<T> {
+	fn default() -> Self {
+		SharedIoHandler {
+			handler: Default::default(),
+			writer: Default::default(),
+		}
+	}
+}
+
+impl<T: Metadata> Clone for SharedIoHandler<T> {
+	fn clone(&self) -> Self {
+		SharedIoHandler {
+			handler: self.handler.clone(),
+			writer: self.writer.clone(),
+		}
+	}
+}
+
+impl<T: Metadata> SharedIoHandler<T> {
+	/// Adds new supported asynchronous method.
+	pub fn add_method<F>(&self, name: &str, method: F) where
+		F: RpcMethodSimple,
+	{
+		self.modify(|handler| handler.add_method(name, method))
+	}
+
+	/// Adds new supported asynchronous method with metadata support.
+	pub fn add_method_with_meta<F>(&self, name: &str, method: F) where
+		F: RpcMethod<T>,
+	{
+		self.modify(|handler| handler.add_method_with_meta(name, method))
+	}
+
+	/// Adds new supported notification
+	pub fn add_notification<F>(&self, name: &str, notification: F) where
+		F: RpcNotificationSimple,
+	{
+		self.modify(|handler| handler.add_notification(name, notification))
+	}
+
+	/// Adds new supported notification with metadata support.
+	pub fn add_notification_with_meta<F>(&self, name: &str, notification: F) where
+		F: RpcNotification<T>,
+	{
+		self.modify(|handler| handler.add_notification_with_meta(name, notification))
+	}
+
+	/// Removes previously registered method or notification.
+	pub fn remove_method(&self, name: &str) -> Option<RemoteProcedure<T>> {
+		self.modify(|handler| handler.remove_method(name))
+	}
+
+	/// Returns current snapshot of registered methods.
+	fn snapshot(&self) -> Arc<MetaIoHandler<T>> {
+		self.handler.read().expect(LOCK_PROOF).clone()
+	}
+
+	/// Modifies a copy of current snapshot and replaces the snapshot with it.
+	fn modify<R, F: FnOnce(&mut MetaIoHandler<T>) -> R>(&self, modify: F) -> R {
+		let _writer = self.writer.lock().expect(LOCK_PROOF);
+		let mut handler = (*self.snapshot()).clone();
+		let result = modify(&mut handler);
+		*self.handler.write().expect(LOCK_PROOF) = Arc::new(handler);
+		result
+	}
+
+	fn handles(handler: &MetaIoHandler<T>, call: &Call) -> bool {
+		match *call {
+			Call::MethodCall(ref call) => handler.has_method(&call.method),
+			Call::Notification(ref notification) => handler.has_method(&notification.method),
+			Call::Invalid { .. } => false,
+		}
+	}
+}
+
+impl<T: Metadata> Middleware<T> for SharedIoHandler<T> {
+	type Future = Box<Future<Item=Option<Response>, Error=()> + Send>;
+
+	fn on_request<F, X>(&self, request: Request, meta: T, next: F) -> Self::Future where
+		F: FnOnce(Request, T) -> X + Send,
+		X: Future<Item=Option<Response>, Error=()> + Send + 'static,
+	{
+		// The lock is only held to take a snapshot, not while the calls are dispatched.
+		let handler = self.snapshot();
+
+		let is_shared = match request {
+			Request::Single(ref call) => Self::handles(&handler, call),
+			Request::Batch(ref calls) => calls.iter().any(|call| Self::handles(&handler, call)),
+		};
+		if !is_shared {
+			return Box::new(next(request, meta));
+		}
+
+		match request {
+			Request::Single(call) => {
+				Box::new(handler.handle_call(call, meta).map(|output| output.map(Response::Single)))
+			},
+			Request::Batch(calls) => {
+				// Outputs are put back in the order of calls: each call is either handled here
+				// or by the next handler, which returns outputs of all calls but notifications.
+				// Invalid calls are handled here, so that they keep their index in the whole batch.
+				let is_local = |call: &Call| match *call {
+					Call::Invalid { .. } => true,
+					_ => Self::handles(&handler, call),
+				};
+				let positions: Vec<_> = calls.iter().map(|call| match *call {
+					Call::Notification(_) => (is_local(call), false),
+					_ => (is_local(call), true),
+				}).collect();
+				let (shared, rest): (Vec<_>, Vec<_>) = calls.into_iter().enumerate().partition(|&(_, ref call)| is_local(call));
+				let shared: Vec<_> = shared.into_iter().map(|(index, call)| match call {
+					Call::Invalid { .. } => future::Either::A(handler.handle_call(call, meta.clone())
+						.map(move |output| output.map(|output| output.with_batch_index(index)))),
+					call => future::Either::B(handler.handle_call(call, meta.clone())),
+				}).collect();
+				let rest: Vec<_> = rest.into_iter().map(|(_, call)| call).collect();
+				let rest = if rest.is_empty() {
+					future::Either::A(future::ok(None))
+				} else {
+					future::Either::B(next(Request::Batch(rest), meta))
+				};
+
+				Box::new(futures::future::join_all(shared).join(rest).map(move |(shared, rest)| {
+					let mut shared = shared.into_iter();
+					let mut rest = match rest {
+						Some(Response::Single(output)) => vec![output],
+						Some(Response::Batch(rest)) => rest,
+						None => vec![],
+					}.into_iter();
+					let mut outputs: Vec<_> = positions.into_iter().filter_map(|position| match position {
+						(true, _) => shared.next().and_then(|output| output),
+						(false, true) => rest.next(),
+						(false, false) => None,
+					}).collect();
+					outputs.extend(rest);
+
+					if outputs.is_empty() {
+						None
+					} else {
+						Some(Response::Batch(outputs))
+					}
+				}))
+			},
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use futures::Future;
+	use io::MetaIoHandler;
+	use types::Value;
+	use super::SharedIoHandler;
+
+	#[test]
+	fn should_dispatch_batch_to_both_handlers() {
+		// given
+		let shared = SharedIoHandler::default();
+		let mut io = MetaIoHandler::<(), _>::with_middleware(shared.clone());
+		io.add_method("static", |_| Ok(Value::String("static".into())));
+		shared.add_method("dynamic", |_| Ok(Value::String("dynamic".into())));
+
+		// when
+		let request = r#"[{"jsonrpc":"2.0","method":"dynamic","id":1},{"jsonrpc":"2.0","method":"static","id":2}]"#;
+		let response = io.handle_request(request, ()).wait().unwrap();
+
+		// then
+		assert_eq!(
+			response,
+			Some(r#"[{"jsonrpc":"2.0","result":"dynamic","id":1},{"jsonrpc":"2.0","result":"static","id":2}]"#.into())
+		);
+	}
+
+	#[test]
+	fn should_keep_outputs_in_order_of_calls() {
+		// given
+		let shared = SharedIoHandler::default();
+		let mut io = MetaIoHandler::<(), _>::with_middleware(shared.clone());
+		io.add_method("static", |_| Ok(Value::String("static".into())));
+		io.add_notification("notify", |_| {});
+		shared.add_method("dynamic", |_| Ok(Value::String("dynamic".into())));
+
+		// when
+		let request = r#"[
+			{"jsonrpc":"2.0","method":"static","id":1},
+			{"jsonrpc":"2.0","method":"notify"},
+			{"jsonrpc":"2.0","method":"dynamic","id":2},
+			{"jsonrpc":"2.0","method":"static","id":3}
+		]"#;
+		let response = io.handle_request(request, ()).wait().unwrap();
+
+		// then
+		assert_eq!(
+			response,
+			Some(r#"[{"jsonrpc":"2.0","result":"static","id":1},{"jsonrpc":"2.0","result":"dynamic","id":2},{"jsonrpc":"2.0","result":"static","id":3}]"#.into())
+		);
+	}
+
+	#[test]
+	fn should_add_index_in_whole_batch_to_invalid_calls() {
+		// given
+		let shared = SharedIoHandler::default();
+		let mut io = MetaIoHandler::<(), _>::with_middleware(shared.clone());
+		io.add_method("static", |_| Ok(Value::String("static".into())));
+		shared.add_method("dynamic", |_| Ok(Value::String("dynamic".into())));
+
+		// when
+		let request = r#"[
+			{"jsonrpc":"2.0","method":"dynamic","id":1},
+			{"jsonrpc":"2.0","method":"static","id":2},
+			{"jsonrpc":"2.0","id":3}
+		]"#;
+		let response = io.handle_request(request, ()).wait().unwrap();
+
+		// then
+		assert_eq!(
+			response,
+			Some(r#"[{"jsonrpc":"2.0","result":"dynamic","id":1},{"jsonrpc":"2.0","result":"static","id":2},{"jsonrpc":"2.0","error":{"code":-32600,"message":"Invalid request","data":{"batch_index":2}},"id":3}]"#.into())
+		);
+	}
+
+	#[test]
+	fn should_register_methods_from_within_calls() {
+		// given
+		let shared = SharedIoHandler::default();
+		let io = MetaIoHandler::<(), _>::with_middleware(shared.clone());
+		let registry = shared.clone();
+		shared.add_method("load", move |_| {
+			registry.add_method("loaded", |_| Ok(Value::String("loaded".into())));
+			Ok(Value::Bool(true))
+		});
+
+		// when
+		let loading = io.handle_request(r#"{"jsonrpc":"2.0","method":"load","id":1}"#, ());
+		let response = io.handle_request(r#"{"jsonrpc":"2.0","method":"loaded","id":2}"#, ()).wait().unwrap();
+		loading.wait().unwrap();
+		let loaded = io.handle_request(r#"{"jsonrpc":"2.0","method":"loaded","id":3}"#, ()).wait().unwrap();
+
+		// then
+		assert_eq!(
+			response,
+			Some(r#"{"jsonrpc":"2.0","error":{"code":-32601,"message":"Method not found"},"id":2}"#.into())
+		);
+		assert_eq!(loaded, Some(r#"{"jsonrpc":"2.0","result":"loaded","id":3}"#.into()));
+	}
+
+	#[test]
+	fn should_stop_serving_removed_methods() {
+		// given
+		let shared = SharedIoHandler::default();
+		let io = MetaIoHandler::<(), _>::with_middleware(shared.clone());
+		shared.add_method("dynamic", |_| Ok(Value::String("dynamic".into())));
+		assert!(shared.remove_method("dynamic").is_some());
+
+		// when
+		let request = r#"{"jsonrpc":"2.0","method":"dynamic","id":1}"#;
+		let response = io.handle_request(request, ()).wait().unwrap();
+
+		// then
+		assert_eq!(
+			response,
+			Some(r#"{"jsonrpc":"2.0","error":{"code":-32601,"message":"Method not found"},"id":1}"#.into())
+		);
+	}
+}
@@ -1,7 +1,10 @@
 //! jsonrpc errors
+use std::fmt;
+
 use serde::de::{Deserialize, Deserializer};
 use serde::ser::{Serialize, Serializer};
-use super::Value;
+use serde_json;
+use super::{Failure, Id, Output, Value, Version};
 
 /// JSONRPC error code
 #[derive(Debug, PartialEq, Clone)]
@@ -124,6 +127,24 @@ impl Error {
 		}
 	}
 
+	/// Creates new `InvalidParams` with given details serialized into `data`.
+	///
+	/// Details that can't be serialized to JSON are included in their debug format as a string.
+	pub fn invalid_params_with_details<M, T>(message: M, details: T) -> Self where
+		M: Into<String>,
+		T: Serialize + fmt::Debug,
+	{
+		let data = serde_json::to_value(&details).unwrap_or_else(|err| {
+			warn!(target: "rpc", "Unable to serialize invalid params details: {}", err);
+			Value::String(format!("{:?}", details))
+		});
+		Error {
+			code: ErrorCode::InvalidParams,
+			message: message.into(),
+			data: Some(data),
+		}
+	}
+
 	/// Creates new `InternalError`
 	pub fn internal_error() -> Self {
 		Self::new(ErrorCode::InternalError)
@@ -137,4 +158,17 @@ impl Error {
 			data: None,
 		}
 	}
+
+	/// Serializes this error as a JSON-RPC 2.0 failure response to a call with given id.
+	///
+	/// The output is exactly what the servers send for a failed call (without trailing new line).
+	pub fn to_response_json(&self, id: Id) -> String {
+		let output = Output::Failure(Failure {
+			jsonrpc: Some(Version::V2),
+			error: self.clone(),
+			id,
+			raw: None,
+		});
+		serde_json::to_string(&output).expect("Serialization of response is infallible;qed")
+	}
 }
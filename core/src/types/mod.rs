@@ -13,7 +13,7 @@ pub use serde_json::to_string;
 
 pub use self::error::{ErrorCode, Error};
 pub use self::id::Id;
-pub use self::params::Params;
+pub use self::params::{FieldError, Fields, Params};
 pub use self::request::{Request, Call, MethodCall, Notification};
 pub use self::response::{Output, Response, Success, Failure};
 pub use self::version::Version;
@@ -6,6 +6,18 @@ use serde_json::value::from_value;
 
 use super::{Value, Error};
 
+/// Maximal number of `FieldError`s listed in error data.
+const MAX_FIELD_ERRORS: usize = 32;
+
+/// Validation error of a single named parameter.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct FieldError {
+	/// Name of the parameter.
+	pub field: String,
+	/// Description of the error.
+	pub message: String,
+}
+
 /// Request parameters
 #[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 #[serde(untagged)]
@@ -20,16 +32,127 @@ pub enum Params {
 
 impl Params {
 	/// Parse incoming `Params` into expected types.
+	///
+	/// A single object wrapped in an array (`[{...}]`) is also accepted
+	/// in place of a map if it can't be parsed as an array.
+	/// An empty array is accepted in place of absent params (e.g. for `()`).
 	pub fn parse<D>(self) -> Result<D, Error> where D: DeserializeOwned {
+		let fallback = match self {
+			Params::Array(ref vec) if vec.len() == 1 && vec[0].is_object() => Some(vec[0].clone()),
+			Params::Array(ref vec) if vec.is_empty() => Some(Value::Null),
+			_ => None,
+		};
 		let value = match self {
 			Params::Array(vec) => Value::Array(vec),
 			Params::Map(map) => Value::Object(map),
 			Params::None =>  Value::Null
 		};
 
-		from_value(value).map_err(|e| {
-			Error::invalid_params(format!("Invalid params: {}.", e))
-		})
+		from_value(value)
+			.or_else(|e| match fallback {
+				Some(value) => from_value(value).map_err(|_| e),
+				None => Err(e),
+			})
+			.map_err(|e| {
+				Error::invalid_params(format!("Invalid params: {}.", e))
+			})
+	}
+
+	/// Parse incoming by-name `Params` (an object) into expected type.
+	///
+	/// Unlike `parse` positional params are rejected.
+	pub fn parse_named<D>(self) -> Result<D, Error> where D: DeserializeOwned {
+		match self {
+			Params::Map(map) => from_value(Value::Object(map)).map_err(|e| {
+				Error::invalid_params(format!("Invalid params: {}.", e))
+			}),
+			_ => Err(Error::invalid_params("Invalid params: expected named parameters (an object).")),
+		}
+	}
+
+	/// Parse incoming by-name `Params` (an object) field by field, reporting errors of all invalid fields.
+	///
+	/// `parse` reads the fields with `Fields::required` and `Fields::optional` and returns `None`
+	/// if some of them are invalid. The `FieldError`s of all invalid fields (up to 32) are then listed in error `data`.
+	pub fn parse_fields<D, F>(self, parse: F) -> Result<D, Error> where
+		F: FnOnce(&mut Fields) -> Option<D>,
+	{
+		let map = match self {
+			Params::Map(map) => map,
+			_ => return Err(Error::invalid_params("Invalid params: expected named parameters (an object).")),
+		};
+
+		let mut fields = Fields {
+			map,
+			errors: Vec::new(),
+			invalid: 0,
+		};
+		match parse(&mut fields) {
+			Some(result) if fields.invalid == 0 => Ok(result),
+			_ if fields.invalid == 0 => Err(Error::invalid_params("Invalid params.")),
+			_ => Err(Error::invalid_params_with_details(format!("Invalid params: {} invalid fields.", fields.invalid), fields.errors)),
+		}
+	}
+}
+
+/// Named params read by `Params::parse_fields`, collecting errors of invalid fields.
+#[derive(Debug)]
+pub struct Fields {
+	/// Fields that were not read yet.
+	map: serde_json::Map<String, Value>,
+	errors: Vec<FieldError>,
+	invalid: usize,
+}
+
+impl Fields {
+	/// Reads a required field, returns `None` if it's missing or invalid.
+	pub fn required<T: DeserializeOwned>(&mut self, field: &str) -> Option<T> {
+		match self.map.remove(field) {
+			Some(value) => self.deserialize(field, value),
+			None => {
+				self.error(field, "missing field".into());
+				None
+			},
+		}
+	}
+
+	/// Reads an optional field, returns `Some(None)` if it's missing and `None` if it's invalid.
+	pub fn optional<T: DeserializeOwned>(&mut self, field: &str) -> Option<Option<T>> {
+		match self.map.remove(field) {
+			Some(Value::Null) | None => Some(None),
+			Some(value) => self.deserialize(field, value).map(Some),
+		}
+	}
+
+	/// Reports all fields that were not read as unknown.
+	pub fn deny_unknown(&mut self) {
+		self.invalid += self.map.len();
+		let free = MAX_FIELD_ERRORS.saturating_sub(self.errors.len());
+		let unknown: Vec<_> = self.map.keys().take(free).cloned().collect();
+		self.errors.extend(unknown.into_iter().map(|field| FieldError {
+			field,
+			message: "unknown field".into(),
+		}));
+	}
+
+	fn deserialize<T: DeserializeOwned>(&mut self, field: &str, value: Value) -> Option<T> {
+		match from_value(value) {
+			Ok(value) => Some(value),
+			Err(e) => {
+				self.error(field, e.to_string());
+				None
+			},
+		}
+	}
+
+	fn error(&mut self, field: &str, message: String) {
+		self.invalid += 1;
+		if self.errors.len() < MAX_FIELD_ERRORS {
+			self.errors.push(FieldError {
+				field: field.into(),
+				message,
+			});
+		}
 	}
 }
 
@@ -55,6 +178,88 @@ mod tests {
 		]), deserialized);
 	}
 
+	#[test]
+	fn should_parse_named_params() {
+		#[derive(Debug, PartialEq, Deserialize)]
+		struct Hello {
+			name: String,
+		}
+
+		let params = |s| serde_json::from_str::<Params>(s).unwrap();
+
+		assert_eq!(params(r#"{"name": "world"}"#).parse_named::<Hello>(), Ok(Hello { name: "world".into() }));
+		assert_eq!(
+			params(r#"{"other": "world"}"#).parse_named::<Hello>().unwrap_err().message,
+			"Invalid params: missing field `name`."
+		);
+		assert_eq!(
+			params(r#"["world"]"#).parse_named::<Hello>().unwrap_err().message,
+			"Invalid params: expected named parameters (an object)."
+		);
+	}
+
+	#[test]
+	fn should_collect_errors_of_all_invalid_fields() {
+		#[derive(Debug, PartialEq)]
+		struct Person {
+			name: String,
+			age: u8,
+			city: String,
+			email: Option<String>,
+		}
+
+		let parse = |s| serde_json::from_str::<Params>(s).unwrap().parse_fields(|fields| {
+			let name = fields.required("name");
+			let age = fields.required("age");
+			let city = fields.required("city");
+			let email = fields.optional("email");
+			fields.deny_unknown();
+			Some(Person { name: name?, age: age?, city: city?, email: email? })
+		});
+
+		let error = parse(r#"{"name": 5, "age": -1, "email": "jan@example.com", "zip": "10115"}"#).unwrap_err();
+
+		assert_eq!(error.code, ErrorCode::InvalidParams);
+		assert_eq!(error.message, "Invalid params: 4 invalid fields.");
+		assert_eq!(error.data, Some(serde_json::from_str(r#"[
+			{"field": "name", "message": "invalid type: integer `5`, expected a string"},
+			{"field": "age", "message": "invalid value: integer `-1`, expected u8"},
+			{"field": "city", "message": "missing field"},
+			{"field": "zip", "message": "unknown field"}
+		]"#).unwrap()));
+		assert_eq!(parse(r#"{"name": "Jan", "age": 30, "city": "Berlin"}"#), Ok(Person {
+			name: "Jan".into(),
+			age: 30,
+			city: "Berlin".into(),
+			email: None,
+		}));
+		assert_eq!(
+			serde_json::from_str::<Params>(r#"["Jan"]"#).unwrap().parse_fields(|_| Some(())).unwrap_err().message,
+			"Invalid params: expected named parameters (an object)."
+		);
+	}
+
+	#[test]
+	fn should_cap_number_of_listed_field_errors() {
+		let fields: Vec<_> = (0..100).map(|i| format!(r#""field{}": {}"#, i, i)).collect();
+		let params = serde_json::from_str::<Params>(&format!("{{{}}}", fields.join(", "))).unwrap();
+
+		let error = params.parse_fields(|fields| {
+			fields.deny_unknown();
+			Some(())
+		}).unwrap_err();
+
+		assert_eq!(error.message, "Invalid params: 100 invalid fields.");
+		assert_eq!(error.data.unwrap().as_array().unwrap().len(), 32);
+	}
+
+	#[test]
+	fn should_parse_empty_array_and_absent_params_as_unit() {
+		assert_eq!(Params::None.parse::<()>(), Ok(()));
+		assert_eq!(Params::Array(vec![]).parse::<()>(), Ok(()));
+		assert!(Params::Array(vec![Value::from(1)]).parse::<()>().is_err());
+	}
+
 	#[test]
 	fn should_return_meaningful_error_when_deserialization_fails() {
 		// given
@@ -75,4 +280,28 @@ mod tests {
 		assert_eq!(err2.message, "Invalid params: invalid length 2, expected a tuple of size 3.");
 		assert_eq!(err2.data, None);
 	}
+
+	#[test]
+	fn should_parse_single_object_wrapped_in_array() {
+		#[derive(Debug, PartialEq, Deserialize)]
+		struct Options {
+			name: String,
+			verbose: bool,
+		}
+
+		// given
+		let map = serde_json::from_str::<Params>(r#"{"name": "world", "verbose": true}"#).unwrap();
+		let array = serde_json::from_str::<Params>(r#"[{"name": "world", "verbose": true}]"#).unwrap();
+
+		// when
+		let from_map: Options = map.parse().unwrap();
+		let from_array: Options = array.parse().unwrap();
+		let as_tuple: (Value,) = serde_json::from_str::<Params>(r#"[{"name": "world"}]"#).unwrap().parse().unwrap();
+
+		// then
+		let expected = Options { name: "world".into(), verbose: true };
+		assert_eq!(from_map, expected);
+		assert_eq!(from_array, expected);
+		assert_eq!(as_tuple.0["name"], Value::String("world".into()));
+	}
 }
@@ -1,4 +1,6 @@
 //! jsonrpc response
+use std::io;
+use serde_json;
 use super::{Id, Value, Error, ErrorCode, Version};
 use {Result as CoreResult};
 
@@ -11,7 +13,10 @@ pub struct Success {
 	/// Result
 	pub result: Value,
 	/// Correlation id
-	pub id: Id
+	pub id: Id,
+	/// Complete serialized response returned by a passthrough method, written instead of this output.
+	#[serde(skip)]
+	pub raw: Option<String>,
 }
 
 /// Unsuccessful response
@@ -23,7 +28,10 @@ pub struct Failure {
 	/// Error
 	pub error: Error,
 	/// Correlation id
-	pub id: Id
+	pub id: Id,
+	/// Complete serialized response returned by a passthrough method, written instead of this output.
+	#[serde(skip)]
+	pub raw: Option<String>,
 }
 
 /// Represents output - failure or success
@@ -44,11 +52,13 @@ impl Output {
 				id: id,
 				jsonrpc: jsonrpc,
 				result: result,
+				raw: None,
 			}),
 			Err(error) => Output::Failure(Failure {
 				id: id,
 				jsonrpc: jsonrpc,
 				error: error,
+				raw: None,
 			}),
 		}
 	}
@@ -59,9 +69,42 @@ impl Output {
 			id: id,
 			jsonrpc: jsonrpc,
 			error: Error::new(ErrorCode::InvalidRequest),
+			raw: None,
 		})
 	}
 
+	/// Creates new output of a complete serialized response returned by a passthrough method.
+	///
+	/// The response is written verbatim, invalid responses are replaced with internal error.
+	pub fn passthrough(response: String, id: Id, jsonrpc: Option<Version>) -> Self {
+		match serde_json::from_str(&response) {
+			Ok(Output::Success(success)) => Output::Success(Success { raw: Some(response), ..success }),
+			Ok(Output::Failure(failure)) => Output::Failure(Failure { raw: Some(response), ..failure }),
+			Err(err) => {
+				warn!(target: "rpc", "Invalid passthrough response: {:?}", err);
+				Output::from(Err(Error::internal_error()), id, jsonrpc)
+			},
+		}
+	}
+
+	/// Adds index of the call within a batch to data of the error (unless it has data already).
+	///
+	/// Used for outputs of invalid calls of a batch, which can't be told apart by their ids.
+	pub fn with_batch_index(self, index: usize) -> Self {
+		match self {
+			Output::Failure(mut failure) => {
+				if failure.error.data.is_none() {
+					let mut data = serde_json::Map::new();
+					data.insert("batch_index".into(), Value::from(index));
+					failure.error.data = Some(Value::Object(data));
+					failure.raw = None;
+				}
+				Output::Failure(failure)
+			},
+			output => output,
+		}
+	}
+
 	/// Get the jsonrpc protocol version.
 	pub fn version(&self) -> Option<Version> {
 		match *self {
@@ -77,6 +120,18 @@ impl Output {
 			Output::Failure(ref f) => &f.id,
 		}
 	}
+
+	/// Writes serialized output to given writer.
+	pub fn write_json<W: io::Write>(&self, mut writer: W) -> io::Result<()> {
+		let raw = match *self {
+			Output::Success(ref s) => s.raw.as_ref(),
+			Output::Failure(ref f) => f.raw.as_ref(),
+		};
+		match raw {
+			Some(raw) => writer.write_all(raw.as_bytes()),
+			None => serde_json::to_writer(writer, self).map_err(Into::into),
+		}
+	}
 }
 
 impl From<Output> for CoreResult<Value> {
@@ -106,8 +161,35 @@ impl Response {
 			id: Id::Null,
 			jsonrpc: jsonrpc,
 			error: error,
+			raw: None,
 		}.into()
 	}
+
+	/// Writes serialized response to given writer.
+	///
+	/// Unlike serializing the response with `serde`, passthrough responses are written verbatim.
+	pub fn write_json<W: io::Write>(&self, mut writer: W) -> io::Result<()> {
+		match *self {
+			Response::Single(ref output) => output.write_json(writer),
+			Response::Batch(ref outputs) => {
+				writer.write_all(b"[")?;
+				for (index, output) in outputs.iter().enumerate() {
+					if index > 0 {
+						writer.write_all(b",")?;
+					}
+					output.write_json(&mut writer)?;
+				}
+				writer.write_all(b"]")
+			},
+		}
+	}
+
+	/// Serializes the response (see `write_json`).
+	pub fn to_json(&self) -> String {
+		let mut buffer = Vec::new();
+		self.write_json(&mut buffer).expect("Serialization of response is infallible; qed");
+		String::from_utf8(buffer).expect("Serialized JSON is valid UTF-8; qed")
+	}
 }
 
 impl From<Failure> for Response {
@@ -130,7 +212,8 @@ fn success_output_serialize() {
 	let so = Output::Success(Success {
 		jsonrpc: Some(Version::V2),
 		result: Value::from(1),
-		id: Id::Num(1)
+		id: Id::Num(1),
+		raw: None,
 	});
 
 	let serialized = serde_json::to_string(&so).unwrap();
@@ -148,7 +231,8 @@ fn success_output_deserialize() {
 	assert_eq!(deserialized, Output::Success(Success {
 		jsonrpc: Some(Version::V2),
 		result: Value::from(1),
-		id: Id::Num(1)
+		id: Id::Num(1),
+		raw: None,
 	}));
 }
 
@@ -159,7 +243,8 @@ fn failure_output_serialize() {
 	let fo = Output::Failure(Failure {
 		jsonrpc: Some(Version::V2),
 		error: Error::parse_error(),
-		id: Id::Num(1)
+		id: Id::Num(1),
+		raw: None,
 	});
 
 	let serialized = serde_json::to_string(&fo).unwrap();
@@ -173,7 +258,8 @@ fn failure_output_serialize_jsonrpc_1() {
 	let fo = Output::Failure(Failure {
 		jsonrpc: None,
 		error: Error::parse_error(),
-		id: Id::Num(1)
+		id: Id::Num(1),
+		raw: None,
 	});
 
 	let serialized = serde_json::to_string(&fo).unwrap();
@@ -190,7 +276,8 @@ fn failure_output_deserialize() {
 	assert_eq!(deserialized, Output::Failure(Failure {
 		jsonrpc: Some(Version::V2),
 		error: Error::parse_error(),
-		id: Id::Num(1)
+		id: Id::Num(1),
+		raw: None,
 	}));
 }
 
@@ -205,7 +292,8 @@ fn single_response_deserialize() {
 	assert_eq!(deserialized, Response::Single(Output::Success(Success {
 		jsonrpc: Some(Version::V2),
 		result: Value::from(1),
-		id: Id::Num(1)
+		id: Id::Num(1),
+		raw: None,
 	})));
 }
 
@@ -221,12 +309,14 @@ fn batch_response_deserialize() {
 		Output::Success(Success {
 			jsonrpc: Some(Version::V2),
 			result: Value::from(1),
-			id: Id::Num(1)
+			id: Id::Num(1),
+			raw: None,
 		}),
 		Output::Failure(Failure {
 			jsonrpc: Some(Version::V2),
 			error: Error::parse_error(),
-			id: Id::Num(1)
+			id: Id::Num(1),
+			raw: None,
 		})
 	]));
 }
@@ -0,0 +1,92 @@
+//! Limit of request bytes buffered across all connections.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Budget of bytes that can be buffered by all requests at once.
+#[derive(Debug)]
+pub struct BufferBudget {
+	limit: usize,
+	used: AtomicUsize,
+}
+
+impl BufferBudget {
+	/// Creates new budget of given number of bytes.
+	pub fn new(limit: usize) -> Self {
+		BufferBudget {
+			limit,
+			used: AtomicUsize::new(0),
+		}
+	}
+
+	/// Returns the maximal number of bytes that can be reserved.
+	pub fn limit(&self) -> usize {
+		self.limit
+	}
+
+	/// Returns the number of currently reserved bytes.
+	pub fn used(&self) -> usize {
+		self.used.load(Ordering::SeqCst)
+	}
+
+	/// Reserves given number of bytes.
+	///
+	/// Returns `None` if the reservation would exceed the limit.
+	pub fn reserve(budget: &Arc<Self>, bytes: usize) -> Option<Reservation> {
+		if !budget.add(bytes) {
+			return None;
+		}
+
+		Some(Reservation {
+			budget: budget.clone(),
+			bytes,
+		})
+	}
+
+	/// Marks given number of bytes as used, unless it would exceed the limit.
+	fn add(&self, bytes: usize) -> bool {
+		let mut used = self.used.load(Ordering::SeqCst);
+		loop {
+			let total = match used.checked_add(bytes) {
+				Some(total) if total <= self.limit => total,
+				_ => return false,
+			};
+			match self.used.compare_exchange(used, total, Ordering::SeqCst, Ordering::SeqCst) {
+				Ok(_) => return true,
+				Err(current) => used = current,
+			}
+		}
+	}
+}
+
+/// Bytes reserved in a `BufferBudget`, released when dropped.
+#[derive(Debug)]
+pub struct Reservation {
+	budget: Arc<BufferBudget>,
+	bytes: usize,
+}
+
+impl Reservation {
+	/// Returns the number of reserved bytes.
+	pub fn bytes(&self) -> usize {
+		self.bytes
+	}
+
+	/// Reserves given number of additional bytes.
+	///
+	/// Returns `false` (leaving the reservation intact) if it would exceed the limit.
+	pub fn grow(&mut self, bytes: usize) -> bool {
+		if !self.budget.add(bytes) {
+			return false;
+		}
+
+		self.bytes += bytes;
+		true
+	}
+}
+
+impl Drop for Reservation {
+	fn drop(&mut self) {
+		self.budget.used.fetch_sub(self.bytes, Ordering::SeqCst);
+	}
+}
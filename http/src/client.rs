@@ -0,0 +1,232 @@
+//! Client making JSON-RPC calls to other servers over HTTP.
+
+use std::{error, fmt, io};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use hyper::{self, header, Method, StatusCode};
+use jsonrpc::{self as core, Error, Params, Value};
+use jsonrpc::futures::{future, Future, Stream};
+use jsonrpc::futures::sync::oneshot;
+use jsonrpc::serde_json;
+use server_utils::reactor::{Remote, UninitializedRemote};
+
+/// Future resolving to the result of a request sent by `HttpClient`.
+pub type ClientFuture<T> = Box<Future<Item = T, Error = RpcError> + Send>;
+
+/// JSON-RPC 2.0 client sending calls to given URL.
+///
+/// Requests are sent from a dedicated event loop, closed when the client is dropped.
+pub struct HttpClient {
+	url: hyper::Uri,
+	remote: Remote,
+	next_id: AtomicUsize,
+}
+
+impl fmt::Debug for HttpClient {
+	fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+		fmt.debug_struct("HttpClient")
+			.field("url", &self.url)
+			.finish()
+	}
+}
+
+impl HttpClient {
+	/// Creates new client of the server at given URL.
+	pub fn new(url: &str) -> io::Result<Self> {
+		let url = url.parse().map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, format!("Invalid URL: {:?}", e)))?;
+		Ok(HttpClient {
+			url,
+			remote: UninitializedRemote::Unspawned.init_with_name("http.client")?,
+			next_id: AtomicUsize::new(1),
+		})
+	}
+
+	/// Calls given method, resolving to its result or the error returned by the server.
+	pub fn call_method(&self, method: &str, params: Params) -> ClientFuture<Value> {
+		let call = self.call(method.into(), params);
+		let id = call.id.clone();
+		let request = core::Request::Single(core::Call::MethodCall(call));
+		Box::new(self.send(request).and_then(move |response| match response {
+			Some(core::Response::Single(output)) => {
+				// Servers answer with `null` id if they couldn't read the id of the request.
+				let is_unidentified_failure = match output {
+					core::Output::Failure(ref failure) => failure.id == core::Id::Null,
+					core::Output::Success(_) => false,
+				};
+				if *output.id() == id || is_unidentified_failure {
+					output_result(output)
+				} else {
+					Err(RpcError::InvalidResponse("id of the output doesn't match the call".into()))
+				}
+			},
+			Some(core::Response::Batch(_)) => Err(RpcError::InvalidResponse("expected a single output".into())),
+			None => Err(RpcError::InvalidResponse("missing output of a call".into())),
+		}))
+	}
+
+	/// Sends a notification of given method, resolving once the server accepted it.
+	pub fn notify(&self, method: &str, params: Params) -> ClientFuture<()> {
+		let request = core::Request::Single(notification(method.into(), params));
+		Box::new(self.send(request).map(|_| ()))
+	}
+
+	/// Calls given methods in a single batch.
+	///
+	/// Resolves to results of the calls in the same order as the calls.
+	pub fn call_batch(&self, calls: Vec<(String, Params)>) -> ClientFuture<Vec<Result<Value, RpcError>>> {
+		calls.into_iter().fold(self.batch(), |batch, (method, params)| batch.call(&method, params)).send()
+	}
+
+	/// Starts a batch of calls and notifications sent in a single request.
+	pub fn batch(&self) -> BatchBuilder {
+		BatchBuilder {
+			client: self,
+			calls: Vec::new(),
+		}
+	}
+
+	fn call(&self, method: String, params: Params) -> core::MethodCall {
+		core::MethodCall {
+			jsonrpc: Some(core::Version::V2),
+			method,
+			params,
+			id: core::Id::Num(self.next_id.fetch_add(1, Ordering::SeqCst) as u64),
+		}
+	}
+
+	/// Sends given request, resolving to `None` if the response is empty (e.g. to notifications).
+	///
+	/// Responses with error status are accepted as long as their body is a JSON-RPC response.
+	fn send(&self, request: core::Request) -> ClientFuture<Option<core::Response>> {
+		let body = serde_json::to_string(&request).expect("Serialization of request is infallible; qed");
+		let url = self.url.clone();
+		let (tx, rx) = oneshot::channel();
+		self.remote.remote().spawn(move |handle| {
+			let mut request = hyper::Request::new(Method::Post, url);
+			request.headers_mut().set(header::ContentType::json());
+			request.set_body(body);
+
+			hyper::Client::new(handle).request(request)
+				.and_then(|response| {
+					let status = response.status();
+					response.body().concat2().map(move |body| (status, body))
+				})
+				.then(move |result| {
+					let _ = tx.send(result);
+					Ok(())
+				})
+		});
+
+		Box::new(rx.then(|result| {
+			let (status, body) = match result {
+				Ok(Ok(response)) => response,
+				Ok(Err(e)) => return Err(RpcError::Transport(e.to_string())),
+				Err(_) => return Err(RpcError::Transport("Client event loop is closed".into())),
+			};
+			if status != StatusCode::Ok {
+				return serde_json::from_slice(&body)
+					.map(Some)
+					.map_err(|_| RpcError::Transport(format!("Unexpected response status: {}", status)));
+			}
+			if body.iter().all(u8::is_ascii_whitespace) {
+				return Ok(None);
+			}
+
+			serde_json::from_slice(&body).map(Some).map_err(|e| RpcError::InvalidResponse(e.to_string()))
+		}))
+	}
+}
+
+/// Builder of a batch of calls and notifications sent by `HttpClient` in a single request.
+#[derive(Debug)]
+pub struct BatchBuilder<'a> {
+	client: &'a HttpClient,
+	calls: Vec<core::Call>,
+}
+
+impl<'a> BatchBuilder<'a> {
+	/// Adds a call of given method to the batch.
+	pub fn call(mut self, method: &str, params: Params) -> Self {
+		self.calls.push(core::Call::MethodCall(self.client.call(method.into(), params)));
+		self
+	}
+
+	/// Adds a notification of given method to the batch.
+	///
+	/// Notifications produce no results.
+	pub fn notify(mut self, method: &str, params: Params) -> Self {
+		self.calls.push(notification(method.into(), params));
+		self
+	}
+
+	/// Sends the batch, resolving to results of the calls in the same order as the calls.
+	pub fn send(self) -> ClientFuture<Vec<Result<Value, RpcError>>> {
+		let ids: Vec<_> = self.calls.iter().filter_map(|call| match *call {
+			core::Call::MethodCall(ref call) => Some(call.id.clone()),
+			_ => None,
+		}).collect();
+		if self.calls.is_empty() {
+			return Box::new(future::ok(Vec::new()));
+		}
+
+		Box::new(self.client.send(core::Request::Batch(self.calls)).and_then(move |response| {
+			let outputs = match response {
+				Some(core::Response::Batch(outputs)) => outputs,
+				Some(core::Response::Single(output)) => vec![output],
+				None => vec![],
+			};
+			let mut outputs: HashMap<_, _> = outputs.into_iter().map(|output| (output.id().clone(), output)).collect();
+			Ok(ids.iter().map(|id| match outputs.remove(id) {
+				Some(output) => output_result(output),
+				None => Err(RpcError::InvalidResponse("missing output of a call".into())),
+			}).collect())
+		}))
+	}
+}
+
+/// Error of a call made by `HttpClient`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RpcError {
+	/// Error returned by the server.
+	Server(Error),
+	/// Request couldn't be sent or the response couldn't be received.
+	Transport(String),
+	/// Response is not a valid JSON-RPC response to the request.
+	InvalidResponse(String),
+}
+
+impl fmt::Display for RpcError {
+	fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			RpcError::Server(ref error) => write!(fmt, "Server error: {} ({:?})", error.message, error.code),
+			RpcError::Transport(ref error) => write!(fmt, "Transport error: {}", error),
+			RpcError::InvalidResponse(ref error) => write!(fmt, "Invalid response: {}", error),
+		}
+	}
+}
+
+impl error::Error for RpcError {
+	fn description(&self) -> &str {
+		match *self {
+			RpcError::Server(_) => "Server error",
+			RpcError::Transport(_) => "Transport error",
+			RpcError::InvalidResponse(_) => "Invalid response",
+		}
+	}
+}
+
+fn notification(method: String, params: Params) -> core::Call {
+	core::Call::Notification(core::Notification {
+		jsonrpc: Some(core::Version::V2),
+		method,
+		params,
+	})
+}
+
+fn output_result(output: core::Output) -> Result<Value, RpcError> {
+	match output {
+		core::Output::Success(success) => Ok(success.result),
+		core::Output::Failure(failure) => Err(RpcError::Server(failure.error)),
+	}
+}
@@ -0,0 +1,260 @@
+//! Reporting of I/O errors, idle timeouts and limits of client connections.
+
+use std::{fmt, io};
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use jsonrpc::futures::{Async, Future, Poll};
+use server_utils::tokio_core::reactor::{Handle, Timeout};
+use server_utils::tokio_io::{AsyncRead, AsyncWrite};
+
+/// Kind of a connection error.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConnectionErrorKind {
+	/// Connection was reset or aborted by the peer.
+	Reset,
+	/// Reading or writing timed out.
+	Timeout,
+	/// Writing to the connection failed, e.g. the peer went away in the middle of a response.
+	///
+	/// The connection is closed and the pending response (if any) dropped.
+	WriteFailed,
+	/// Any other I/O error.
+	Other,
+}
+
+/// I/O error of a client connection passed to `ServerBuilder::on_connection_error` hook.
+#[derive(Debug)]
+pub struct ConnectionError {
+	/// Address of the peer.
+	pub peer: SocketAddr,
+	/// Kind of the error.
+	pub kind: ConnectionErrorKind,
+	/// Underlying error.
+	pub error: io::Error,
+}
+
+impl ConnectionError {
+	fn new(peer: SocketAddr, error: io::Error, writing: bool) -> Self {
+		let kind = match error.kind() {
+			_ if writing => ConnectionErrorKind::WriteFailed,
+			io::ErrorKind::ConnectionReset | io::ErrorKind::ConnectionAborted | io::ErrorKind::BrokenPipe => ConnectionErrorKind::Reset,
+			io::ErrorKind::TimedOut => ConnectionErrorKind::Timeout,
+			_ => ConnectionErrorKind::Other,
+		};
+
+		ConnectionError {
+			peer,
+			kind,
+			error,
+		}
+	}
+}
+
+/// Hook invoked on connection errors.
+pub type ConnectionErrorHook = Option<Arc<Fn(ConnectionError) + Send + Sync>>;
+
+/// Numbers of open connections of each peer IP.
+#[derive(Debug)]
+pub struct PeerConnections {
+	limit: usize,
+	open: Mutex<HashMap<IpAddr, usize>>,
+}
+
+impl PeerConnections {
+	/// Creates new tracker allowing at most `limit` connections per IP.
+	pub fn new(limit: usize) -> Self {
+		PeerConnections {
+			limit,
+			open: Mutex::new(HashMap::new()),
+		}
+	}
+
+	/// Registers new connection from given IP.
+	///
+	/// Returns `None` if the IP already has the maximal number of connections open.
+	pub fn register(connections: &Arc<Self>, ip: IpAddr) -> Option<PeerConnection> {
+		let mut open = connections.open.lock().expect("Connections lock is never poisoned.");
+		let count = open.entry(ip).or_insert(0);
+		if *count >= connections.limit {
+			return None;
+		}
+
+		*count += 1;
+		Some(PeerConnection {
+			connections: connections.clone(),
+			ip,
+		})
+	}
+}
+
+/// Open connection registered in `PeerConnections`, unregistered when dropped.
+#[derive(Debug)]
+pub struct PeerConnection {
+	connections: Arc<PeerConnections>,
+	ip: IpAddr,
+}
+
+impl Drop for PeerConnection {
+	fn drop(&mut self) {
+		let mut open = self.connections.open.lock().expect("Connections lock is never poisoned.");
+		let is_last = match open.get_mut(&self.ip) {
+			Some(count) => {
+				*count -= 1;
+				*count == 0
+			},
+			None => false,
+		};
+		if is_last {
+			open.remove(&self.ip);
+		}
+	}
+}
+
+/// Connection stream reporting its I/O errors to the hook (if any).
+///
+/// With idle timeout set, reading fails with `TimedOut` error once nothing
+/// was read or written for the given time. With maximal age set, the stream ends
+/// once it's older and no request is being read.
+pub struct ObservedStream<T> {
+	stream: T,
+	peer: SocketAddr,
+	hook: ConnectionErrorHook,
+	idle_timeout: Option<(Duration, Handle)>,
+	idle_timer: Option<Timeout>,
+	max_age_timer: Option<Timeout>,
+	// Whether a request was read since the last write.
+	reading_request: bool,
+	// Only kept alive together with the stream.
+	_registration: Option<PeerConnection>,
+}
+
+impl<T> fmt::Debug for ObservedStream<T> {
+	fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+		fmt.debug_struct("ObservedStream")
+			.field("peer", &self.peer)
+			.finish()
+	}
+}
+
+impl<T> ObservedStream<T> {
+	/// Wraps given stream of a connection with `peer`.
+	pub fn new(stream: T, peer: SocketAddr, hook: ConnectionErrorHook) -> Self {
+		ObservedStream {
+			stream,
+			peer,
+			hook,
+			idle_timeout: None,
+			idle_timer: None,
+			max_age_timer: None,
+			reading_request: false,
+			_registration: None,
+		}
+	}
+
+	/// Keeps the connection registered for as long as the stream is alive.
+	pub fn with_registration(mut self, registration: Option<PeerConnection>) -> Self {
+		self._registration = registration;
+		self
+	}
+
+	/// Fails reading after the connection was idle for given time (if any).
+	pub fn with_idle_timeout(mut self, timeout: Option<Duration>, handle: &Handle) -> Self {
+		self.idle_timeout = timeout.map(|timeout| (timeout, handle.clone()));
+		self
+	}
+
+	/// Ends the stream once it's open for longer than given time (if any).
+	pub fn with_max_age(mut self, max_age: Option<Duration>, handle: &Handle) -> Self {
+		self.max_age_timer = max_age.and_then(|max_age| Timeout::new(max_age, handle)
+			.map_err(|err| warn!("Unable to set up maximal connection age: {:?}", err))
+			.ok()
+		);
+		self
+	}
+
+	/// Returns end of the stream if it's past its maximal age and no request is being read.
+	fn check_age(&mut self) -> io::Result<usize> {
+		let expired = match self.max_age_timer {
+			Some(ref mut timer) => timer.poll()?.is_ready(),
+			None => false,
+		};
+
+		if expired && !self.reading_request {
+			Ok(0)
+		} else {
+			Err(io::ErrorKind::WouldBlock.into())
+		}
+	}
+
+	/// Starts (or keeps) the idle timer, returning an error if it elapsed.
+	///
+	/// Polling the timer makes sure the task is woken up once it elapses.
+	fn check_idle(&mut self) -> io::Result<()> {
+		let (timeout, handle) = match self.idle_timeout {
+			Some(ref idle_timeout) => idle_timeout.clone(),
+			None => return Ok(()),
+		};
+		if self.idle_timer.is_none() {
+			self.idle_timer = Some(Timeout::new(timeout, &handle)?);
+		}
+
+		match self.idle_timer.as_mut().expect("Timer was set above; qed").poll()? {
+			Async::Ready(()) => Err(io::Error::new(io::ErrorKind::TimedOut, "Connection was idle for too long")),
+			Async::NotReady => Ok(()),
+		}
+	}
+
+	fn observe<R>(&self, result: io::Result<R>, writing: bool) -> io::Result<R> {
+		if let (&Err(ref err), &Some(ref hook)) = (&result, &self.hook) {
+			if err.kind() != io::ErrorKind::WouldBlock {
+				// `io::Error` is not `Clone`, so the hook gets a copy of its kind and message.
+				hook(ConnectionError::new(self.peer, io::Error::new(err.kind(), err.to_string()), writing));
+			}
+		}
+		result
+	}
+}
+
+impl<T: io::Read> io::Read for ObservedStream<T> {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		let result = match self.stream.read(buf) {
+			Err(ref err) if err.kind() == io::ErrorKind::WouldBlock => self.check_idle().and_then(|_| self.check_age()),
+			result => {
+				if let Ok(read) = result {
+					self.reading_request = self.reading_request || read > 0;
+				}
+				self.idle_timer = None;
+				result
+			},
+		};
+		self.observe(result, false)
+	}
+}
+
+impl<T: io::Write> io::Write for ObservedStream<T> {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		let result = self.stream.write(buf);
+		if result.is_ok() {
+			self.idle_timer = None;
+			self.reading_request = false;
+		}
+		self.observe(result, true)
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		let result = self.stream.flush();
+		self.observe(result, true)
+	}
+}
+
+impl<T: AsyncRead> AsyncRead for ObservedStream<T> {}
+
+impl<T: AsyncWrite> AsyncWrite for ObservedStream<T> {
+	fn shutdown(&mut self) -> Poll<(), io::Error> {
+		let result = self.stream.shutdown();
+		self.observe(result, true)
+	}
+}
@@ -0,0 +1,52 @@
+//! Fault injection for testing resilience of clients.
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::Duration;
+
+use jsonrpc::{Error, ErrorCode};
+
+/// Error code returned for calls failed by fault injection.
+pub const INJECTED_FAULT_ERROR_CODE: i64 = -32002;
+
+/// Faults injected into processing of requests.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FaultConfig {
+	/// Maximal delay added before processing a request.
+	/// Each request is delayed by a random duration up to this value.
+	pub delay: Option<Duration>,
+	/// Fraction of requests (between `0.0` and `1.0`) failed with an injected error
+	/// instead of being processed.
+	pub error_rate: f64,
+}
+
+impl FaultConfig {
+	/// Decides whether the next request should fail.
+	pub fn should_fail(&self) -> bool {
+		random() < self.error_rate
+	}
+
+	/// Returns a random delay for the next request (if configured).
+	pub fn delay(&self) -> Option<Duration> {
+		self.delay.map(|delay| {
+			let millis = delay.as_secs() * 1_000 + u64::from(delay.subsec_nanos()) / 1_000_000;
+			Duration::from_millis((millis as f64 * random()) as u64)
+		})
+	}
+}
+
+/// Returns the error for failed calls.
+pub fn injected_error() -> Error {
+	Error {
+		code: ErrorCode::ServerError(INJECTED_FAULT_ERROR_CODE),
+		message: "Injected fault".into(),
+		data: None,
+	}
+}
+
+/// Returns a random number in `[0, 1)`.
+fn random() -> f64 {
+	// Every `RandomState` is seeded with different keys.
+	let hash = RandomState::new().build_hasher().finish();
+	(hash >> 11) as f64 / (1u64 << 53) as f64
+}
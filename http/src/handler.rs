@@ -1,52 +1,156 @@
 use Rpc;
 
-use std::{fmt, mem, str};
+use std::{cmp, fmt, io, mem, str};
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::net::SocketAddr;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use flate2::Compression;
+use flate2::read::{GzDecoder, ZlibDecoder};
+use flate2::write::GzEncoder;
+use rmp_serde;
+use sha2::{Digest, Sha256};
 
 use hyper::{self, mime, server, Method};
 use hyper::header::{self, Headers};
 use unicase::Ascii;
 
 use jsonrpc::{self as core, FutureResult, Metadata, Middleware, NoopMiddleware};
-use jsonrpc::futures::{Future, Poll, Async, Stream, future};
+use jsonrpc::futures::{Future, Poll, Async, Sink, Stream, future, stream};
+use jsonrpc::futures::sync::mpsc;
 use jsonrpc::serde_json;
+use buffers::{BufferBudget, Reservation};
+use fault::{self, FaultConfig};
+use idempotency::{self, Lookup};
 use response::Response;
+use streaming::{Outputs, WriteOutput};
 use server_utils::cors;
+use server_utils::tokio_core::reactor::{Handle, Timeout};
+
+use {utils, RequestMiddlewareAction, CorsDomains, AllowedHosts, DuplicateBatchIdPolicy, ErrorEnvelope, ErrorStatus, OptionsResponse, RestApi, ServerConfig};
+
+const APPLICATION_MSGPACK: &str = "application/msgpack";
+/// Header disabling compression of the response even if the client accepts gzip.
+const NO_COMPRESSION_HEADER: &str = "X-No-Compression";
+/// Delay (in milliseconds) before retrying to reserve buffer space for a request body.
+const BUFFER_RETRY_DELAY_MS: u64 = 10;
+/// Error code returned for calls of a batch that didn't finish within the batch timeout.
+pub const BATCH_TIMEOUT_ERROR_CODE: i64 = -32001;
+/// Error code returned for calls that didn't finish within the request timeout.
+pub const REQUEST_TIMEOUT_ERROR_CODE: i64 = BATCH_TIMEOUT_ERROR_CODE;
+/// Error code returned instead of responses exceeding the maximal response size.
+pub const RESPONSE_TOO_LARGE_ERROR_CODE: i64 = -32000;
+
+/// Timings of a request passed to `ServerBuilder::request_timings` hook.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RequestTimings {
+	/// Time the request waited for the handler to start executing it.
+	pub queued_duration: Duration,
+	/// Time spent executing the handler.
+	pub handler_duration: Duration,
+}
+
+/// Access log record of a request passed to `RequestLogger`.
+///
+/// Batches produce a single record listing all of their calls.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RequestRecord {
+	/// Methods and ids (`None` for notifications) of the calls, empty if the request wasn't parsed.
+	pub calls: Vec<(String, Option<core::Id>)>,
+	/// HTTP status of the response.
+	pub status: u16,
+	/// Length of the (possibly compressed) request body.
+	pub request_bytes: usize,
+	/// Length of the (possibly compressed) response body.
+	pub response_bytes: usize,
+	/// Address of the client.
+	pub remote_addr: Option<SocketAddr>,
+	/// Time from receiving the request headers until the response was ready.
+	pub elapsed: Duration,
+}
+
+/// Sink of access log records set with `ServerBuilder::request_logger`.
+pub trait RequestLogger: Send + Sync + 'static {
+	/// Logs a handled request.
+	fn log(&self, record: RequestRecord);
+}
+
+/// Tracks the number of requests submitted to the handler pool.
+#[derive(Debug)]
+pub struct PoolSaturation {
+	size: usize,
+	busy: AtomicUsize,
+	retry_after: Duration,
+}
+
+impl PoolSaturation {
+	/// Creates new tracker of a pool with given number of threads.
+	pub fn new(size: usize, retry_after: Duration) -> Self {
+		PoolSaturation {
+			size,
+			busy: AtomicUsize::new(0),
+			retry_after,
+		}
+	}
+
+	fn is_saturated(&self) -> bool {
+		self.busy.load(Ordering::SeqCst) >= self.size
+	}
+}
+
+/// Tracks the number of requests that are currently being processed.
+#[derive(Debug, Default)]
+pub struct InFlight {
+	count: AtomicUsize,
+}
+
+impl InFlight {
+	/// Returns the number of requests in flight.
+	pub fn count(&self) -> usize {
+		self.count.load(Ordering::SeqCst)
+	}
 
-use {utils, RequestMiddleware, RequestMiddlewareAction, CorsDomains, AllowedHosts, RestApi};
+	fn track(in_flight: &Arc<Self>) -> InFlightRequest {
+		in_flight.count.fetch_add(1, Ordering::SeqCst);
+		InFlightRequest(in_flight.clone())
+	}
+}
+
+/// A request counted as in flight until dropped.
+struct InFlightRequest(Arc<InFlight>);
+
+impl Drop for InFlightRequest {
+	fn drop(&mut self) {
+		(self.0).count.fetch_sub(1, Ordering::SeqCst);
+	}
+}
 
 /// jsonrpc http request handler.
 pub struct ServerHandler<M: Metadata = (), S: Middleware<M> = NoopMiddleware> {
-	jsonrpc_handler: Rpc<M, S>,
+	config: Arc<ServerConfig<M, S>>,
 	allowed_hosts: AllowedHosts,
-	cors_domains: CorsDomains,
-	cors_max_age: Option<u32>,
-	middleware: Arc<RequestMiddleware>,
-	rest_api: RestApi,
-	max_request_body_size: usize,
+	closes_at: Option<Instant>,
+	handle: Handle,
 }
 
 impl<M: Metadata, S: Middleware<M>> ServerHandler<M, S> {
 	/// Create new request handler.
-	pub fn new(
-		jsonrpc_handler: Rpc<M, S>,
-		cors_domains: CorsDomains,
-		cors_max_age: Option<u32>,
-		allowed_hosts: AllowedHosts,
-		middleware: Arc<RequestMiddleware>,
-		rest_api: RestApi,
-		max_request_body_size: usize,
-	) -> Self {
+	pub fn new(config: Arc<ServerConfig<M, S>>, allowed_hosts: AllowedHosts, handle: Handle) -> Self {
 		ServerHandler {
-			jsonrpc_handler,
+			closes_at: config.max_connection_age.map(|age| Instant::now() + age),
+			config,
 			allowed_hosts,
-			cors_domains,
-			cors_max_age,
-			middleware,
-			rest_api,
-			max_request_body_size,
+			handle,
 		}
 	}
+
+	/// Responds with given HTTP error, converted to JSON if configured.
+	fn error(&self, response: Response) -> Handler<M, S> {
+		Handler::Error(Some(if self.config.http_errors_as_json { http_error_as_json(response) } else { response }))
+	}
 }
 
 impl<M: Metadata, S: Middleware<M>> server::Service for ServerHandler<M, S> {
@@ -57,7 +161,7 @@ impl<M: Metadata, S: Middleware<M>> server::Service for ServerHandler<M, S> {
 
 	fn call(&self, request: Self::Request) -> Self::Future {
 		let is_host_allowed = utils::is_host_allowed(&request, &self.allowed_hosts);
-		let action = self.middleware.on_request(request);
+		let action = self.config.request_middleware.on_request(request);
 
 		let (should_validate_hosts, should_continue_on_invalid_cors, response) = match action {
 			RequestMiddlewareAction::Proceed { should_continue_on_invalid_cors, request }=> (
@@ -70,25 +174,69 @@ impl<M: Metadata, S: Middleware<M>> server::Service for ServerHandler<M, S> {
 
 		// Validate host
 		if should_validate_hosts && !is_host_allowed {
-			return Handler::Error(Some(Response::host_not_allowed()));
+			return self.error(Response::host_not_allowed());
 		}
 
 		// Replace response with the one returned by middleware.
 		match response {
 			Ok(response) => Handler::Middleware(response),
 			Err(request) => {
+				let is_health_check = *request.method() == Method::Get
+					&& self.config.health_check_path.as_ref().map_or(false, |path| path == request.uri().path());
+				if is_health_check {
+					return Handler::Error(Some(Response::health_check()));
+				}
+
+				// Requests to routes are served by their handlers.
+				let route = match self.config.routes {
+					Some(ref routes) => match routes.get(request.uri().path()) {
+						Some(route) if route.is_authorized(&request) => Some(route),
+						Some(_) => return self.error(Response::route_unauthorized()),
+						None if request.uri().path() == "/" => None,
+						None => return self.error(self.config.not_found_response.clone()),
+					},
+					None => None,
+				};
+				let (jsonrpc_handler, cors_domains) = match route {
+					Some(route) => (route.rpc().clone(), route.cors_domains(&self.config.cors_domains)),
+					// Requests in flight keep using the handler they started with when it's swapped.
+					None => (self.config.jsonrpc_handler.read().expect("Handler lock is never poisoned.").clone(), self.config.cors_domains.clone()),
+				};
+
 				Handler::Rpc(RpcHandler {
-					jsonrpc_handler: self.jsonrpc_handler.clone(),
+					jsonrpc_handler,
 					state: RpcHandlerState::ReadingHeaders {
 						request: request,
-						cors_domains: self.cors_domains.clone(),
+						cors_domains,
 						continue_on_invalid_cors: should_continue_on_invalid_cors,
 					},
 					is_options: false,
+					accepts_msgpack: false,
+					accepts_gzip: false,
+					is_http10_keep_alive: false,
+					error_context: None,
+					idempotency_key: None,
+					idempotency_body_hash: None,
+					request_signature: None,
+					retry_after: None,
+					close_connection: self.closes_at.map_or(false, |closes_at| Instant::now() >= closes_at),
+					content_length: None,
+					buffer_reservation: None,
+					buffer_retry: None,
+					pending_chunk: None,
+					cors_request_headers: None,
+					dispatched_at: None,
+					handler_duration: None,
+					call_methods: None,
+					log_record: None,
+					if_none_match: None,
+					is_cacheable: false,
+					etag: None,
+					outputs: None,
 					cors_header: cors::CorsHeader::NotRequired,
-					rest_api: self.rest_api,
-					cors_max_age: self.cors_max_age,
-					max_request_body_size: self.max_request_body_size,
+					config: self.config.clone(),
+					_in_flight: InFlight::track(&self.config.in_flight),
+					handle: self.handle.clone(),
 				})
 			}
 		}
@@ -147,6 +295,7 @@ enum RpcHandlerState<M, F> where
 		body: hyper::Body,
 		uri: Option<hyper::Uri>,
 		request: Vec<u8>,
+		encoding: header::Encoding,
 		metadata: M,
 	},
 	ProcessRest {
@@ -154,10 +303,20 @@ enum RpcHandlerState<M, F> where
 		metadata: M,
 	},
 	Writing(Response),
-	Waiting(FutureResult<F>),
+	Waiting(WaitingFuture<F>),
+	Streaming {
+		call: Box<Future<Item = core::StreamingCall, Error = ()>>,
+		timeout: Option<Timeout>,
+		id: core::Id,
+		jsonrpc: Option<core::Version>,
+		metadata: M,
+	},
 	Done,
 }
 
+type ResponseFuture = Box<Future<Item = Option<String>, Error = ()>>;
+type WaitingFuture<F> = future::Either<FutureResult<F>, ResponseFuture>;
+
 impl<M, F> fmt::Debug for RpcHandlerState<M, F> where
 	F: Future<Item = Option<core::Response>, Error = ()>,
 {
@@ -170,6 +329,7 @@ impl<M, F> fmt::Debug for RpcHandlerState<M, F> where
 			ProcessRest {..} => write!(fmt, "ProcessRest"),
 			Writing(ref res) => write!(fmt, "Writing({:?})", res),
 			Waiting(_) => write!(fmt, "Waiting"),
+			Streaming {..} => write!(fmt, "Streaming"),
 			Done => write!(fmt, "Done"),
 		}
 	}
@@ -179,10 +339,33 @@ pub struct RpcHandler<M: Metadata, S: Middleware<M>> {
 	jsonrpc_handler: Rpc<M, S>,
 	state: RpcHandlerState<M, S::Future>,
 	is_options: bool,
+	accepts_msgpack: bool,
+	accepts_gzip: bool,
+	is_http10_keep_alive: bool,
+	error_context: Option<core::Value>,
+	idempotency_key: Option<String>,
+	idempotency_body_hash: Option<String>,
+	request_signature: Option<String>,
+	retry_after: Option<Duration>,
+	close_connection: bool,
+	content_length: Option<u64>,
+	buffer_reservation: Option<Reservation>,
+	buffer_retry: Option<Timeout>,
+	/// Chunk of the body waiting for space in the buffer budget.
+	pending_chunk: Option<hyper::Chunk>,
+	cors_request_headers: Option<Vec<Ascii<String>>>,
+	dispatched_at: Option<Instant>,
+	handler_duration: Option<Duration>,
+	call_methods: Option<HashMap<core::Id, String>>,
+	log_record: Option<(Instant, RequestRecord)>,
+	if_none_match: Option<String>,
+	is_cacheable: bool,
+	etag: Option<String>,
+	outputs: Option<Outputs>,
 	cors_header: cors::CorsHeader<header::AccessControlAllowOrigin>,
-	cors_max_age: Option<u32>,
-	rest_api: RestApi,
-	max_request_body_size: usize,
+	config: Arc<ServerConfig<M, S>>,
+	_in_flight: InFlightRequest,
+	handle: Handle,
 }
 
 impl<M: Metadata, S: Middleware<M>> Future for RpcHandler<M, S> {
@@ -193,56 +376,252 @@ impl<M: Metadata, S: Middleware<M>> Future for RpcHandler<M, S> {
 		let new_state = match mem::replace(&mut self.state, RpcHandlerState::Done) {
 			RpcHandlerState::ReadingHeaders { request, cors_domains, continue_on_invalid_cors, } => {
 				// Read cors header
-				self.cors_header = utils::cors_header(&request, &cors_domains);
+				self.cors_header = utils::cors_header(&request, &cors_domains, &self.config.trusted_proxies);
 				self.is_options = *request.method() == Method::Options;
-				// Read other headers
-				RpcPollState::Ready(self.read_headers(request, continue_on_invalid_cors))
+				if self.is_options {
+					self.cors_request_headers = request.headers().get::<header::AccessControlRequestHeaders>()
+						.map(|headers| headers.0.clone());
+				}
+				self.accepts_msgpack = Self::accepts_msgpack(request.headers().get::<header::Accept>());
+				self.accepts_gzip = Self::accepts_gzip(request.headers().get::<header::AcceptEncoding>())
+					&& !Self::opts_out_of_compression(&request);
+				self.error_context = self.jsonrpc_handler.extractor.read_error_context(&request);
+				self.is_http10_keep_alive = self.config.keep_alive && Self::is_http10_keep_alive(&request);
+				if self.config.idempotency.is_some() && *request.method() == Method::Post {
+					self.idempotency_key = Self::idempotency_key(&request);
+				}
+				if let Some(ref signature) = self.config.signature {
+					self.request_signature = utils::read_header(&request, signature.header()).map(Into::into);
+				}
+				if self.config.buffers.is_some() {
+					self.content_length = request.headers().get::<header::ContentLength>().map(|length| length.0);
+				}
+				if !self.config.cacheable_methods.is_empty() {
+					self.if_none_match = utils::read_header(&request, "If-None-Match").map(Into::into);
+				}
+				if self.config.request_logger.is_some() {
+					self.log_record = Some((Instant::now(), RequestRecord {
+						calls: Vec::new(),
+						status: 0,
+						request_bytes: 0,
+						response_bytes: 0,
+						remote_addr: request.remote_addr(),
+						elapsed: Duration::from_secs(0),
+					}));
+				}
+				// Reject the request right away if there is no thread to execute it.
+				let retry_after = match self.config.saturation {
+					Some(ref saturation) if *request.method() == Method::Post && saturation.is_saturated() => {
+						Some(saturation.retry_after)
+					},
+					_ => None,
+				};
+				if retry_after.is_some() {
+					self.retry_after = retry_after;
+					RpcPollState::Ready(RpcHandlerState::Writing(Response::service_unavailable()))
+				} else {
+					// Read other headers
+					RpcPollState::Ready(self.read_headers(request, continue_on_invalid_cors))
+				}
 			},
-			RpcHandlerState::ReadingBody { body, request, metadata, uri, } => {
-				match self.process_body(body, request, uri, metadata) {
-					Err(BodyError::Utf8(ref e)) => {
-						let mesg = format!("utf-8 encoding error at byte {} in request body", e.valid_up_to());
-						let resp = Response::bad_request(mesg);
-						RpcPollState::Ready(RpcHandlerState::Writing(resp))
-					}
+			RpcHandlerState::ReadingBody { body, request, encoding, metadata, uri, } => {
+				match self.reserve_buffer() {
 					Err(BodyError::TooLarge) => {
-						let resp = Response::too_large("request body size exceeds allowed maximum");
+						let resp = Response::request_too_large();
 						RpcPollState::Ready(RpcHandlerState::Writing(resp))
-					}
-					Err(BodyError::Hyper(e)) => return Err(e),
-					Ok(state) => state,
+					},
+					Err(_) => RpcPollState::Ready(RpcHandlerState::Writing(Response::internal_error())),
+					Ok(Async::NotReady) => RpcPollState::NotReady(RpcHandlerState::ReadingBody {
+						body,
+						request,
+						encoding,
+						metadata,
+						uri,
+					}),
+					Ok(Async::Ready(())) => {
+						let state = match self.process_body(body, request, encoding, uri, metadata) {
+							Err(BodyError::Decompression(ref e)) => {
+								let mesg = format!("unable to decompress request body: {}", e);
+								let resp = Response::bad_request(mesg);
+								RpcPollState::Ready(RpcHandlerState::Writing(resp))
+							}
+							Err(BodyError::Utf8(ref e)) => {
+								let message = format!("Invalid UTF-8 sequence at byte {} in request body", e.valid_up_to());
+								RpcPollState::Ready(RpcHandlerState::Writing(parse_error(message, self.config.error_status)))
+							}
+							Err(BodyError::NotJson) => {
+								// The rest of the body is never read, so the connection can't be reused.
+								self.close_connection = true;
+								let message = "Request body is not a JSON object or array".to_owned();
+								RpcPollState::Ready(RpcHandlerState::Writing(parse_error(message, self.config.error_status)))
+							}
+							Err(BodyError::InvalidSignature) => {
+								RpcPollState::Ready(RpcHandlerState::Writing(Response::unauthorized()))
+							}
+							Err(BodyError::TooLarge) => {
+								let resp = Response::request_too_large();
+								RpcPollState::Ready(RpcHandlerState::Writing(resp))
+							}
+							Err(BodyError::Timer) => {
+								RpcPollState::Ready(RpcHandlerState::Writing(Response::internal_error()))
+							}
+							Err(BodyError::Hyper(e)) => return Err(e),
+							Ok(state) => state,
+						};
+						// The body is not buffered anymore once it's been read.
+						if let RpcPollState::Ready(_) = state {
+							self.buffer_reservation = None;
+						}
+						state
+					},
 				}
 			},
 			RpcHandlerState::ProcessRest { uri, metadata } => {
 				self.process_rest(uri, metadata)?
 			},
 			RpcHandlerState::Waiting(mut waiting) => {
+				if self.dispatched_at.is_none() {
+					self.dispatched_at = Some(Instant::now());
+				}
 				match waiting.poll() {
 					Ok(Async::Ready(response)) => {
-						RpcPollState::Ready(RpcHandlerState::Writing(match response {
-							// Notification, just return empty response.
-							None => Response::ok(String::new()),
-							// Add new line to have nice output when using CLI clients (curl)
-							Some(result) => Response::ok(format!("{}\n", result)),
-						}.into()))
+						self.handler_duration = self.dispatched_at.map(|dispatched_at| dispatched_at.elapsed());
+						let success_flag = self.config.error_envelope == ErrorEnvelope::SuccessFlag;
+						let call_methods = self.call_methods.take();
+						let response = if success_flag || call_methods.is_some() {
+							response.map(|response| with_output_fields(&response, |output| {
+								let mut fields = Vec::new();
+								if success_flag {
+									let success = match *output {
+										core::Output::Success(_) => true,
+										core::Output::Failure(_) => false,
+									};
+									fields.push(("success", core::Value::Bool(success)));
+								}
+								if let Some(method) = call_methods.as_ref().and_then(|methods| methods.get(output.id())) {
+									fields.push(("method", core::Value::String(method.clone())));
+								}
+								fields
+							}))
+						} else {
+							response
+						};
+						if self.is_cacheable {
+							self.etag = response.as_ref().and_then(|response| response_etag(response));
+						}
+						let is_not_modified = match (self.etag.as_ref(), self.if_none_match.as_ref()) {
+							(Some(etag), Some(if_none_match)) => etag_matches(if_none_match, etag),
+							_ => false,
+						};
+						if let (Some(key), Some(body_hash), Some(cache)) = (self.idempotency_key.take(), self.idempotency_body_hash.take(), self.config.idempotency.as_ref()) {
+							cache.lock().expect("Idempotency cache lock is never poisoned.").insert(key, body_hash, response.clone());
+						}
+						if is_not_modified {
+							RpcPollState::Ready(RpcHandlerState::Writing(Response::not_modified()))
+						} else {
+							let status = response.as_ref().and_then(|response| error_status(response, self.config.error_status));
+							let mut response = rpc_response(response);
+							if let Some(status) = status {
+								response.code = status;
+							}
+							RpcPollState::Ready(RpcHandlerState::Writing(response))
+						}
 					},
 					Ok(Async::NotReady) => RpcPollState::NotReady(RpcHandlerState::Waiting(waiting)),
 					Err(_) => RpcPollState::Ready(RpcHandlerState::Writing(Response::internal_error())),
 				}
 			},
+			RpcHandlerState::Streaming { mut call, mut timeout, id, jsonrpc, metadata } => {
+				match call.poll() {
+					Ok(Async::Ready(core::StreamingCall::Results(results))) => {
+						// HTTP/1.0 connection can't be reused, since the length of the response is unknown.
+						self.close_connection |= self.is_http10_keep_alive;
+						let outputs = Outputs::new(results, id, jsonrpc, self.output_writer(metadata));
+						self.outputs = Some(match timeout {
+							Some(timeout) => outputs.with_timeout(timeout, timeout_error()),
+							None => outputs,
+						});
+						RpcPollState::Ready(RpcHandlerState::Writing(Response::ok(String::new())))
+					},
+					Ok(Async::Ready(core::StreamingCall::Response(response))) => {
+						RpcPollState::Ready(RpcHandlerState::Writing(self.streaming_call_response(response, metadata)))
+					},
+					Ok(Async::NotReady) => {
+						let timed_out = match timeout.as_mut().map(Future::poll) {
+							Some(Ok(Async::NotReady)) | None => false,
+							Some(Ok(Async::Ready(()))) | Some(Err(_)) => true,
+						};
+						if timed_out {
+							let output = core::Output::from(Err(timeout_error()), id, jsonrpc);
+							let response = self.streaming_call_response(Some(core::Response::Single(output)), metadata);
+							RpcPollState::Ready(RpcHandlerState::Writing(response))
+						} else {
+							RpcPollState::NotReady(RpcHandlerState::Streaming { call, timeout, id, jsonrpc, metadata })
+						}
+					},
+					Err(_) => RpcPollState::Ready(RpcHandlerState::Writing(Response::internal_error())),
+				}
+			},
 			state => RpcPollState::NotReady(state),
 		};
 
 		let (new_state, is_ready) = new_state.decompose();
 		match new_state {
 			RpcHandlerState::Writing(res) => {
-				let mut response: server::Response = res.into();
+				let mut res = if self.config.http_errors_as_json { http_error_as_json(res) } else { res };
+				if self.config.ascii_only_output && res.content_type == header::ContentType::json() {
+					res.content = escape_non_ascii(&res.content);
+				}
+				let code = res.code;
+				let (content_type, body) = if self.accepts_msgpack {
+					Self::into_msgpack(res)
+				} else {
+					(res.content_type, res.content.into_bytes())
+				};
+				let mut response = server::Response::new()
+					.with_status(code)
+					.with_header(content_type);
+				let outputs = self.outputs.take();
+				let body = if self.accepts_gzip && outputs.is_none() && self.config.compression.should_compress(body.len()) {
+					response.headers_mut().set(header::ContentEncoding(vec![header::Encoding::Gzip]));
+					Self::gzip(&body)
+				} else {
+					body
+				};
+				if let Some(retry_after) = self.retry_after {
+					response.headers_mut().set_raw("Retry-After", retry_after.as_secs().to_string());
+				}
+				if let (true, Some(duration)) = (self.config.server_timing, self.handler_duration) {
+					response.headers_mut().set_raw("Server-Timing", server_timing(duration));
+				}
+				if let Some(etag) = self.etag.take() {
+					response.headers_mut().set_raw("ETag", etag);
+				}
+				// HTTP/1.0 connection can only be reused if the length of the response is known.
+				if self.close_connection {
+					response.headers_mut().set(header::Connection::close());
+				} else if self.is_http10_keep_alive {
+					response.headers_mut().set(header::ContentLength(body.len() as u64));
+					response.headers_mut().set(header::Connection::keep_alive());
+				}
+				if let (Some(logger), Some((received_at, mut record))) = (self.config.request_logger.as_ref(), self.log_record.take()) {
+					record.status = code.as_u16();
+					record.response_bytes = body.len();
+					record.elapsed = received_at.elapsed();
+					logger.log(record);
+				}
+				response.set_body(match outputs {
+					Some(outputs) => self.streamed_body(outputs),
+					None => self.chunked_body(body),
+				});
 				let cors_header = mem::replace(&mut self.cors_header, cors::CorsHeader::Invalid);
+				let cors_allow_headers = self.cors_allow_headers();
 				Self::set_response_headers(
 					response.headers_mut(),
 					self.is_options,
 					cors_header.into(),
-					self.cors_max_age,
+					cors_allow_headers,
+					self.config.cors_max_age,
 				);
 				Ok(Async::Ready(response))
 			},
@@ -262,8 +641,12 @@ impl<M: Metadata, S: Middleware<M>> Future for RpcHandler<M, S> {
 // error cases occuring during request body processing.
 enum BodyError {
 	Hyper(hyper::Error),
+	Decompression(io::Error),
 	Utf8(str::Utf8Error),
+	NotJson,
+	InvalidSignature,
 	TooLarge,
+	Timer,
 }
 
 impl From<hyper::Error> for BodyError {
@@ -274,7 +657,7 @@ impl From<hyper::Error> for BodyError {
 
 impl<M: Metadata, S: Middleware<M>> RpcHandler<M, S> {
 	fn read_headers(
-		&self,
+		&mut self,
 		request: server::Request,
 		continue_on_invalid_cors: bool,
 	) -> RpcHandlerState<M, S::Future> {
@@ -282,22 +665,41 @@ impl<M: Metadata, S: Middleware<M>> RpcHandler<M, S> {
 			return RpcHandlerState::Writing(Response::invalid_cors());
 		}
 		// Read metadata
-		let metadata = self.jsonrpc_handler.extractor.read_metadata(&request);
+		let metadata = match self.config.path_version {
+			Some((ref prefix, ref versions)) => match utils::path_version(request.uri().path(), prefix) {
+				Some(version) if versions.contains(&version) => {
+					self.jsonrpc_handler.extractor.read_versioned_metadata(&request, version)
+				},
+				_ => return RpcHandlerState::Writing(self.config.not_found_response.clone()),
+			},
+			None => self.jsonrpc_handler.extractor.read_metadata(&request),
+		};
 
 		// Proceed
 		match *request.method() {
 			// Validate the ContentType header
 			// to prevent Cross-Origin XHRs with text/plain
 			Method::Post if Self::is_json(request.headers().get::<header::ContentType>()) => {
-				let uri = if self.rest_api != RestApi::Disabled { Some(request.uri().clone()) } else { None };
+				let encoding = match Self::content_encoding(request.headers().get::<header::ContentEncoding>()) {
+					Some(encoding) => encoding,
+					None => return RpcHandlerState::Writing(Response::unsupported_content_encoding()),
+				};
+				// Reject declared oversized bodies without reading them.
+				let content_length = request.headers().get::<header::ContentLength>().map(|length| length.0);
+				if content_length.map_or(false, |length| length > self.config.max_request_body_size as u64) {
+					self.close_connection = true;
+					return RpcHandlerState::Writing(Response::request_too_large());
+				}
+				let uri = if self.config.rest_api != RestApi::Disabled { Some(request.uri().clone()) } else { None };
 				RpcHandlerState::ReadingBody {
 					metadata,
 					request: Default::default(),
+					encoding,
 					uri,
 					body: request.body(),
 				}
 			},
-			Method::Post if self.rest_api == RestApi::Unsecure && request.uri().path().split('/').count() > 2 => {
+			Method::Post if self.config.rest_api == RestApi::Unsecure && request.uri().path().split('/').count() > 2 => {
 				RpcHandlerState::ProcessRest {
 					metadata,
 					uri: request.uri().clone(),
@@ -307,9 +709,18 @@ impl<M: Metadata, S: Middleware<M>> RpcHandler<M, S> {
 			Method::Post => {
 				RpcHandlerState::Writing(Response::unsupported_content_type())
 			},
+			// Advertise available methods if requested
+			Method::Options if self.config.advertise_methods && Self::is_discovery(request.uri()) => {
+				let methods = serde_json::to_string(&self.jsonrpc_handler.handler.methods()).expect("Serialization of strings is infallible; qed");
+				RpcHandlerState::Writing(Response::ok(format!("{}\n", methods)))
+			},
 			// Don't validate content type on options
 			Method::Options => {
-				RpcHandlerState::Writing(Response::empty())
+				RpcHandlerState::Writing(match self.config.options_response {
+					OptionsResponse::Ok => Response::empty(),
+					OptionsResponse::NoContent => Response::no_content(),
+					OptionsResponse::Custom(ref response) => response.clone(),
+				})
 			},
 			// Disallow other methods.
 			_ => {
@@ -319,7 +730,7 @@ impl<M: Metadata, S: Middleware<M>> RpcHandler<M, S> {
 	}
 
 	fn process_rest(
-		&self,
+		&mut self,
 		uri: hyper::Uri,
 		metadata: M,
 	) -> Result<RpcPollState<M, S::Future>, hyper::Error> {
@@ -345,31 +756,146 @@ impl<M: Metadata, S: Middleware<M>> RpcHandler<M, S> {
 			params: Params::Array(params),
 			id: Id::Num(1),
 		}));
+		if let Some((_, ref mut record)) = self.log_record {
+			record.calls = logged_calls(&call);
+		}
 
-		return Ok(RpcPollState::Ready(RpcHandlerState::Waiting(
-			future::Either::B(self.jsonrpc_handler.handler.handle_rpc_request(call, metadata))
-				.map(|res| res.map(|x| serde_json::to_string(&x)
-					.expect("Serialization of response is infallible;qed")
-				))
-		)));
+		if let Some(response) = self.maintenance_response(&call) {
+			return Ok(RpcPollState::Ready(RpcHandlerState::Waiting(future::Either::B(
+				Box::new(future::ok(write_response(response)))
+			))));
+		}
+
+		return Ok(RpcPollState::Ready(RpcHandlerState::Waiting(self.dispatch(call, metadata))));
+	}
+
+	/// Reserves space for the request body in the buffer budget shared by all connections (if any).
+	///
+	/// Bodies of unknown length start with an empty reservation grown as their chunks arrive.
+	/// Returns `NotReady` (and schedules a retry) if the body has to wait for other requests
+	/// to release their buffers and `TooLarge` if the body would never fit.
+	fn reserve_buffer(&mut self) -> Poll<(), BodyError> {
+		let budget = match self.config.buffers {
+			Some(ref budget) if self.buffer_reservation.is_none() => budget.clone(),
+			_ => return Ok(Async::Ready(())),
+		};
+		let size = match self.content_length {
+			Some(length) if length > budget.limit() as u64 => return Err(BodyError::TooLarge),
+			Some(length) => cmp::min(length as usize, self.config.max_request_body_size),
+			None => 0,
+		};
+
+		loop {
+			if let Some(reservation) = BufferBudget::reserve(&budget, size) {
+				self.buffer_retry = None;
+				self.buffer_reservation = Some(reservation);
+				return Ok(Async::Ready(()));
+			}
+
+			if let Async::NotReady = self.wait_for_buffers()? {
+				return Ok(Async::NotReady);
+			}
+		}
+	}
+
+	/// Grows the buffer reservation (if any) to hold `size` bytes of the body.
+	///
+	/// Returns `NotReady` (and schedules a retry) if the body has to wait for other requests
+	/// to release their buffers and `TooLarge` if the body would never fit.
+	fn grow_buffer(&mut self, size: usize) -> Poll<(), BodyError> {
+		let limit = self.config.buffers.as_ref().map_or(usize::max_value(), |budget| budget.limit());
+		loop {
+			let missing = match self.buffer_reservation {
+				Some(ref reservation) if reservation.bytes() < size => size - reservation.bytes(),
+				_ => return Ok(Async::Ready(())),
+			};
+			if size > limit {
+				return Err(BodyError::TooLarge);
+			}
+			if self.buffer_reservation.as_mut().map_or(true, |reservation| reservation.grow(missing)) {
+				self.buffer_retry = None;
+				return Ok(Async::Ready(()));
+			}
+
+			if let Async::NotReady = self.wait_for_buffers()? {
+				return Ok(Async::NotReady);
+			}
+		}
+	}
+
+	/// Waits a while for other requests to release their buffers.
+	///
+	/// Returns `Ready` once the delay elapsed and the reservation should be retried.
+	fn wait_for_buffers(&mut self) -> Poll<(), BodyError> {
+		let mut retry = match self.buffer_retry.take() {
+			Some(retry) => retry,
+			None => Timeout::new(Duration::from_millis(BUFFER_RETRY_DELAY_MS), &self.handle).map_err(|err| {
+				warn!("Unable to set up buffer reservation retry: {:?}", err);
+				BodyError::Timer
+			})?,
+		};
+		match retry.poll() {
+			Ok(Async::NotReady) => {
+				self.buffer_retry = Some(retry);
+				Ok(Async::NotReady)
+			},
+			Ok(Async::Ready(())) => Ok(Async::Ready(())),
+			Err(err) => {
+				warn!("Buffer reservation retry failed: {:?}", err);
+				Err(BodyError::Timer)
+			},
+		}
 	}
 
 	fn process_body(
-		&self,
+		&mut self,
 		mut body: hyper::Body,
 		mut request: Vec<u8>,
+		encoding: header::Encoding,
 		uri: Option<hyper::Uri>,
 		metadata: M,
 	) -> Result<RpcPollState<M, S::Future>, BodyError> {
 		loop {
-			match body.poll()? {
+			// A chunk waiting for space in the buffer budget is processed before reading any further.
+			let polled = match self.pending_chunk.take() {
+				Some(chunk) => Async::Ready(Some(chunk)),
+				None => body.poll()?,
+			};
+			match polled {
 				Async::Ready(Some(chunk)) => {
-					if request.len().checked_add(chunk.len()).map(|n| n > self.max_request_body_size).unwrap_or(true) {
+					let max_size = self.config.max_request_body_size;
+					if request.len().checked_add(chunk.len()).map(|n| n > max_size).unwrap_or(true) {
 						return Err(BodyError::TooLarge)
 					}
+					// Reject bodies that clearly aren't JSON without waiting for the rest of them.
+					if encoding == header::Encoding::Identity && !may_be_json(&request, &*chunk) {
+						return Err(BodyError::NotJson)
+					}
+					if let Async::NotReady = self.grow_buffer(request.len() + chunk.len())? {
+						self.pending_chunk = Some(chunk);
+						break;
+					}
 					request.extend_from_slice(&*chunk)
 				},
 				Async::Ready(None) => {
+					if let Some(ref signature) = self.config.signature {
+						let is_valid = self.request_signature.as_ref().map_or(false, |sig| signature.verify(&request, sig));
+						if !is_valid {
+							return Err(BodyError::InvalidSignature);
+						}
+					}
+
+					// Return cached response if the request was already processed.
+					if let (Some(key), Some(cache)) = (self.idempotency_key.as_ref(), self.config.idempotency.as_ref()) {
+						let body_hash = idempotency::body_hash(&request);
+						match cache.lock().expect("Idempotency cache lock is never poisoned.").get(key, &body_hash) {
+							Lookup::Hit(response) => return Ok(RpcPollState::Ready(RpcHandlerState::Writing(rpc_response(response)))),
+							Lookup::Conflict => return Ok(RpcPollState::Ready(RpcHandlerState::Writing(Response::idempotency_key_reused()))),
+							Lookup::Miss => {},
+						}
+						self.idempotency_body_hash = Some(body_hash);
+					}
+
 					if let (Some(uri), true) = (uri, request.is_empty()) {
 						return Ok(RpcPollState::Ready(RpcHandlerState::ProcessRest {
 							uri,
@@ -377,6 +903,10 @@ impl<M: Metadata, S: Middleware<M>> RpcHandler<M, S> {
 						}));
 					}
 
+					if let Some((_, ref mut record)) = self.log_record {
+						record.request_bytes = request.len();
+					}
+					let request = self.decompress(request, &encoding)?;
 					let content = match str::from_utf8(&request) {
 						Ok(content) => content,
 						Err(err) => {
@@ -385,27 +915,379 @@ impl<M: Metadata, S: Middleware<M>> RpcHandler<M, S> {
 						},
 					};
 
+					// Every request is parsed once here and dispatched the same way regardless of configuration,
+					// only malformed requests are passed as-is to the handler to get the parse error.
+					let request = match serde_json::from_str::<core::Request>(content) {
+						Ok(request) => request,
+						Err(_) => return Ok(RpcPollState::Ready(RpcHandlerState::Waiting(future::Either::A(
+							self.jsonrpc_handler.handler.handle_request(content, metadata)
+						)))),
+					};
+
+					if self.config.include_method_in_response {
+						self.call_methods = Some(call_methods(&request));
+					}
+					if let Some((_, ref mut record)) = self.log_record {
+						record.calls = logged_calls(&request);
+					}
+					if let core::Request::Single(core::Call::MethodCall(ref call)) = request {
+						self.is_cacheable = self.config.cacheable_methods.contains(&call.method);
+					}
+
+					// Reject the request early if we are in maintenance mode.
+					if let Some(response) = self.maintenance_response(&request) {
+						return Ok(RpcPollState::Ready(RpcHandlerState::Waiting(future::Either::B(
+							Box::new(future::ok(write_response(response)))
+						))));
+					}
+
+					// Fail the request if requested by fault injection.
+					if self.config.fault_injection.as_ref().map_or(false, FaultConfig::should_fail) {
+						return Ok(RpcPollState::Ready(RpcHandlerState::Waiting(future::Either::B(
+							Box::new(future::ok(write_response(reject_request(&request, fault::injected_error()))))
+						))));
+					}
+
+					// Reject batches with ambiguous ids if configured.
+					if self.config.duplicate_batch_id_policy == DuplicateBatchIdPolicy::Reject && has_duplicate_ids(&request) {
+						let response = core::Response::from(core::Error::invalid_request(), Some(core::Version::V2));
+						return Ok(RpcPollState::Ready(RpcHandlerState::Waiting(future::Either::B(
+							Box::new(future::ok(write_response(Some(response))))
+						))));
+					}
+
+					let request = if self.config.strict { Self::strict_request(request) } else { request };
+
+					// Fail calls of methods with tripped circuit breaker right away.
+					let has_tripped_calls = match request {
+						core::Request::Single(ref call) => if let Some(error) = self.tripped_error(call) {
+							return Ok(RpcPollState::Ready(RpcHandlerState::Waiting(future::Either::B(
+								Box::new(future::ok(write_response(reject_request(&request, error))))
+							))));
+						} else {
+							false
+						},
+						core::Request::Batch(ref calls) => calls.iter().any(|call| self.tripped_error(call).is_some()),
+					};
+
+					// Results of streaming methods are written as they come, once the call passed the middleware.
+					let is_streaming = match request {
+						core::Request::Single(core::Call::MethodCall(ref call)) => {
+							self.jsonrpc_handler.handler.streaming_method(&call.method).is_some()
+						},
+						_ => false,
+					};
+					let request = match request {
+						core::Request::Single(core::Call::MethodCall(call)) if is_streaming => {
+							let (id, jsonrpc) = (call.id.clone(), call.jsonrpc);
+							let call = self.spawn(self.jsonrpc_handler.handler.handle_streaming_call(call, metadata.clone()));
+							return Ok(RpcPollState::Ready(RpcHandlerState::Streaming {
+								call,
+								timeout: self.request_timer(),
+								id,
+								jsonrpc,
+								metadata,
+							}));
+						},
+						request => request,
+					};
+
+					// Batches are processed call-by-call if a timeout is configured, order is not preserved
+					// or some of the calls are rejected by circuit breakers.
+					let request = match request {
+						core::Request::Batch(calls) if self.config.batch_timeout.is_some() || self.config.request_timeout.is_some()
+							|| !self.config.batch_ordered || has_tripped_calls => {
+							let response = future::Either::B(self.process_batch(calls, metadata));
+							return Ok(RpcPollState::Ready(RpcHandlerState::Waiting(self.with_injected_delay(response))));
+						},
+						request => request,
+					};
+
 					// Content is ready
-					return Ok(RpcPollState::Ready(RpcHandlerState::Waiting(
-						self.jsonrpc_handler.handler.handle_request(content, metadata)
-					)));
-				},
-				Async::NotReady => {
-					return Ok(RpcPollState::NotReady(RpcHandlerState::ReadingBody {
-						body,
-						request,
-						metadata,
-						uri,
-					}));
+					let timeout_response = self.config.request_timeout.map(|_| write_response(reject_request(&request, timeout_error())));
+					let response = self.with_injected_delay(self.dispatch(request, metadata));
+					return Ok(RpcPollState::Ready(RpcHandlerState::Waiting(self.with_timeout(response, timeout_response))));
 				},
+				Async::NotReady => break,
 			}
 		}
+
+		Ok(RpcPollState::NotReady(RpcHandlerState::ReadingBody {
+			body,
+			request,
+			encoding,
+			metadata,
+			uri,
+		}))
+	}
+
+	/// Replaces calls that don't comply with JSON-RPC 2.0 specification with invalid ones.
+	///
+	/// Deviations already rejected by the request parser (unknown fields, invalid `id`, etc.)
+	/// are not checked again.
+	fn strict_request(request: core::Request) -> core::Request {
+		use self::core::types::{Call, Id, Request, Version};
+
+		let strict_call = |call: Call| match call {
+			Call::MethodCall(ref call) if call.jsonrpc != Some(Version::V2) => Call::Invalid { id: call.id.clone() },
+			Call::Notification(ref notification) if notification.jsonrpc != Some(Version::V2) => {
+				Call::Invalid { id: Id::Null }
+			},
+			call => call,
+		};
+
+		match request {
+			Request::Single(call) => Request::Single(strict_call(call)),
+			// Empty batch is not a valid request.
+			Request::Batch(ref calls) if calls.is_empty() => Request::Single(Call::Invalid { id: Id::Null }),
+			Request::Batch(calls) => Request::Batch(calls.into_iter().map(strict_call).collect()),
+		}
+	}
+
+	/// Processes each call of the batch separately, replacing outputs of calls
+	/// that didn't finish within batch or request timeout (if configured) with a timeout error.
+	/// Calls of methods with tripped circuit breakers fail with the error of the breaker.
+	/// Outputs are returned in completion order unless the batch is ordered.
+	fn process_batch(&self, calls: Vec<core::Call>, metadata: M) -> ResponseFuture {
+		use self::core::types::{Call, Output, Request, Response};
+
+		let outputs: Vec<_> = calls.into_iter().enumerate().map(|(index, call)| {
+			let is_invalid = match call {
+				Call::Invalid { .. } => true,
+				_ => false,
+			};
+			let timeout_output = match call {
+				Call::MethodCall(ref call) => Some(Output::from(Err(timeout_error()), call.id.clone(), call.jsonrpc)),
+				_ => None,
+			};
+			let output: Box<Future<Item = Option<Output>, Error = ()>> = match self.tripped_error(&call) {
+				Some(error) => Box::new(future::ok(match call {
+					Call::MethodCall(ref call) => Some(Output::from(Err(error), call.id.clone(), call.jsonrpc)),
+					_ => None,
+				})),
+				None => Box::new(self.spawn(self.jsonrpc_handler.handler.handle_rpc_request(Request::Single(call), metadata.clone()))
+					.map(move |response| match response {
+						Some(Response::Single(output)) if is_invalid => Some(output.with_batch_index(index)),
+						Some(Response::Single(output)) => Some(output),
+						_ => None,
+					})),
+			};
+
+			let timeout = match (self.config.batch_timeout, self.config.request_timeout) {
+				(Some(batch_timeout), Some(request_timeout)) => Some(cmp::min(batch_timeout, request_timeout)),
+				(batch_timeout, request_timeout) => batch_timeout.or(request_timeout),
+			};
+			let timer = timeout.and_then(|timeout| Timeout::new(timeout, &self.handle)
+				.map_err(|err| warn!("Unable to set up batch timeout: {:?}", err))
+				.ok()
+			);
+			match timer {
+				Some(timer) => future::Either::A(output.select2(timer).then(move |result| match result {
+					Ok(future::Either::A((output, _))) => Ok(output),
+					Ok(future::Either::B(_)) | Err(future::Either::B(_)) => Ok(timeout_output),
+					Err(future::Either::A(_)) => Err(()),
+				})),
+				None => future::Either::B(output),
+			}
+		}).collect();
+		let outputs = if self.config.batch_ordered {
+			future::Either::A(future::join_all(outputs))
+		} else {
+			future::Either::B(stream::futures_unordered(outputs).collect())
+		};
+
+		let error_context = self.error_context.clone();
+		let error_rewriter = self.config.error_rewriter.clone();
+		let max_response_size = self.config.max_response_size;
+		Box::new(outputs.map(move |outputs| {
+			let outputs: Vec<_> = outputs.into_iter().filter_map(|v| v).collect();
+			if outputs.is_empty() {
+				None
+			} else {
+				let response = match error_rewriter {
+					Some(ref rewriter) => rewrite_errors(Response::Batch(outputs), |error| rewriter(&metadata, error)),
+					None => Response::Batch(outputs),
+				};
+				write_limited_response(Some(match error_context {
+					Some(ref context) => with_error_context(response, context),
+					None => response,
+				}), max_response_size)
+			}
+		}))
+	}
+
+	/// Passes parsed request to the handler.
+	fn dispatch(&self, request: core::Request, metadata: M) -> WaitingFuture<S::Future> {
+		let error_rewriter = self.config.error_rewriter.clone().map(|rewriter| (rewriter, metadata.clone()));
+		let response = self.jsonrpc_handler.handler.handle_rpc_request(request, metadata);
+		if self.config.handler_pool.is_none() && self.error_context.is_none() && self.config.max_response_size.is_none()
+			&& self.config.timings_hook.is_none() && error_rewriter.is_none() {
+			return future::Either::A(future::Either::B(response).map(write_response));
+		}
+
+		let error_context = self.error_context.clone();
+		let max_response_size = self.config.max_response_size;
+		future::Either::B(Box::new(self.spawn(response).map(move |response| {
+			let response = match error_rewriter {
+				Some((ref rewriter, ref metadata)) => response.map(|response| {
+					rewrite_errors(response, |error| rewriter(metadata, error))
+				}),
+				None => response,
+			};
+			write_limited_response(match error_context {
+				Some(ref context) => response.map(|response| with_error_context(response, context)),
+				None => response,
+			}, max_response_size)
+		})))
+	}
+
+	/// Delays the response if requested by fault injection.
+	fn with_injected_delay(&self, response: WaitingFuture<S::Future>) -> WaitingFuture<S::Future> {
+		let delay = match self.config.fault_injection.as_ref().and_then(FaultConfig::delay) {
+			Some(delay) => delay,
+			None => return response,
+		};
+
+		match Timeout::new(delay, &self.handle) {
+			Ok(timer) => future::Either::B(Box::new(timer.then(move |_| response))),
+			Err(err) => {
+				warn!("Unable to set up injected delay: {:?}", err);
+				response
+			},
+		}
+	}
+
+	/// Replaces the response with `timeout_response` if it's not ready within the request timeout.
+	fn with_timeout(&self, response: WaitingFuture<S::Future>, timeout_response: Option<Option<String>>) -> WaitingFuture<S::Future> {
+		let (timeout, timeout_response) = match (self.config.request_timeout, timeout_response) {
+			(Some(timeout), Some(timeout_response)) => (timeout, timeout_response),
+			_ => return response,
+		};
+
+		match Timeout::new(timeout, &self.handle) {
+			Ok(timer) => future::Either::B(Box::new(response.select2(timer).then(move |result| match result {
+				Ok(future::Either::A((response, _))) => Ok(response),
+				Ok(future::Either::B(_)) | Err(future::Either::B(_)) => Ok(timeout_response),
+				Err(future::Either::A((err, _))) => Err(err),
+			}))),
+			Err(err) => {
+				warn!("Unable to set up request timeout: {:?}", err);
+				response
+			},
+		}
+	}
+
+	/// Returns a timer firing after the request timeout (if configured).
+	fn request_timer(&self) -> Option<Timeout> {
+		let timeout = self.config.request_timeout?;
+		match Timeout::new(timeout, &self.handle) {
+			Ok(timer) => Some(timer),
+			Err(err) => {
+				warn!("Unable to set up request timeout: {:?}", err);
+				None
+			},
+		}
+	}
+
+	/// Writes the response to a call of a streaming method that didn't stream any results
+	/// (e.g. because it was rejected by the middleware or timed out).
+	fn streaming_call_response(&self, response: Option<core::Response>, metadata: M) -> Response {
+		let content = match response {
+			Some(core::Response::Single(output)) => Some((self.output_writer(metadata))(output)),
+			response => write_response(response),
+		};
+		let status = content.as_ref().and_then(|content| error_status(content, self.config.error_status));
+		let mut response = rpc_response(content);
+		if let Some(status) = status {
+			response.code = status;
+		}
+		response
+	}
+
+	/// Runs given future on the handler pool (if configured), reporting its timings to the hook (if any).
+	fn spawn<F, T>(&self, task: F) -> Box<Future<Item = T, Error = ()>> where
+		F: Future<Item = T, Error = ()> + Send + 'static,
+		T: Send + 'static,
+	{
+		let task: Box<Future<Item = T, Error = ()> + Send> = match self.config.timings_hook.clone() {
+			Some(hook) => {
+				let queued_at = Instant::now();
+				Box::new(future::lazy(move || {
+					let started_at = Instant::now();
+					task.then(move |result| {
+						hook(&RequestTimings {
+							queued_duration: started_at.duration_since(queued_at),
+							handler_duration: started_at.elapsed(),
+						});
+						result
+					})
+				}))
+			},
+			None => Box::new(task),
+		};
+
+		let task: Box<Future<Item = T, Error = ()> + Send> = match self.config.saturation.clone() {
+			Some(saturation) => {
+				saturation.busy.fetch_add(1, Ordering::SeqCst);
+				Box::new(task.then(move |result| {
+					saturation.busy.fetch_sub(1, Ordering::SeqCst);
+					result
+				}))
+			},
+			None => task,
+		};
+
+		match self.config.handler_pool {
+			Some(ref pool) => Box::new(pool.spawn(task)),
+			None => task,
+		}
+	}
+
+	/// Decodes the request body according to `Content-Encoding`.
+	///
+	/// The limit on request body size is enforced on the decompressed data as well.
+	fn decompress(&self, request: Vec<u8>, encoding: &header::Encoding) -> Result<Vec<u8>, BodyError> {
+		match *encoding {
+			header::Encoding::Gzip => read_limited(GzDecoder::new(&*request), self.config.max_request_body_size),
+			header::Encoding::Deflate => read_limited(ZlibDecoder::new(&*request), self.config.max_request_body_size),
+			_ => Ok(request),
+		}
+	}
+
+	/// Returns the error of a tripped circuit breaker of the called method (if any).
+	fn tripped_error(&self, call: &core::Call) -> Option<core::Error> {
+		let method = match *call {
+			core::Call::MethodCall(ref call) => &call.method,
+			core::Call::Notification(ref notification) => &notification.method,
+			core::Call::Invalid { .. } => return None,
+		};
+		self.config.tripped_methods.read().expect("Tripped methods lock is never poisoned.").get(method).cloned()
+	}
+
+	/// Returns a response to given request if the server is in maintenance mode
+	/// and the request calls any method that is not on the allowlist.
+	fn maintenance_response(&self, request: &core::Request) -> Option<Option<core::Response>> {
+		use self::core::types::{Call, Request};
+
+		let error = self.config.maintenance.read().expect("Maintenance lock is never poisoned.").clone()?;
+		let is_allowed = |call: &Call| match *call {
+			Call::MethodCall(ref call) => self.config.maintenance_allowlist.contains(&call.method),
+			Call::Notification(ref notification) => self.config.maintenance_allowlist.contains(&notification.method),
+			Call::Invalid { .. } => false,
+		};
+
+		match *request {
+			Request::Single(ref call) if is_allowed(call) => None,
+			Request::Batch(ref calls) if calls.iter().all(is_allowed) => None,
+			_ => Some(reject_request(request, error)),
+		}
 	}
 
 	fn set_response_headers(
 		headers: &mut Headers,
 		is_options: bool,
 		cors_header: Option<header::AccessControlAllowOrigin>,
+		cors_allow_headers: Vec<Ascii<String>>,
 		cors_max_age: Option<u32>,
 	) {
 		if is_options {
@@ -423,11 +1305,7 @@ impl<M: Metadata, S: Middleware<M>> RpcHandler<M, S> {
 				Method::Options,
 				Method::Post
 			]));
-			headers.set(header::AccessControlAllowHeaders(vec![
-				Ascii::new("origin".to_owned()),
-				Ascii::new("content-type".to_owned()),
-				Ascii::new("accept".to_owned()),
-			]));
+			headers.set(header::AccessControlAllowHeaders(cors_allow_headers));
 			if let Some(cors_max_age) = cors_max_age {
 				headers.set(header::AccessControlMaxAge(cors_max_age));
 			}
@@ -438,6 +1316,160 @@ impl<M: Metadata, S: Middleware<M>> RpcHandler<M, S> {
 		}
 	}
 
+	/// Returns headers allowed in CORS requests.
+	///
+	/// For preflight requests only the requested headers are returned.
+	fn cors_allow_headers(&self) -> Vec<Ascii<String>> {
+		let mut allowed = vec![
+			Ascii::new("origin".to_owned()),
+			Ascii::new("content-type".to_owned()),
+			Ascii::new("accept".to_owned()),
+		];
+		allowed.extend(self.config.cors_allow_headers.iter().cloned().map(Ascii::new));
+
+		match self.cors_request_headers {
+			Some(ref requested) => requested.iter().filter(|header| allowed.contains(header)).cloned().collect(),
+			None => allowed,
+		}
+	}
+
+	/// Returns the encoding of request body or `None` if it's not supported.
+	fn content_encoding(content_encoding: Option<&header::ContentEncoding>) -> Option<header::Encoding> {
+		let encodings = match content_encoding {
+			Some(&header::ContentEncoding(ref encodings)) => encodings,
+			None => return Some(header::Encoding::Identity),
+		};
+
+		match encodings.len() {
+			0 => Some(header::Encoding::Identity),
+			1 => match encodings[0] {
+				header::Encoding::Identity | header::Encoding::Gzip | header::Encoding::Deflate => Some(encodings[0].clone()),
+				_ => None,
+			},
+			// Multiple encodings are not supported.
+			_ => None,
+		}
+	}
+
+	/// Returns `true` for HTTP/1.0 requests with `Connection: keep-alive` header.
+	///
+	/// HTTP/1.1 connections are persistent by default, which is handled by hyper.
+	fn is_http10_keep_alive(request: &server::Request) -> bool {
+		*request.version() == hyper::HttpVersion::Http10 && request.headers().get::<header::Connection>()
+			.map_or(false, |connection| connection.iter().any(|option| *option == header::ConnectionOption::KeepAlive))
+	}
+
+	/// Reads `Idempotency-Key` header of the request, scoped to the requested path.
+	fn idempotency_key(request: &server::Request) -> Option<String> {
+		request.headers().get_raw("Idempotency-Key")
+			.and_then(|value| value.one())
+			.and_then(|value| str::from_utf8(value).ok())
+			.map(|key| format!("{} {}", request.uri().path(), key))
+	}
+
+	/// Returns `true` if the request asks for the list of methods (`OPTIONS /?methods`).
+	fn is_discovery(uri: &hyper::Uri) -> bool {
+		uri.query().map_or(false, |query| query.split('&').any(|param| param == "methods"))
+	}
+
+	fn accepts_msgpack(accept: Option<&header::Accept>) -> bool {
+		match accept {
+			Some(&header::Accept(ref items)) => items.iter().any(|item| {
+				item.quality > header::q(0) && item.item.as_ref() == APPLICATION_MSGPACK
+			}),
+			None => false,
+		}
+	}
+
+	fn accepts_gzip(accept: Option<&header::AcceptEncoding>) -> bool {
+		match accept {
+			Some(&header::AcceptEncoding(ref items)) => items.iter().any(|item| {
+				item.quality > header::q(0) && item.item == header::Encoding::Gzip
+			}),
+			None => false,
+		}
+	}
+
+	/// Returns `true` if the client disabled compression of the response with `X-No-Compression: true`.
+	fn opts_out_of_compression(request: &server::Request) -> bool {
+		utils::read_header(request, NO_COMPRESSION_HEADER).map_or(false, |value| value.trim().eq_ignore_ascii_case("true"))
+	}
+
+	/// Compresses the response body with gzip.
+	fn gzip(body: &[u8]) -> Vec<u8> {
+		let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+		encoder.write_all(body).and_then(|_| encoder.finish()).expect("Writing to Vec never fails; qed")
+	}
+
+	/// Converts successful JSON responses to msgpack, leaves other responses untouched.
+	/// Returns content type and body of the response.
+	fn into_msgpack(response: Response) -> (header::ContentType, Vec<u8>) {
+		if response.code != hyper::StatusCode::Ok
+			|| response.content_type != header::ContentType::json()
+			|| response.content.is_empty() {
+			return (response.content_type, response.content.into_bytes());
+		}
+
+		let encoded = serde_json::from_str::<serde_json::Value>(&response.content).ok()
+			.and_then(|value| rmp_serde::to_vec(&value).ok());
+
+		match encoded {
+			Some(body) => (header::ContentType(APPLICATION_MSGPACK.parse().expect("Mime is valid; qed")), body),
+			None => (response.content_type, response.content.into_bytes()),
+		}
+	}
+
+	/// Splits the body into chunks of at most `chunk_size` bytes (if configured).
+	fn chunked_body(&self, body: Vec<u8>) -> hyper::Body {
+		let chunk_size = match self.config.chunk_size {
+			Some(chunk_size) if body.len() > chunk_size => chunk_size,
+			_ => return body.into(),
+		};
+
+		let chunks: Vec<Result<hyper::Chunk, hyper::Error>> = body
+			.chunks(chunk_size)
+			.map(|chunk| Ok(chunk.to_vec().into()))
+			.collect();
+		let (sender, body) = hyper::Body::pair();
+		self.handle.spawn(
+			sender.send_all(stream::iter_ok::<_, mpsc::SendError<_>>(chunks))
+				.map(|_| ())
+				.map_err(|e| debug!("Unable to write response body: {:?}", e))
+		);
+		body
+	}
+
+	/// Returns a writer of streamed outputs, processing them the same way as regular responses.
+	fn output_writer(&self, metadata: M) -> WriteOutput {
+		let error_rewriter = self.config.error_rewriter.clone();
+		let error_context = self.error_context.clone();
+		let max_response_size = self.config.max_response_size;
+		let ascii_only = self.config.ascii_only_output;
+		Box::new(move |output| {
+			let response = match error_rewriter {
+				Some(ref rewriter) => rewrite_errors(core::Response::Single(output), |error| rewriter(&metadata, error)),
+				None => core::Response::Single(output),
+			};
+			let response = match error_context {
+				Some(ref context) => with_error_context(response, context),
+				None => response,
+			};
+			let content = write_limited_response(Some(response), max_response_size).expect("Response is always written; qed");
+			if ascii_only { escape_non_ascii(&content) } else { content }
+		})
+	}
+
+	/// Writes each output as a separate chunk as soon as it's ready.
+	fn streamed_body(&self, outputs: Outputs) -> hyper::Body {
+		let (sender, body) = hyper::Body::pair();
+		self.handle.spawn(
+			sender.send_all(outputs)
+				.map(|_| ())
+				.map_err(|e| debug!("Unable to write streamed response body: {:?}", e))
+		);
+		body
+	}
+
 	fn is_json(content_type: Option<&header::ContentType>) -> bool {
 		const APPLICATION_JSON_UTF_8: &str = "application/json; charset=utf-8";
 
@@ -448,3 +1480,312 @@ impl<M: Metadata, S: Middleware<M>> RpcHandler<M, S> {
 		}
 	}
 }
+
+/// Reads whole `reader`, failing if more than `limit` bytes are produced.
+fn read_limited<R: Read>(reader: R, limit: usize) -> Result<Vec<u8>, BodyError> {
+	let mut data = Vec::new();
+	reader
+		.take(limit as u64 + 1)
+		.read_to_end(&mut data)
+		.map_err(BodyError::Decompression)?;
+
+	if data.len() > limit {
+		return Err(BodyError::TooLarge);
+	}
+
+	Ok(data)
+}
+
+/// Returns `false` if the first non-whitespace byte of the body can't start a JSON-RPC request.
+///
+/// `read` is the part of the body read so far and `chunk` the one that was just received.
+fn may_be_json(read: &[u8], chunk: &[u8]) -> bool {
+	let is_whitespace = |byte: &&u8| b" \t\r\n".contains(*byte);
+	if read.iter().any(|byte| !is_whitespace(&byte)) {
+		return true;
+	}
+
+	chunk.iter().find(|byte| !is_whitespace(byte)).map_or(true, |byte| *byte == b'{' || *byte == b'[')
+}
+
+/// Creates HTTP response with parse error of given details.
+fn parse_error(details: String, error_status: ErrorStatus) -> Response {
+	let mut error = core::Error::parse_error();
+	error.data = Some(core::Value::String(details));
+	let code = error_status.status(&error);
+	let response = core::Response::from(error, Some(core::Version::V2));
+	let mut response = Response::ok(format!("{}\n", serde_json::to_string(&response)
+		.expect("Serialization of response is infallible;qed")));
+	response.code = code;
+	response
+}
+
+/// Returns `true` if the request is a batch with multiple method calls of the same id.
+fn has_duplicate_ids(request: &core::Request) -> bool {
+	use std::collections::HashSet;
+	use self::core::types::{Call, Request};
+
+	match *request {
+		Request::Single(_) => false,
+		Request::Batch(ref calls) => {
+			let mut ids = HashSet::new();
+			calls.iter().any(|call| match *call {
+				Call::MethodCall(ref call) => !ids.insert(&call.id),
+				_ => false,
+			})
+		},
+	}
+}
+
+/// Returns a response failing all calls of the request with given error.
+fn reject_request(request: &core::Request, error: core::Error) -> Option<core::Response> {
+	use self::core::types::{Call, Output, Request, Response, Version};
+
+	let reject = |call: &Call| match *call {
+		Call::MethodCall(ref call) => Some(Output::from(Err(error.clone()), call.id.clone(), call.jsonrpc)),
+		Call::Notification(_) => None,
+		Call::Invalid { ref id } => Some(Output::invalid_request(id.clone(), Some(Version::V2))),
+	};
+
+	match *request {
+		Request::Single(ref call) => reject(call).map(Response::Single),
+		Request::Batch(ref calls) => {
+			let outputs: Vec<_> = calls.iter().enumerate().filter_map(|(index, call)| match *call {
+				Call::Invalid { .. } => reject(call).map(|output| output.with_batch_index(index)),
+				_ => reject(call),
+			}).collect();
+			if outputs.is_empty() { None } else { Some(Response::Batch(outputs)) }
+		},
+	}
+}
+
+/// Sets `data` of all errors without data to given context.
+fn with_error_context(response: core::Response, context: &core::Value) -> core::Response {
+	let add_context = |output| match output {
+		core::Output::Failure(mut failure) => {
+			if failure.error.data.is_none() {
+				failure.error.data = Some(context.clone());
+				failure.raw = None;
+			}
+			core::Output::Failure(failure)
+		},
+		output => output,
+	};
+
+	match response {
+		core::Response::Single(output) => core::Response::Single(add_context(output)),
+		core::Response::Batch(outputs) => core::Response::Batch(outputs.into_iter().map(add_context).collect()),
+	}
+}
+
+fn rewrite_errors<F: Fn(core::Error) -> core::Error>(response: core::Response, rewrite: F) -> core::Response {
+	let rewrite_output = |output| match output {
+		core::Output::Failure(mut failure) => {
+			failure.error = rewrite(failure.error);
+			failure.raw = None;
+			core::Output::Failure(failure)
+		},
+		output => output,
+	};
+
+	match response {
+		core::Response::Single(output) => core::Response::Single(rewrite_output(output)),
+		core::Response::Batch(outputs) => core::Response::Batch(outputs.into_iter().map(rewrite_output).collect()),
+	}
+}
+
+fn timeout_error() -> core::Error {
+	core::Error {
+		code: core::ErrorCode::ServerError(BATCH_TIMEOUT_ERROR_CODE),
+		message: "Request timed out".into(),
+		data: None,
+	}
+}
+
+/// Converts plain text HTTP error responses (4xx and 5xx) to JSON, leaves other responses untouched.
+fn http_error_as_json(response: Response) -> Response {
+	let is_error = response.code.is_client_error() || response.code.is_server_error();
+	if !is_error || response.content_type != header::ContentType::plaintext() {
+		return response;
+	}
+
+	let mut error = serde_json::Map::new();
+	error.insert("code".into(), serde_json::Value::from(response.code.as_u16()));
+	error.insert("message".into(), serde_json::Value::String(response.content.trim_right().into()));
+	let mut body = serde_json::Map::new();
+	body.insert("error".into(), serde_json::Value::Object(error));
+
+	Response {
+		code: response.code,
+		content_type: header::ContentType::json(),
+		content: format!("{}\n", serde_json::Value::Object(body)),
+	}
+}
+
+/// Creates HTTP response for serialized RPC response.
+fn rpc_response(response: Option<String>) -> Response {
+	match response {
+		// Notification, just return empty response.
+		None => Response::ok(String::new()),
+		// Add new line to have nice output when using CLI clients (curl)
+		Some(result) => Response::ok(format!("{}\n", result)),
+	}
+}
+
+/// Formats the value of `Server-Timing` header with given handler duration (in milliseconds).
+fn server_timing(duration: Duration) -> String {
+	let millis = duration.as_secs() as f64 * 1000.0 + f64::from(duration.subsec_nanos()) / 1_000_000.0;
+	format!("handler;dur={:.3}", millis)
+}
+
+/// Escapes non-ASCII characters of serialized JSON as `\uXXXX` (using surrogate pairs where needed).
+///
+/// JSON permits non-ASCII characters only within strings, where the escapes are equivalent.
+fn escape_non_ascii(content: &str) -> String {
+	use std::fmt::Write;
+
+	let mut escaped = String::with_capacity(content.len());
+	for c in content.chars() {
+		if c.is_ascii() {
+			escaped.push(c);
+			continue;
+		}
+		let mut units = [0; 2];
+		for unit in c.encode_utf16(&mut units) {
+			write!(escaped, "\\u{:04x}", unit).expect("Writing to a string is infallible; qed");
+		}
+	}
+	escaped
+}
+
+/// Prepends given fields to each output of serialized response.
+fn with_output_fields<F>(response: &str, fields: F) -> String where
+	F: Fn(&core::Output) -> Vec<(&'static str, core::Value)>,
+{
+	let extended = |output: &core::Output| {
+		let prefix: String = fields(output).into_iter().map(|(name, value)| {
+			format!("\"{}\":{},", name, value)
+		}).collect();
+		let output = serde_json::to_string(output).expect("Serialization of output is infallible; qed");
+		format!("{{{}{}", prefix, &output[1..])
+	};
+
+	match serde_json::from_str::<core::Response>(response) {
+		Ok(core::Response::Single(ref output)) => extended(output),
+		Ok(core::Response::Batch(ref outputs)) => {
+			format!("[{}]", outputs.iter().map(extended).collect::<Vec<_>>().join(","))
+		},
+		Err(_) => response.to_owned(),
+	}
+}
+
+/// Returns names of methods called by the request, by id of the call.
+fn call_methods(request: &core::Request) -> HashMap<core::Id, String> {
+	let calls: Vec<_> = match *request {
+		core::Request::Single(ref call) => vec![call],
+		core::Request::Batch(ref calls) => calls.iter().collect(),
+	};
+
+	calls.into_iter().filter_map(|call| match *call {
+		core::Call::MethodCall(ref call) => Some((call.id.clone(), call.method.clone())),
+		_ => None,
+	}).collect()
+}
+
+/// Returns methods and ids (`None` for notifications) of valid calls of the request.
+fn logged_calls(request: &core::Request) -> Vec<(String, Option<core::Id>)> {
+	let calls: Vec<_> = match *request {
+		core::Request::Single(ref call) => vec![call],
+		core::Request::Batch(ref calls) => calls.iter().collect(),
+	};
+
+	calls.into_iter().filter_map(|call| match *call {
+		core::Call::MethodCall(ref call) => Some((call.method.clone(), Some(call.id.clone()))),
+		core::Call::Notification(ref notification) => Some((notification.method.clone(), None)),
+		core::Call::Invalid { .. } => None,
+	}).collect()
+}
+
+/// Computes ETag of a successful response to a single call from its result.
+fn response_etag(response: &str) -> Option<String> {
+	match serde_json::from_str(response) {
+		Ok(core::Response::Single(core::Output::Success(ref success))) => {
+			let result = serde_json::to_vec(&success.result).expect("Serialization of values is infallible; qed");
+			let hash: String = Sha256::digest(&result).iter().take(16).map(|byte| format!("{:02x}", byte)).collect();
+			Some(format!("\"{}\"", hash))
+		},
+		_ => None,
+	}
+}
+
+/// Returns HTTP status of the response if it's a single failure.
+fn error_status(response: &str, error_status: ErrorStatus) -> Option<hyper::StatusCode> {
+	if error_status == ErrorStatus::Ok {
+		return None;
+	}
+
+	match serde_json::from_str(response) {
+		Ok(core::Response::Single(core::Output::Failure(ref failure))) => Some(error_status.status(&failure.error)),
+		_ => None,
+	}
+}
+
+/// Returns `true` if `If-None-Match` header value matches given ETag.
+fn etag_matches(if_none_match: &str, etag: &str) -> bool {
+	if_none_match.split(',').map(str::trim).any(|tag| tag == "*" || tag.trim_left_matches("W/") == etag)
+}
+
+fn write_response(response: Option<core::Response>) -> Option<String> {
+	response.map(|x| x.to_json())
+}
+
+/// Serializes the response, replacing it with an error if it's larger than `max_size` bytes.
+///
+/// Serialization stops as soon as the limit is exceeded, so the whole response is never buffered.
+fn write_limited_response(response: Option<core::Response>, max_size: Option<usize>) -> Option<String> {
+	let (response, max_size) = match (response, max_size) {
+		(Some(response), Some(max_size)) => (response, max_size),
+		(response, _) => return write_response(response),
+	};
+
+	let mut writer = LimitedWriter { buffer: Vec::new(), limit: max_size };
+	match response.write_json(&mut writer) {
+		Ok(()) => Some(String::from_utf8(writer.buffer).expect("Serialized JSON is valid UTF-8; qed")),
+		Err(_) => write_response(Some(response_too_large(response))),
+	}
+}
+
+/// Replaces all outputs of the response with the response too large error.
+fn response_too_large(response: core::Response) -> core::Response {
+	let error = core::Error {
+		code: core::ErrorCode::ServerError(RESPONSE_TOO_LARGE_ERROR_CODE),
+		message: "Response too large".into(),
+		data: None,
+	};
+	let replace = |output: core::Output| core::Output::from(Err(error.clone()), output.id().clone(), output.version());
+
+	match response {
+		core::Response::Single(output) => core::Response::Single(replace(output)),
+		core::Response::Batch(outputs) => core::Response::Batch(outputs.into_iter().map(replace).collect()),
+	}
+}
+
+/// A writer that fails when more than `limit` bytes are written.
+struct LimitedWriter {
+	buffer: Vec<u8>,
+	limit: usize,
+}
+
+impl io::Write for LimitedWriter {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		if self.buffer.len() + buf.len() > self.limit {
+			return Err(io::Error::new(io::ErrorKind::Other, "Response too large"));
+		}
+		self.buffer.extend_from_slice(buf);
+		Ok(buf.len())
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		Ok(())
+	}
+}
@@ -0,0 +1,83 @@
+//! Cache of responses to requests carrying `Idempotency-Key` header.
+
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+use sha2::{Digest, Sha256};
+
+/// Result of a lookup of a request in the cache.
+#[derive(Debug, PartialEq)]
+pub enum Lookup {
+	/// The request wasn't processed yet.
+	Miss,
+	/// The request was already processed, with given response.
+	Hit(Option<String>),
+	/// The key was already used by a request with a different body.
+	Conflict,
+}
+
+/// Caches serialized responses by idempotency key, along with a hash of the request body.
+///
+/// Holds at most `capacity` responses, each of them for at most `ttl`.
+#[derive(Debug)]
+pub struct IdempotencyCache {
+	capacity: usize,
+	ttl: Duration,
+	responses: HashMap<String, (Instant, String, Option<String>)>,
+	// Keys in insertion order, oldest first.
+	keys: VecDeque<String>,
+}
+
+impl IdempotencyCache {
+	/// Creates new empty cache.
+	pub fn new(capacity: usize, ttl: Duration) -> Self {
+		IdempotencyCache {
+			capacity,
+			ttl,
+			responses: HashMap::with_capacity(capacity),
+			keys: VecDeque::with_capacity(capacity),
+		}
+	}
+
+	/// Looks up a response cached for given key and hash of the request body (if it didn't expire yet).
+	pub fn get(&mut self, key: &str, body_hash: &str) -> Lookup {
+		self.remove_expired();
+		match self.responses.get(key) {
+			Some(&(_, ref hash, ref response)) if hash == body_hash => Lookup::Hit(response.clone()),
+			Some(_) => Lookup::Conflict,
+			None => Lookup::Miss,
+		}
+	}
+
+	/// Caches a response for given key and hash of the request body, evicting the oldest responses if the cache is full.
+	pub fn insert(&mut self, key: String, body_hash: String, response: Option<String>) {
+		self.remove_expired();
+		if self.responses.insert(key.clone(), (Instant::now(), body_hash, response)).is_some() {
+			self.keys.retain(|k| *k != key);
+		}
+		self.keys.push_back(key);
+
+		while self.keys.len() > self.capacity {
+			if let Some(key) = self.keys.pop_front() {
+				self.responses.remove(&key);
+			}
+		}
+	}
+
+	fn remove_expired(&mut self) {
+		let now = Instant::now();
+		while let Some(key) = self.keys.front().cloned() {
+			let expired = self.responses.get(&key).map_or(true, |&(inserted, _, _)| now.duration_since(inserted) >= self.ttl);
+			if !expired {
+				break;
+			}
+			self.keys.pop_front();
+			self.responses.remove(&key);
+		}
+	}
+}
+
+/// Returns a hash of the request body identifying the request along with its key.
+pub fn body_hash(body: &[u8]) -> String {
+	Sha256::digest(body).iter().map(|byte| format!("{:02x}", byte)).collect()
+}
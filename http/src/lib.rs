@@ -26,6 +26,11 @@
 extern crate unicase;
 extern crate jsonrpc_server_utils as server_utils;
 extern crate net2;
+extern crate flate2;
+extern crate futures_cpupool;
+extern crate rmp_serde;
+extern crate hmac;
+extern crate sha2;
 
 pub extern crate jsonrpc_core;
 pub extern crate hyper;
@@ -33,15 +38,28 @@ pub extern crate hyper;
 #[macro_use]
 extern crate log;
 
+mod buffers;
+mod client;
+mod connection;
+#[cfg_attr(not(feature = "fault-injection"), allow(dead_code))]
+mod fault;
 mod handler;
+mod idempotency;
+mod proxy;
 mod response;
+mod route;
+mod signature;
+mod streaming;
 mod utils;
 #[cfg(test)]
 mod tests;
 
 use std::io;
-use std::sync::{mpsc, Arc};
-use std::net::SocketAddr;
+use std::thread;
+use std::collections::HashMap;
+use std::sync::{mpsc, Arc, Mutex, RwLock};
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, Instant};
 
 use hyper::server;
 use jsonrpc_core as jsonrpc;
@@ -49,13 +67,24 @@ use jsonrpc::MetaIoHandler;
 use jsonrpc::futures::{self, Future, Stream};
 use jsonrpc::futures::sync::oneshot;
 use server_utils::reactor::{Remote, UninitializedRemote};
+use idempotency::IdempotencyCache;
+use signature::SignatureVerifier;
+use buffers::BufferBudget;
+use connection::{ConnectionErrorHook, ObservedStream, PeerConnections};
+use route::Route;
 
 pub use server_utils::hosts::{Host, DomainsValidation};
 pub use server_utils::cors::{AccessControlAllowOrigin, Origin};
 pub use server_utils::tokio_core;
-pub use handler::ServerHandler;
+use handler::{InFlight, PoolSaturation};
+pub use handler::{ServerHandler, RequestLogger, RequestRecord, RequestTimings, BATCH_TIMEOUT_ERROR_CODE, REQUEST_TIMEOUT_ERROR_CODE, RESPONSE_TOO_LARGE_ERROR_CODE};
 pub use utils::{is_host_allowed, cors_header, CorsHeader};
 pub use response::Response;
+pub use client::{BatchBuilder, ClientFuture, HttpClient, RpcError};
+pub use route::RouteOptions;
+pub use connection::{ConnectionError, ConnectionErrorKind};
+#[cfg(feature = "fault-injection")]
+pub use fault::{FaultConfig, INJECTED_FAULT_ERROR_CODE};
 
 /// Action undertaken by a middleware.
 pub enum RequestMiddlewareAction {
@@ -132,6 +161,21 @@ impl RequestMiddleware for NoopRequestMiddleware {
 pub trait MetaExtractor<M: jsonrpc::Metadata>: Sync + Send + 'static {
 	/// Read the metadata from the request
 	fn read_metadata(&self, _: &server::Request) -> M;
+
+	/// Read the context of the request that should be included in error responses (e.g. trace id).
+	///
+	/// The context is set as `data` of every error returned for the request, unless the error
+	/// already carries some data.
+	fn read_error_context(&self, _: &server::Request) -> Option<jsonrpc::Value> {
+		None
+	}
+
+	/// Read the metadata from the request with API version parsed from the path.
+	///
+	/// Only used if `ServerBuilder::path_version_prefix` is configured, defaults to `read_metadata`.
+	fn read_versioned_metadata(&self, req: &server::Request, _version: u32) -> M {
+		self.read_metadata(req)
+	}
 }
 
 impl<M, F> MetaExtractor<M> for F where
@@ -150,6 +194,48 @@ impl<M: jsonrpc::Metadata + Default> MetaExtractor<M> for NoopExtractor {
 		M::default()
 	}
 }
+
+/// Metadata of HTTP request: address of the peer and values of selected headers.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RequestMetadata {
+	/// Address of the peer (if known).
+	pub remote_addr: Option<SocketAddr>,
+	/// Values of headers read by `RequestMetadataExtractor` (only the ones present in the request).
+	pub headers: HashMap<String, String>,
+}
+
+impl jsonrpc::Metadata for RequestMetadata {}
+
+/// Extracts `RequestMetadata` with values of given headers from the HTTP request.
+#[derive(Debug, Clone, Default)]
+pub struct RequestMetadataExtractor {
+	headers: Vec<String>,
+}
+
+impl RequestMetadataExtractor {
+	/// Creates new extractor reading values of given headers.
+	///
+	/// Header values are stored under the names given here.
+	pub fn new<T: Into<String>>(headers: Vec<T>) -> Self {
+		RequestMetadataExtractor {
+			headers: headers.into_iter().map(Into::into).collect(),
+		}
+	}
+}
+
+impl MetaExtractor<RequestMetadata> for RequestMetadataExtractor {
+	fn read_metadata(&self, req: &server::Request) -> RequestMetadata {
+		let headers = self.headers.iter().filter_map(|name| {
+			let value = req.headers().get_raw(name)?.one()?;
+			Some((name.clone(), String::from_utf8_lossy(value).into_owned()))
+		}).collect();
+
+		RequestMetadata {
+			remote_addr: req.remote_addr(),
+			headers,
+		}
+	}
+}
 //
 /// RPC Handler bundled with metadata extractor.
 pub struct Rpc<M: jsonrpc::Metadata = (), S: jsonrpc::Middleware<M> = jsonrpc::NoopMiddleware> {
@@ -170,6 +256,18 @@ impl<M: jsonrpc::Metadata, S: jsonrpc::Middleware<M>> Clone for Rpc<M, S> {
 
 type AllowedHosts = Option<Vec<Host>>;
 type CorsDomains = Option<Vec<AccessControlAllowOrigin>>;
+type Maintenance = Arc<RwLock<Option<jsonrpc::Error>>>;
+type TrippedMethods = Arc<RwLock<HashMap<String, jsonrpc::Error>>>;
+type PathVersion = Option<(String, Vec<u32>)>;
+type Idempotency = Option<Arc<Mutex<IdempotencyCache>>>;
+type TimingsHook = Option<Arc<Fn(&RequestTimings) + Send + Sync>>;
+type RequestLoggerHook = Option<Arc<RequestLogger>>;
+type Signature = Option<Arc<SignatureVerifier>>;
+type Saturation = Option<Arc<PoolSaturation>>;
+type Buffers = Option<Arc<BufferBudget>>;
+type ErrorRewriter<M> = Option<Arc<Fn(&M, jsonrpc::Error) -> jsonrpc::Error + Send + Sync>>;
+type SharedRpc<M, S> = Arc<RwLock<Rpc<M, S>>>;
+type Routes<M, S> = Option<Arc<HashMap<String, Route<M, S>>>>;
 
 /// REST -> RPC converter state.
 #[derive(Debug, PartialEq, Clone, Copy)]
@@ -189,6 +287,142 @@ pub enum RestApi {
 	Disabled,
 }
 
+/// Response sent to `OPTIONS` requests (including CORS preflight requests).
+///
+/// CORS and `Allow` headers are added regardless of the variant.
+#[derive(Debug, Clone)]
+pub enum OptionsResponse {
+	/// `200 OK` with empty body.
+	Ok,
+	/// `204 No Content`.
+	NoContent,
+	/// Custom status code and body.
+	Custom(Response),
+}
+
+/// Compression of response bodies for clients accepting `gzip` encoding.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum CompressionMode {
+	/// Responses are never compressed.
+	Disabled,
+	/// All responses are compressed.
+	Always,
+	/// Only responses with (uncompressed) body larger than given number of bytes are compressed.
+	OnlyLarge(usize),
+}
+
+impl CompressionMode {
+	/// Returns `true` if a body of given (uncompressed) size should be compressed.
+	pub fn should_compress(&self, size: usize) -> bool {
+		match *self {
+			CompressionMode::Disabled => false,
+			CompressionMode::Always => true,
+			CompressionMode::OnlyLarge(threshold) => size > threshold,
+		}
+	}
+}
+
+/// Handling of batches containing multiple calls with the same id.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum DuplicateBatchIdPolicy {
+	/// Calls are processed as usual, clients can't tell their outputs apart by id.
+	Allow,
+	/// The whole batch is rejected with `InvalidRequest` error.
+	Reject,
+}
+
+/// Format of outputs of RPC calls.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ErrorEnvelope {
+	/// Outputs as specified by JSON-RPC.
+	Standard,
+	/// Each output additionally carries `success` field, `false` for errors and `true` otherwise.
+	SuccessFlag,
+}
+
+/// HTTP status of responses carrying a single RPC error.
+///
+/// Responses of batches and successful calls are always sent with `200 OK`.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ErrorStatus {
+	/// Errors are sent with `200 OK` status.
+	Ok,
+	/// Status reflects the error: `400 Bad Request` for parse errors, invalid requests and invalid params,
+	/// `404 Not Found` for unknown methods and `500 Internal Server Error` otherwise.
+	Mapped,
+}
+
+impl ErrorStatus {
+	/// Returns HTTP status of a response carrying given error.
+	pub fn status(&self, error: &jsonrpc::Error) -> hyper::StatusCode {
+		use jsonrpc::ErrorCode::*;
+
+		if *self == ErrorStatus::Ok {
+			return hyper::StatusCode::Ok;
+		}
+
+		match error.code {
+			ParseError | InvalidRequest | InvalidParams => hyper::StatusCode::BadRequest,
+			MethodNotFound => hyper::StatusCode::NotFound,
+			_ => hyper::StatusCode::InternalServerError,
+		}
+	}
+}
+
+/// Configuration shared by all connections of a server, built once by `ServerBuilder::start_http`.
+pub struct ServerConfig<M: jsonrpc::Metadata = (), S: jsonrpc::Middleware<M> = jsonrpc::NoopMiddleware> {
+	jsonrpc_handler: SharedRpc<M, S>,
+	request_middleware: Arc<RequestMiddleware>,
+	cors_domains: CorsDomains,
+	cors_max_age: Option<u32>,
+	allowed_hosts: AllowedHosts,
+	rest_api: RestApi,
+	keep_alive: bool,
+	reuse_port: bool,
+	max_request_body_size: usize,
+	maintenance: Maintenance,
+	maintenance_allowlist: Vec<String>,
+	batch_timeout: Option<Duration>,
+	chunk_size: Option<usize>,
+	strict: bool,
+	advertise_methods: bool,
+	handler_pool: Option<futures_cpupool::CpuPool>,
+	path_version: PathVersion,
+	max_response_size: Option<usize>,
+	options_response: OptionsResponse,
+	idempotency: Idempotency,
+	http_errors_as_json: bool,
+	batch_ordered: bool,
+	fault_injection: Option<fault::FaultConfig>,
+	trusted_proxies: Vec<IpAddr>,
+	not_found_response: Response,
+	timings_hook: TimingsHook,
+	duplicate_batch_id_policy: DuplicateBatchIdPolicy,
+	signature: Signature,
+	saturation: Saturation,
+	compression: CompressionMode,
+	buffers: Buffers,
+	ascii_only_output: bool,
+	accept_proxy_protocol: bool,
+	error_envelope: ErrorEnvelope,
+	error_status: ErrorStatus,
+	cors_allow_headers: Vec<String>,
+	connection_error_hook: ConnectionErrorHook,
+	peer_connections: Option<Arc<PeerConnections>>,
+	error_rewriter: ErrorRewriter<M>,
+	server_timing: bool,
+	include_method_in_response: bool,
+	in_flight: Arc<InFlight>,
+	idle_timeout: Option<Duration>,
+	request_timeout: Option<Duration>,
+	max_connection_age: Option<Duration>,
+	routes: Routes<M, S>,
+	request_logger: RequestLoggerHook,
+	cacheable_methods: Vec<String>,
+	tripped_methods: TrippedMethods,
+	health_check_path: Option<String>,
+}
+
 /// Convenient JSON-RPC HTTP Server builder.
 pub struct ServerBuilder<M: jsonrpc::Metadata = (), S: jsonrpc::Middleware<M> = jsonrpc::NoopMiddleware> {
 	handler: Arc<MetaIoHandler<M, S>>,
@@ -202,6 +436,45 @@ pub struct ServerBuilder<M: jsonrpc::Metadata = (), S: jsonrpc::Middleware<M> =
 	keep_alive: bool,
 	threads: usize,
 	max_request_body_size: usize,
+	maintenance_allowlist: Vec<String>,
+	batch_timeout: Option<Duration>,
+	chunk_size: Option<usize>,
+	strict: bool,
+	advertise_methods: bool,
+	handler_pool_size: Option<usize>,
+	saturated_retry_after: Option<Duration>,
+	path_version: PathVersion,
+	max_response_size: Option<usize>,
+	options_response: OptionsResponse,
+	idempotency: Option<(usize, Duration)>,
+	http_errors_as_json: bool,
+	batch_ordered: bool,
+	fault_injection: Option<fault::FaultConfig>,
+	trusted_proxies: Vec<IpAddr>,
+	not_found_response: Response,
+	timings_hook: TimingsHook,
+	duplicate_batch_id_policy: DuplicateBatchIdPolicy,
+	signature: Signature,
+	compression: CompressionMode,
+	connection_error_hook: ConnectionErrorHook,
+	max_total_buffered_bytes: Option<usize>,
+	ascii_only_output: bool,
+	accept_proxy_protocol: bool,
+	error_envelope: ErrorEnvelope,
+	error_status: ErrorStatus,
+	cors_allow_headers: Vec<String>,
+	max_connections_per_ip: Option<usize>,
+	error_rewriter: ErrorRewriter<M>,
+	server_timing: bool,
+	shutdown_timeout: Duration,
+	include_method_in_response: bool,
+	idle_timeout: Option<Duration>,
+	request_timeout: Option<Duration>,
+	max_connection_age: Option<Duration>,
+	routes: HashMap<String, (Arc<MetaIoHandler<M, S>>, RouteOptions)>,
+	request_logger: RequestLoggerHook,
+	cacheable_methods: Vec<String>,
+	health_check_path: Option<String>,
 }
 
 const SENDER_PROOF: &'static str = "Server initialization awaits local address.";
@@ -241,6 +514,45 @@ impl<M: jsonrpc::Metadata, S: jsonrpc::Middleware<M>> ServerBuilder<M, S> {
 			keep_alive: true,
 			threads: 1,
 			max_request_body_size: 5 * 1024 * 1024,
+			maintenance_allowlist: Vec::new(),
+			batch_timeout: None,
+			chunk_size: None,
+			strict: false,
+			advertise_methods: false,
+			handler_pool_size: None,
+			saturated_retry_after: None,
+			path_version: None,
+			max_response_size: None,
+			options_response: OptionsResponse::Ok,
+			idempotency: None,
+			http_errors_as_json: false,
+			batch_ordered: true,
+			fault_injection: None,
+			trusted_proxies: Vec::new(),
+			not_found_response: Response::not_found(),
+			timings_hook: None,
+			duplicate_batch_id_policy: DuplicateBatchIdPolicy::Allow,
+			signature: None,
+			compression: CompressionMode::Disabled,
+			connection_error_hook: None,
+			max_total_buffered_bytes: None,
+			ascii_only_output: false,
+			accept_proxy_protocol: false,
+			error_envelope: ErrorEnvelope::Standard,
+			error_status: ErrorStatus::Ok,
+			cors_allow_headers: Vec::new(),
+			max_connections_per_ip: None,
+			error_rewriter: None,
+			server_timing: false,
+			shutdown_timeout: Duration::from_secs(5),
+			include_method_in_response: false,
+			idle_timeout: None,
+			request_timeout: None,
+			max_connection_age: None,
+			routes: HashMap::new(),
+			request_logger: None,
+			cacheable_methods: Vec::new(),
+			health_check_path: None,
 		}
 	}
 
@@ -269,11 +581,31 @@ impl<M: jsonrpc::Metadata, S: jsonrpc::Middleware<M>> ServerBuilder<M, S> {
 		self
 	}
 
+	/// Closes connections on which nothing was read or written for given time.
+	///
+	/// Mostly limits how long idle keep-alive connections are kept open.
+	/// Default is no timeout.
+	pub fn idle_timeout(mut self, timeout: Duration) -> Self {
+		self.idle_timeout = Some(timeout);
+		self
+	}
+
+	/// Closes connections that are open for longer than given time, e.g. to rebalance
+	/// long-lived keep-alive connections behind a load balancer.
+	///
+	/// Requests in progress are completed first, their responses carry `Connection: close` header.
+	/// Default is no limit.
+	pub fn max_connection_age(mut self, age: Duration) -> Self {
+		self.max_connection_age = Some(age);
+		self
+	}
+
 	/// Sets number of threads of the server to run.
 	///
 	/// Panics when set to `0`.
 	#[cfg(not(unix))]
-	pub fn threads(mut self, _threads: usize) -> Self {
+	pub fn threads(mut self, threads: usize) -> Self {
+		assert!(threads > 0, "The server needs at least 1 thread, got 0.");
 		warn!("Multi-threaded server is not available on Windows. Falling back to single thread.");
 		self
 	}
@@ -283,6 +615,7 @@ impl<M: jsonrpc::Metadata, S: jsonrpc::Middleware<M>> ServerBuilder<M, S> {
 	/// Panics when set to `0`.
 	#[cfg(unix)]
 	pub fn threads(mut self, threads: usize) -> Self {
+		assert!(threads > 0, "The server needs at least 1 thread, got 0.");
 		self.threads = threads;
 		self
 	}
@@ -293,6 +626,17 @@ impl<M: jsonrpc::Metadata, S: jsonrpc::Middleware<M>> ServerBuilder<M, S> {
 		self
 	}
 
+	/// Serves requests to given path (e.g. `/admin`) with a separate handler and options.
+	///
+	/// Once a route is added, the handler passed to the builder only serves requests to `/`
+	/// and requests to other paths get the not found response (see `not_found_response`).
+	pub fn route<T>(mut self, path: &str, handler: T, options: RouteOptions) -> Self where
+		T: Into<MetaIoHandler<M, S>>,
+	{
+		self.routes.insert(path.into(), (Arc::new(handler.into()), options));
+		self
+	}
+
 	/// Configure CORS `AccessControlMaxAge` header returned.
 	///
 	/// Passing `Some(millis)` informs the client that the CORS preflight request is not necessary
@@ -303,6 +647,15 @@ impl<M: jsonrpc::Metadata, S: jsonrpc::Middleware<M>> ServerBuilder<M, S> {
 		self
 	}
 
+	/// Configures additional headers allowed in CORS requests.
+	///
+	/// `Origin`, `Content-Type` and `Accept` are always allowed. Preflight requests
+	/// get back only those of the headers listed in `Access-Control-Request-Headers`.
+	pub fn cors_allow_headers<T: Into<String>>(mut self, headers: Vec<T>) -> Self {
+		self.cors_allow_headers = headers.into_iter().map(Into::into).collect();
+		self
+	}
+
 	/// Configures request middleware
 	pub fn request_middleware<T: RequestMiddleware>(mut self, middleware: T) -> Self {
 		self.request_middleware = Arc::new(middleware);
@@ -328,61 +681,437 @@ impl<M: jsonrpc::Metadata, S: jsonrpc::Middleware<M>> ServerBuilder<M, S> {
 	}
 
 	/// Sets the maximum size of a request body in bytes (default is 5 MiB).
+	///
+	/// For compressed requests (`Content-Encoding: gzip` or `deflate`) the limit
+	/// applies to both the received and the decompressed body.
 	pub fn max_request_body_size(mut self, val: usize) -> Self {
 		self.max_request_body_size = val;
 		self
 	}
 
+	/// Sets a list of methods that are still served while the server is in maintenance mode.
+	///
+	/// See `Server::set_maintenance`.
+	pub fn maintenance_allowlist<T: Into<String>>(mut self, methods: Vec<T>) -> Self {
+		self.maintenance_allowlist = methods.into_iter().map(Into::into).collect();
+		self
+	}
+
+	/// Sets the maximum time to wait for a call to be handled.
+	///
+	/// Calls still pending after the timeout are answered with an error
+	/// (code `REQUEST_TIMEOUT_ERROR_CODE`) and the handler future is dropped.
+	/// When enabled, middleware sees each call of a batch as a separate request.
+	pub fn request_timeout(mut self, timeout: Duration) -> Self {
+		self.request_timeout = Some(timeout);
+		self
+	}
+
+	/// Sets the maximum time to wait for all calls of a batch request.
+	///
+	/// Calls still pending after the timeout are answered with an error
+	/// (code `BATCH_TIMEOUT_ERROR_CODE`) and the batch response is sent.
+	/// When enabled, middleware sees each call of the batch as a separate request.
+	pub fn batch_timeout(mut self, timeout: Duration) -> Self {
+		self.batch_timeout = Some(timeout);
+		self
+	}
+
+	/// Sets the maximum size of a single chunk of the response body in bytes.
+	///
+	/// Larger responses are sent in multiple chunks of given size.
+	/// By default the whole response is sent as a single chunk.
+	pub fn chunk_size(mut self, chunk_size: usize) -> Self {
+		assert!(chunk_size > 0, "Chunk size has to be greater than zero.");
+		self.chunk_size = Some(chunk_size);
+		self
+	}
+
+	/// Enables strict JSON-RPC 2.0 compliance checks.
+	///
+	/// In strict mode calls without `"jsonrpc": "2.0"` and empty batches are rejected
+	/// as invalid requests, regardless of the handler compatibility settings.
+	/// Requests with unknown fields or invalid `id` are always rejected.
+	pub fn strict(mut self, strict: bool) -> Self {
+		self.strict = strict;
+		self
+	}
+
+	/// Enables listing of available methods in response to `OPTIONS /?methods`.
+	///
+	/// The response body is a JSON array of names returned by `MetaIoHandler::methods`.
+	pub fn advertise_methods_on_options(mut self, advertise: bool) -> Self {
+		self.advertise_methods = advertise;
+		self
+	}
+
+	/// Sets the number of threads used to execute RPC handlers.
+	///
+	/// By default handlers are executed on the I/O threads,
+	/// use a dedicated pool if handlers do CPU-intensive work.
+	/// The pool is shared by all I/O threads.
+	pub fn handler_pool_size(mut self, size: usize) -> Self {
+		assert!(size > 0, "Handler pool size has to be greater than zero.");
+		self.handler_pool_size = Some(size);
+		self
+	}
+
+	/// Rejects requests with `503 Service Unavailable` and `Retry-After` header
+	/// while all threads of the handler pool are busy, instead of queueing them.
+	///
+	/// Has no effect unless `handler_pool_size` is configured.
+	pub fn reject_when_saturated(mut self, retry_after: Duration) -> Self {
+		self.saturated_retry_after = Some(retry_after);
+		self
+	}
+
+	/// Enables parsing API version from the request path, e.g. `POST /v2` given `/v` prefix.
+	///
+	/// Requests with a version outside of `versions` (or without any) are rejected with 404.
+	/// The version is passed to `MetaExtractor::read_versioned_metadata`.
+	pub fn path_version_prefix<T: Into<String>>(mut self, prefix: T, versions: Vec<u32>) -> Self {
+		self.path_version = Some((prefix.into(), versions));
+		self
+	}
+
+	/// Sets the maximal size of serialized response in bytes (unlimited by default).
+	///
+	/// Responses exceeding the limit are replaced with a "Response too large" error
+	/// (see `RESPONSE_TOO_LARGE_ERROR_CODE`) for every call.
+	pub fn max_response_size(mut self, size: usize) -> Self {
+		self.max_response_size = Some(size);
+		self
+	}
+
+	/// Configures the response to `OPTIONS` requests.
+	///
+	/// Default is `OptionsResponse::Ok` (`200 OK` with empty body).
+	pub fn options_response(mut self, response: OptionsResponse) -> Self {
+		self.options_response = response;
+		self
+	}
+
+	/// Enables deduplication of requests by `Idempotency-Key` header.
+	///
+	/// Responses to requests carrying the header are cached for `ttl`
+	/// and returned for subsequent requests with the same key and body without calling the handler again.
+	/// Requests reusing a key with a different body are rejected with `422 Unprocessable Entity`.
+	/// The cache is only consulted once the body is read and its signature (if required) verified.
+	/// At most `cache_size` responses are kept (oldest ones are evicted first).
+	/// The cache is shared by all server threads.
+	pub fn idempotency(mut self, cache_size: usize, ttl: Duration) -> Self {
+		assert!(cache_size > 0, "Idempotency cache size has to be greater than zero.");
+		self.idempotency = Some((cache_size, ttl));
+		self
+	}
+
+	/// Returns HTTP-level failures (e.g. 403, 405, 415) as JSON instead of plain text.
+	///
+	/// The body is `{"error":{"code":<HTTP status code>,"message":<description>}}`
+	/// sent with `Content-Type: application/json`.
+	pub fn http_errors_as_json(mut self, enabled: bool) -> Self {
+		self.http_errors_as_json = enabled;
+		self
+	}
+
+	/// Sets whether outputs of a batch are returned in the order of calls (default).
+	///
+	/// If disabled, outputs are returned in the order in which the calls completed,
+	/// so clients need to match them by `id`.
+	pub fn batch_ordered(mut self, ordered: bool) -> Self {
+		self.batch_ordered = ordered;
+		self
+	}
+
+	/// Injects delays and errors into processing of requests.
+	///
+	/// Meant only for testing how clients deal with slow or failing servers.
+	/// Faults apply to whole requests: a batch is delayed or failed as a single unit.
+	/// Failed calls return `INJECTED_FAULT_ERROR_CODE` error.
+	#[cfg(feature = "fault-injection")]
+	pub fn fault_injection(mut self, faults: FaultConfig) -> Self {
+		self.fault_injection = Some(faults);
+		self
+	}
+
+	/// Sets proxies allowed to determine the scheme of the request with `X-Forwarded-Proto` header (default: none).
+	///
+	/// The header is ignored in requests coming from other peers, so clients can't spoof it.
+	/// For requests of trusted proxies, requests are considered same-origin
+	/// only if the scheme of `Origin` matches the effective scheme (`http` if the header is missing).
+	pub fn trust_forwarded_proto(mut self, proxies: Vec<IpAddr>) -> Self {
+		self.trusted_proxies = proxies;
+		self
+	}
+
+	/// Configures the response to requests for unknown paths (e.g. unsupported API versions).
+	///
+	/// Default is `404 Not Found` with a plain text message.
+	pub fn not_found_response<T: Into<String>>(
+		mut self,
+		status: hyper::StatusCode,
+		body: T,
+		content_type: hyper::header::ContentType,
+	) -> Self {
+		self.not_found_response = Response {
+			code: status,
+			content_type: content_type,
+			content: body.into(),
+		};
+		self
+	}
+
+	/// Enables HTTP caching of results of given methods, e.g. read-only methods
+	/// registered with `add_cached_method`.
+	///
+	/// Successful responses to single calls of the methods carry `ETag` header computed over the result.
+	/// The method is still called for requests with matching `If-None-Match` header,
+	/// but they are answered with `304 Not Modified` and no body.
+	pub fn cacheable_methods(mut self, methods: Vec<String>) -> Self {
+		self.cacheable_methods = methods;
+		self
+	}
+
+	/// Configures a path answering `GET` requests with `200 OK` and `{"status":"ok"}` body,
+	/// e.g. for health checks of load balancers.
+	///
+	/// The requests are never passed to the RPC handler. Default is `None`.
+	pub fn health_check_path(mut self, path: Option<String>) -> Self {
+		self.health_check_path = path;
+		self
+	}
+
+	/// Sets a sink of access log records, one for each request answered by the RPC handler.
+	///
+	/// Batches are logged as a single record listing all of their calls.
+	pub fn request_logger(mut self, logger: Arc<RequestLogger>) -> Self {
+		self.request_logger = Some(logger);
+		self
+	}
+
+	/// Sets a hook invoked with timings of each executed request.
+	///
+	/// Timings distinguish time spent waiting for a free thread of the handler pool
+	/// (see `handler_pool_size`) from time spent executing the handler.
+	/// Calls of a batch are measured separately.
+	pub fn request_timings<F>(mut self, hook: F) -> Self where
+		F: Fn(&RequestTimings) + Send + Sync + 'static,
+	{
+		self.timings_hook = Some(Arc::new(hook));
+		self
+	}
+
+	/// Configures handling of batches containing multiple calls with the same id.
+	///
+	/// Default is `DuplicateBatchIdPolicy::Allow`.
+	pub fn duplicate_batch_id_policy(mut self, policy: DuplicateBatchIdPolicy) -> Self {
+		self.duplicate_batch_id_policy = policy;
+		self
+	}
+
+	/// Requires requests to be signed with given secret.
+	///
+	/// The signature is hex-encoded HMAC-SHA256 of the raw (possibly compressed) body,
+	/// sent in `header_name` header. Requests with missing or invalid signature
+	/// are rejected with `401 Unauthorized` before the body is parsed.
+	pub fn verify_signature<T: Into<Vec<u8>>>(mut self, secret: T, header_name: &str) -> Self {
+		self.signature = Some(Arc::new(SignatureVerifier::new(secret.into(), header_name.into())));
+		self
+	}
+
+	/// Configures compression of responses to clients sending `Accept-Encoding: gzip`.
+	///
+	/// Clients can still opt out for a single request with `X-No-Compression: true` header.
+	/// Default is `CompressionMode::Disabled`.
+	pub fn compression(mut self, mode: CompressionMode) -> Self {
+		self.compression = mode;
+		self
+	}
+
+	/// Sets a hook invoked on I/O errors of client connections (e.g. when a connection is reset
+	/// or the client goes away before the whole response is written).
+	pub fn on_connection_error<F>(mut self, hook: F) -> Self where
+		F: Fn(ConnectionError) + Send + Sync + 'static,
+	{
+		self.connection_error_hook = Some(Arc::new(hook));
+		self
+	}
+
+	/// Limits the number of concurrent connections from a single IP address.
+	///
+	/// Connections over the limit are closed right after they are accepted.
+	/// The limit is shared by all server threads.
+	pub fn max_connections_per_ip(mut self, limit: usize) -> Self {
+		self.max_connections_per_ip = Some(limit);
+		self
+	}
+
+	/// Caps the total size of request bodies buffered across all connections.
+	///
+	/// Each request reserves its `Content-Length` before its body is read,
+	/// bodies of unknown length reserve their chunks as they arrive. While the reservation
+	/// doesn't fit, reading of the body is paused until other requests release their buffers.
+	/// Requests declaring a body larger than the cap are rejected with `413 Payload Too Large`.
+	pub fn max_total_buffered_bytes(mut self, limit: usize) -> Self {
+		self.max_total_buffered_bytes = Some(limit);
+		self
+	}
+
+	/// Escapes all non-ASCII characters in JSON responses as `\uXXXX`.
+	///
+	/// Default is `false`.
+	pub fn ascii_only_output(mut self, ascii_only: bool) -> Self {
+		self.ascii_only_output = ascii_only;
+		self
+	}
+
+	/// Expects each connection to start with PROXY protocol (version 1) header.
+	///
+	/// The source address advertised in the header is used as the remote address
+	/// of requests (see `hyper::server::Request::remote_addr`).
+	/// Connections without a valid header are closed.
+	pub fn accept_proxy_protocol(mut self, accept: bool) -> Self {
+		self.accept_proxy_protocol = accept;
+		self
+	}
+
+	/// Sets a function rewriting errors returned by RPC calls, given the metadata of the request.
+	///
+	/// Allows to keep error codes and messages expected by older clients.
+	pub fn error_rewriter<F>(mut self, rewriter: F) -> Self where
+		F: Fn(&M, jsonrpc::Error) -> jsonrpc::Error + Send + Sync + 'static,
+	{
+		self.error_rewriter = Some(Arc::new(rewriter));
+		self
+	}
+
+	/// Adds `Server-Timing` header with the duration of the handler to RPC responses.
+	///
+	/// The duration (in milliseconds) is measured from dispatching the request
+	/// until the response is ready, so it includes time spent waiting for the handler pool.
+	/// Default is `false`.
+	pub fn server_timing(mut self, enabled: bool) -> Self {
+		self.server_timing = enabled;
+		self
+	}
+
+	/// Adds non-standard `method` field with the name of the called method to each output.
+	///
+	/// Meant for debugging only, since the responses no longer comply with the specification.
+	/// Default is `false`.
+	pub fn include_method_in_response(mut self, include: bool) -> Self {
+		self.include_method_in_response = include;
+		self
+	}
+
+	/// Sets the maximal time `Server::close` waits for requests in flight to finish.
+	///
+	/// Default is 5 seconds.
+	pub fn shutdown_timeout(mut self, timeout: Duration) -> Self {
+		self.shutdown_timeout = timeout;
+		self
+	}
+
+	/// Configures the format of outputs of RPC calls.
+	///
+	/// Default is `ErrorEnvelope::Standard`.
+	pub fn error_envelope(mut self, envelope: ErrorEnvelope) -> Self {
+		self.error_envelope = envelope;
+		self
+	}
+
+	/// Configures HTTP status of responses carrying a single RPC error.
+	///
+	/// Default is `ErrorStatus::Ok`, JSON-RPC errors are sent with `200 OK`.
+	pub fn error_status(mut self, mode: ErrorStatus) -> Self {
+		self.error_status = mode;
+		self
+	}
+
 	/// Start this JSON-RPC HTTP server trying to bind to specified `SocketAddr`.
-	pub fn start_http(self, addr: &SocketAddr) -> io::Result<Server> {
-		let cors_domains = self.cors_domains;
-		let cors_max_age = self.cors_max_age;
-		let request_middleware = self.request_middleware;
-		let allowed_hosts = self.allowed_hosts;
-		let jsonrpc_handler = Rpc {
-			handler: self.handler,
-			extractor: self.meta_extractor,
+	pub fn start_http(self, addr: &SocketAddr) -> io::Result<Server<M, S>> {
+		let routes: Routes<M, S> = if self.routes.is_empty() {
+			None
+		} else {
+			let extractor = self.meta_extractor.clone();
+			Some(Arc::new(self.routes.into_iter().map(|(path, (handler, options))| {
+				(path, Route::new(Rpc { handler, extractor: extractor.clone() }, options))
+			}).collect()))
 		};
-		let rest_api = self.rest_api;
-		let keep_alive = self.keep_alive;
-		let reuse_port = self.threads > 1;
+		let handler_pool = self.handler_pool_size.map(|size| {
+			futures_cpupool::Builder::new().pool_size(size).name_prefix("jsonrpc-http-handler-").create()
+		});
+		let saturation = match (self.handler_pool_size, self.saturated_retry_after) {
+			(Some(size), Some(retry_after)) => Some(Arc::new(PoolSaturation::new(size, retry_after))),
+			_ => None,
+		};
+		let config = Arc::new(ServerConfig {
+			jsonrpc_handler: Arc::new(RwLock::new(Rpc {
+				handler: self.handler,
+				extractor: self.meta_extractor,
+			})),
+			request_middleware: self.request_middleware,
+			cors_domains: self.cors_domains,
+			cors_max_age: self.cors_max_age,
+			allowed_hosts: self.allowed_hosts,
+			rest_api: self.rest_api,
+			keep_alive: self.keep_alive,
+			reuse_port: self.threads > 1,
+			max_request_body_size: self.max_request_body_size,
+			maintenance: Arc::new(RwLock::new(None)),
+			maintenance_allowlist: self.maintenance_allowlist,
+			batch_timeout: self.batch_timeout,
+			chunk_size: self.chunk_size,
+			strict: self.strict,
+			advertise_methods: self.advertise_methods,
+			handler_pool,
+			path_version: self.path_version,
+			max_response_size: self.max_response_size,
+			options_response: self.options_response,
+			idempotency: self.idempotency.map(|(cache_size, ttl)| {
+				Arc::new(Mutex::new(IdempotencyCache::new(cache_size, ttl)))
+			}),
+			http_errors_as_json: self.http_errors_as_json,
+			batch_ordered: self.batch_ordered,
+			fault_injection: self.fault_injection,
+			trusted_proxies: self.trusted_proxies,
+			not_found_response: self.not_found_response,
+			timings_hook: self.timings_hook,
+			duplicate_batch_id_policy: self.duplicate_batch_id_policy,
+			signature: self.signature,
+			saturation,
+			compression: self.compression,
+			buffers: self.max_total_buffered_bytes.map(|limit| Arc::new(BufferBudget::new(limit))),
+			ascii_only_output: self.ascii_only_output,
+			accept_proxy_protocol: self.accept_proxy_protocol,
+			error_envelope: self.error_envelope,
+			error_status: self.error_status,
+			cors_allow_headers: self.cors_allow_headers,
+			connection_error_hook: self.connection_error_hook,
+			peer_connections: self.max_connections_per_ip.map(|limit| Arc::new(PeerConnections::new(limit))),
+			error_rewriter: self.error_rewriter,
+			server_timing: self.server_timing,
+			include_method_in_response: self.include_method_in_response,
+			in_flight: Arc::new(InFlight::default()),
+			idle_timeout: self.idle_timeout,
+			request_timeout: self.request_timeout,
+			max_connection_age: self.max_connection_age,
+			routes,
+			request_logger: self.request_logger,
+			cacheable_methods: self.cacheable_methods,
+			tripped_methods: Default::default(),
+			health_check_path: self.health_check_path,
+		});
 
 		let (local_addr_tx, local_addr_rx) = mpsc::channel();
 		let (close, shutdown_signal) = oneshot::channel();
 		let eloop = self.remote.init_with_name("http.worker0")?;
-		let req_max_size = self.max_request_body_size;
-		serve(
-			(shutdown_signal, local_addr_tx),
-			eloop.remote(),
-			addr.to_owned(),
-			cors_domains.clone(),
-			cors_max_age,
-			request_middleware.clone(),
-			allowed_hosts.clone(),
-			jsonrpc_handler.clone(),
-			rest_api,
-			keep_alive,
-			reuse_port,
-			req_max_size,
-		);
+		serve((shutdown_signal, local_addr_tx), eloop.remote(), addr.to_owned(), config.clone());
 		let handles = (0..self.threads - 1).map(|i| {
 			let (local_addr_tx, local_addr_rx) = mpsc::channel();
 			let (close, shutdown_signal) = oneshot::channel();
 			let eloop = UninitializedRemote::Unspawned.init_with_name(format!("http.worker{}", i + 1))?;
-			serve(
-				(shutdown_signal, local_addr_tx),
-				eloop.remote(),
-				addr.to_owned(),
-				cors_domains.clone(),
-				cors_max_age,
-				request_middleware.clone(),
-				allowed_hosts.clone(),
-				jsonrpc_handler.clone(),
-				rest_api,
-				keep_alive,
-				reuse_port,
-				req_max_size,
-			);
+			serve((shutdown_signal, local_addr_tx), eloop.remote(), addr.to_owned(), config.clone());
 			Ok((eloop, close, local_addr_rx))
 		}).collect::<io::Result<Vec<_>>>()?;
 
@@ -395,11 +1124,16 @@ impl<M: jsonrpc::Metadata, S: jsonrpc::Middleware<M>> ServerBuilder<M, S> {
 		}).collect::<io::Result<(Vec<_>)>>()?;
 		handles.push((eloop, close));
 		let (remotes, close) = handles.into_iter().unzip();
+		let local_addr = local_addr?;
 
 		Ok(Server {
-			address: local_addr?,
+			address: local_addr,
+			threads: self.threads,
 			remote: Some(remotes),
 			close: Some(close),
+			allowed_hosts: server_utils::hosts::update(config.allowed_hosts.clone(), &local_addr),
+			config,
+			shutdown_timeout: self.shutdown_timeout,
 		})
 	}
 }
@@ -414,17 +1148,10 @@ fn serve<M: jsonrpc::Metadata, S: jsonrpc::Middleware<M>>(
 	signals: (oneshot::Receiver<()>, mpsc::Sender<io::Result<SocketAddr>>),
 	remote: tokio_core::reactor::Remote,
 	addr: SocketAddr,
-	cors_domains: CorsDomains,
-	cors_max_age: Option<u32>,
-	request_middleware: Arc<RequestMiddleware>,
-	allowed_hosts: AllowedHosts,
-	jsonrpc_handler: Rpc<M, S>,
-	rest_api: RestApi,
-	keep_alive: bool,
-	reuse_port: bool,
-	max_request_body_size: usize,
+	config: Arc<ServerConfig<M, S>>,
 ) {
 	let (shutdown_signal, local_addr_tx) = signals;
+	let reuse_port = config.reuse_port;
 	remote.spawn(move |handle| {
 		let handle1 = handle.clone();
 		let bind = move || {
@@ -462,25 +1189,47 @@ fn serve<M: jsonrpc::Metadata, S: jsonrpc::Middleware<M>>(
 
 		let handle = handle.clone();
 		bind_result.and_then(move |(listener, local_addr)| {
-			let allowed_hosts = server_utils::hosts::update(allowed_hosts, &local_addr);
+			let allowed_hosts = server_utils::hosts::update(config.allowed_hosts.clone(), &local_addr);
 
 			let http = {
 				let mut http = server::Http::new();
-				http.keep_alive(keep_alive);
+				http.keep_alive(config.keep_alive);
 				http.sleep_on_errors(true);
 				http
 			};
 			listener.incoming()
 				.for_each(move |(socket, addr)| {
-					http.bind_connection(&handle, socket, addr, ServerHandler::new(
-						jsonrpc_handler.clone(),
-						cors_domains.clone(),
-						cors_max_age,
-						allowed_hosts.clone(),
-						request_middleware.clone(),
-						rest_api,
-						max_request_body_size,
-					));
+					let registration = match config.peer_connections {
+						Some(ref connections) => match PeerConnections::register(connections, addr.ip()) {
+							Some(registration) => Some(registration),
+							None => {
+								debug!("Closing connection from {}: too many connections from the IP.", addr);
+								return Ok(());
+							},
+						},
+						None => None,
+					};
+					let socket = ObservedStream::new(socket, addr, config.connection_error_hook.clone())
+						.with_registration(registration)
+						.with_idle_timeout(config.idle_timeout, &handle)
+						.with_max_age(config.max_connection_age, &handle);
+					let service = ServerHandler::new(config.clone(), allowed_hosts.clone(), handle.clone());
+
+					if !config.accept_proxy_protocol {
+						http.bind_connection(&handle, socket, addr, service);
+						return Ok(());
+					}
+
+					// Replace the address of the proxy with the one advertised in the header.
+					let http = http.clone();
+					let handle2 = handle.clone();
+					handle.spawn(proxy::read_header(socket).then(move |result| {
+						match result {
+							Ok((socket, source)) => http.bind_connection(&handle2, socket, source.unwrap_or(addr), service),
+							Err(err) => debug!("Closing connection from {} with invalid PROXY header: {:?}", addr, err),
+						}
+						Ok(())
+					}));
 					Ok(())
 				})
 				.map_err(|e| {
@@ -512,25 +1261,109 @@ fn configure_port(_reuse: bool, _tcp: &net2::TcpBuilder) -> io::Result<()> {
 }
 
 /// jsonrpc http server instance
-pub struct Server {
+pub struct Server<M: jsonrpc::Metadata = (), S: jsonrpc::Middleware<M> = jsonrpc::NoopMiddleware> {
 	address: SocketAddr,
+	threads: usize,
 	remote: Option<Vec<Remote>>,
 	close: Option<Vec<oneshot::Sender<()>>>,
+	config: Arc<ServerConfig<M, S>>,
+	allowed_hosts: AllowedHosts,
+	shutdown_timeout: Duration,
 }
 
 const PROOF: &'static str = "Server is always Some until self is consumed.";
-impl Server {
+/// Interval (in milliseconds) of checking whether requests in flight finished while closing the server.
+const DRAIN_POLL_INTERVAL_MS: u64 = 5;
+impl<M: jsonrpc::Metadata, S: jsonrpc::Middleware<M>> Server<M, S> {
 	/// Returns address of this server
 	pub fn address(&self) -> &SocketAddr {
 		&self.address
 	}
 
+	/// Returns the number of threads serving requests.
+	pub fn threads(&self) -> usize {
+		self.threads
+	}
+
+	/// Returns origins allowed by CORS validation, or `["*"]` if the validation is disabled.
+	pub fn allowed_origins(&self) -> Vec<String> {
+		match self.config.cors_domains {
+			Some(ref origins) => origins.iter().map(ToString::to_string).collect(),
+			None => vec!["*".into()],
+		}
+	}
+
+	/// Returns hosts allowed by `Host` header validation (including the address of the server),
+	/// or `["*"]` if the validation is disabled.
+	pub fn allowed_hosts(&self) -> Vec<String> {
+		match self.allowed_hosts {
+			Some(ref hosts) => {
+				let mut hosts: Vec<String> = hosts.iter().map(|host| String::from(&**host)).collect();
+				hosts.sort();
+				hosts
+			},
+			None => vec!["*".into()],
+		}
+	}
+
+	/// Puts the server into (or takes it out of) maintenance mode.
+	///
+	/// While set, every method call is answered with given error,
+	/// unless all methods called by the request are on the maintenance allowlist.
+	/// Pass `None` to resume normal operation.
+	pub fn set_maintenance(&self, error: Option<jsonrpc::Error>) {
+		*self.config.maintenance.write().expect("Maintenance lock is never poisoned.") = error;
+	}
+
+	/// Trips the circuit breaker of given method, e.g. when a dependency of the method is failing.
+	///
+	/// Calls of the method fail right away with given error (e.g. `-32000` server error)
+	/// until the breaker is reset with `reset_method`. Other calls of a batch are handled as usual.
+	pub fn trip_method<T: Into<String>>(&self, name: T, error: jsonrpc::Error) {
+		self.config.tripped_methods.write().expect("Tripped methods lock is never poisoned.").insert(name.into(), error);
+	}
+
+	/// Resets the circuit breaker of given method tripped with `trip_method`.
+	pub fn reset_method(&self, name: &str) {
+		self.config.tripped_methods.write().expect("Tripped methods lock is never poisoned.").remove(name);
+	}
+
+	/// Replaces the handler of RPC requests.
+	///
+	/// Requests in flight are finished by the previous handler,
+	/// requests received afterwards are handled by the new one.
+	pub fn swap_handler<T>(&self, handler: T) where
+		T: Into<MetaIoHandler<M, S>>,
+	{
+		self.config.jsonrpc_handler.write().expect("Handler lock is never poisoned.").handler = Arc::new(handler.into());
+	}
+
+	/// Returns the number of bytes currently reserved for buffering request bodies.
+	///
+	/// Always `0` unless `ServerBuilder::max_total_buffered_bytes` is configured.
+	pub fn buffered_bytes(&self) -> usize {
+		self.config.buffers.as_ref().map_or(0, |buffers| buffers.used())
+	}
+
+	/// Returns the number of requests that are currently being processed.
+	pub fn requests_in_flight(&self) -> usize {
+		self.config.in_flight.count()
+	}
+
 	/// Closes the server.
+	///
+	/// Stops accepting new connections and waits for requests in flight to finish
+	/// (up to `ServerBuilder::shutdown_timeout`) before closing the event loops.
 	pub fn close(mut self) {
 		for close in self.close.take().expect(PROOF) {
 			let _ = close.send(());
 		}
 
+		let started = Instant::now();
+		while self.config.in_flight.count() > 0 && started.elapsed() < self.shutdown_timeout {
+			thread::sleep(Duration::from_millis(DRAIN_POLL_INTERVAL_MS));
+		}
+
 		for remote in self.remote.take().expect(PROOF) {
 			remote.close();
 		}
@@ -544,7 +1377,7 @@ impl Server {
 	}
 }
 
-impl Drop for Server {
+impl<M: jsonrpc::Metadata, S: jsonrpc::Middleware<M>> Drop for Server<M, S> {
 	fn drop(&mut self) {
 		self.remote.take().map(|remotes| {
 			for remote in remotes { remote.close(); }
@@ -0,0 +1,78 @@
+//! Parsing of PROXY protocol (version 1) header preceding the request.
+
+use std::io::{self, Read};
+use std::net::{IpAddr, SocketAddr};
+
+use jsonrpc::futures::{Async, Future, Poll};
+
+/// Maximal length of the header (including CRLF) according to the specification.
+const MAX_HEADER_LENGTH: usize = 107;
+const PROOF: &'static str = "Stream is always Some until the header is read.";
+
+/// Future reading PROXY header from the stream.
+///
+/// Resolves to the stream and the source address advertised by the proxy
+/// (`None` for `UNKNOWN` connections).
+pub struct ReadHeader<T> {
+	io: Option<T>,
+	line: Vec<u8>,
+}
+
+/// Reads PROXY header from given stream.
+///
+/// The stream is read byte-by-byte, so nothing past the header is consumed.
+pub fn read_header<T: Read>(io: T) -> ReadHeader<T> {
+	ReadHeader {
+		io: Some(io),
+		line: Vec::with_capacity(MAX_HEADER_LENGTH),
+	}
+}
+
+impl<T: Read> Future for ReadHeader<T> {
+	type Item = (T, Option<SocketAddr>);
+	type Error = io::Error;
+
+	fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+		loop {
+			let mut byte = [0; 1];
+			match self.io.as_mut().expect(PROOF).read(&mut byte) {
+				Ok(0) => return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Connection closed within PROXY header")),
+				Ok(_) => self.line.push(byte[0]),
+				Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(Async::NotReady),
+				Err(e) => return Err(e),
+			}
+
+			if self.line.ends_with(b"\r\n") {
+				let source = parse_header(&self.line[..self.line.len() - 2]).ok_or_else(invalid_header)?;
+				return Ok(Async::Ready((self.io.take().expect(PROOF), source)));
+			}
+			if self.line.len() >= MAX_HEADER_LENGTH {
+				return Err(invalid_header());
+			}
+		}
+	}
+}
+
+fn invalid_header() -> io::Error {
+	io::Error::new(io::ErrorKind::InvalidData, "Invalid PROXY header")
+}
+
+/// Parses header line (without CRLF), e.g. `PROXY TCP4 192.168.0.1 192.168.0.11 56324 443`.
+fn parse_header(line: &[u8]) -> Option<Option<SocketAddr>> {
+	let line = ::std::str::from_utf8(line).ok()?;
+	let mut parts = line.split(' ');
+	if parts.next() != Some("PROXY") {
+		return None;
+	}
+
+	match parts.next()? {
+		"UNKNOWN" => Some(None),
+		"TCP4" | "TCP6" => {
+			let source = parts.next()?.parse::<IpAddr>().ok()?;
+			let _destination = parts.next()?;
+			let port = parts.next()?.parse::<u16>().ok()?;
+			Some(Some(SocketAddr::new(source, port)))
+		},
+		_ => None,
+	}
+}
@@ -5,7 +5,7 @@ use hyper::server;
 pub use hyper::{header, Method, StatusCode};
 
 /// Simple server response structure
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Response {
 	/// Response code
 	pub code: StatusCode,
@@ -30,6 +30,24 @@ impl Response {
 		}
 	}
 
+	/// Create a response with empty body and 204 No Content status code.
+	pub fn no_content() -> Self {
+		Response {
+			code: StatusCode::NoContent,
+			content_type: header::ContentType::plaintext(),
+			content: String::new(),
+		}
+	}
+
+	/// Create a response for requests with up-to-date cached result.
+	pub fn not_modified() -> Self {
+		Response {
+			code: StatusCode::NotModified,
+			content_type: header::ContentType::plaintext(),
+			content: String::new(),
+		}
+	}
+
 	/// Create a response for internal error.
 	pub fn internal_error() -> Self {
 		Response {
@@ -39,6 +57,42 @@ impl Response {
 		}
 	}
 
+	/// Create a response for requests with missing or invalid signature.
+	pub fn unauthorized() -> Self {
+		Response {
+			code: StatusCode::Unauthorized,
+			content_type: header::ContentType::plaintext(),
+			content: "Request signature is missing or invalid.\n".to_owned(),
+		}
+	}
+
+	/// Create a response for requests reusing an idempotency key with a different body.
+	pub fn idempotency_key_reused() -> Self {
+		Response {
+			code: StatusCode::UnprocessableEntity,
+			content_type: header::ContentType::plaintext(),
+			content: "Idempotency key was already used for a different request.\n".to_owned(),
+		}
+	}
+
+	/// Create a response for requests rejected by the authorization check of a route.
+	pub fn route_unauthorized() -> Self {
+		Response {
+			code: StatusCode::Unauthorized,
+			content_type: header::ContentType::plaintext(),
+			content: "Request is not authorized.\n".to_owned(),
+		}
+	}
+
+	/// Create a response for requests rejected because the server is busy.
+	pub fn service_unavailable() -> Self {
+		Response {
+			code: StatusCode::ServiceUnavailable,
+			content_type: header::ContentType::plaintext(),
+			content: "Server is busy, try again later.\n".to_owned(),
+		}
+	}
+
 	/// Create a response for not allowed hosts.
 	pub fn host_not_allowed() -> Self {
 		Response {
@@ -57,6 +111,33 @@ impl Response {
 		}
 	}
 
+	/// Create a response for unsupported content encoding.
+	pub fn unsupported_content_encoding() -> Self {
+		Response {
+			code: StatusCode::UnsupportedMediaType,
+			content_type: header::ContentType::plaintext(),
+			content: "Supplied content encoding is not supported. Use gzip, deflate or identity\n".to_owned(),
+		}
+	}
+
+	/// Create a response for not found resource.
+	pub fn not_found() -> Self {
+		Response {
+			code: StatusCode::NotFound,
+			content_type: header::ContentType::plaintext(),
+			content: "Requested resource was not found.\n".to_owned(),
+		}
+	}
+
+	/// Create a response for health check requests.
+	pub fn health_check() -> Self {
+		Response {
+			code: StatusCode::Ok,
+			content_type: header::ContentType::json(),
+			content: "{\"status\":\"ok\"}\n".to_owned(),
+		}
+	}
+
 	/// Create a response for disallowed method used.
 	pub fn method_not_allowed() -> Self {
 		Response {
@@ -84,6 +165,11 @@ impl Response {
 		}
 	}
 
+	/// Create a response for request body exceeding the maximal size.
+	pub fn request_too_large() -> Self {
+		Self::too_large("Request body size exceeds allowed maximum.\n")
+	}
+
 	/// Create a response for too large (413)
 	pub fn too_large<S: Into<String>>(msg: S) -> Self {
 		Response {
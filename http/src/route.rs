@@ -0,0 +1,81 @@
+//! Separate handlers of requests to specific paths.
+
+use std::fmt;
+use std::sync::Arc;
+
+use hyper::server;
+use jsonrpc::{Metadata, Middleware};
+use {AccessControlAllowOrigin, CorsDomains, DomainsValidation, Rpc};
+
+/// Check deciding whether a request to a route is authorized.
+type Authorization = Option<Arc<Fn(&server::Request) -> bool + Send + Sync>>;
+
+/// Options of a route added with `ServerBuilder::route`.
+#[derive(Default, Clone)]
+pub struct RouteOptions {
+	cors_domains: Option<CorsDomains>,
+	authorization: Authorization,
+}
+
+impl fmt::Debug for RouteOptions {
+	fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+		fmt.debug_struct("RouteOptions")
+			.field("cors_domains", &self.cors_domains)
+			.field("authorization", &self.authorization.is_some())
+			.finish()
+	}
+}
+
+impl RouteOptions {
+	/// Creates options using CORS configuration of the server and authorizing all requests.
+	pub fn new() -> Self {
+		Default::default()
+	}
+
+	/// Configures CORS of the route, overriding `ServerBuilder::cors`.
+	pub fn cors(mut self, cors_domains: DomainsValidation<AccessControlAllowOrigin>) -> Self {
+		self.cors_domains = Some(cors_domains.into());
+		self
+	}
+
+	/// Sets a check of requests to the route (e.g. of `Authorization` header).
+	///
+	/// Requests failing the check are rejected with `401 Unauthorized` before the body is read.
+	pub fn authorize<F>(mut self, check: F) -> Self where
+		F: Fn(&server::Request) -> bool + Send + Sync + 'static,
+	{
+		self.authorization = Some(Arc::new(check));
+		self
+	}
+}
+
+/// Handler and options of requests to a single path.
+pub struct Route<M: Metadata, S: Middleware<M>> {
+	rpc: Rpc<M, S>,
+	options: RouteOptions,
+}
+
+impl<M: Metadata, S: Middleware<M>> Route<M, S> {
+	/// Creates new route served by given handler.
+	pub fn new(rpc: Rpc<M, S>, options: RouteOptions) -> Self {
+		Route {
+			rpc,
+			options,
+		}
+	}
+
+	/// Returns the handler of the route.
+	pub fn rpc(&self) -> &Rpc<M, S> {
+		&self.rpc
+	}
+
+	/// Returns CORS configuration of the route, `default` unless overridden.
+	pub fn cors_domains(&self, default: &CorsDomains) -> CorsDomains {
+		self.options.cors_domains.as_ref().unwrap_or(default).clone()
+	}
+
+	/// Returns `true` if the request passes the authorization check (if any).
+	pub fn is_authorized(&self, request: &server::Request) -> bool {
+		self.options.authorization.as_ref().map_or(true, |check| check(request))
+	}
+}
@@ -0,0 +1,50 @@
+//! Verification of HMAC signatures of request bodies.
+
+use std::str;
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+/// Verifies hex-encoded HMAC-SHA256 signatures of request bodies sent in a header.
+#[derive(Debug)]
+pub struct SignatureVerifier {
+	secret: Vec<u8>,
+	header: String,
+}
+
+impl SignatureVerifier {
+	/// Creates new verifier of signatures sent in `header` made with given `secret`.
+	pub fn new(secret: Vec<u8>, header: String) -> Self {
+		SignatureVerifier {
+			secret,
+			header,
+		}
+	}
+
+	/// Returns name of the header carrying the signature.
+	pub fn header(&self) -> &str {
+		&self.header
+	}
+
+	/// Returns `true` if `signature` is a valid signature of `body`.
+	pub fn verify(&self, body: &[u8], signature: &str) -> bool {
+		let signature = match from_hex(signature) {
+			Some(signature) => signature,
+			None => return false,
+		};
+
+		let mut mac = Hmac::<Sha256>::new_varkey(&self.secret).expect("HMAC accepts keys of any size; qed");
+		mac.input(body);
+		mac.verify(&signature).is_ok()
+	}
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+	if hex.len() % 2 != 0 {
+		return None;
+	}
+
+	hex.as_bytes().chunks(2)
+		.map(|byte| str::from_utf8(byte).ok().and_then(|byte| u8::from_str_radix(byte, 16).ok()))
+		.collect()
+}
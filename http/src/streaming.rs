@@ -0,0 +1,86 @@
+//! Streaming multiple results of a single call.
+
+use hyper;
+use jsonrpc::{self as core, BoxStream, Value};
+use jsonrpc::futures::{Async, Future, Poll, Stream};
+use jsonrpc::futures::sync::mpsc;
+use server_utils::tokio_core::reactor::Timeout;
+
+/// Serializes a single output, applying the same processing as to regular responses.
+pub type WriteOutput = Box<Fn(core::Output) -> String + Send>;
+
+/// Stream of response body chunks, one output (terminated with a new line) per result.
+///
+/// The stream ends after the first error, which is sent as a failure output.
+pub struct Outputs {
+	results: Option<BoxStream<Value>>,
+	id: core::Id,
+	jsonrpc: Option<core::Version>,
+	write: WriteOutput,
+	timeout: Option<(Timeout, core::Error)>,
+}
+
+impl Outputs {
+	/// Creates outputs of results of a call with given id.
+	pub fn new(results: BoxStream<Value>, id: core::Id, jsonrpc: Option<core::Version>, write: WriteOutput) -> Self {
+		Outputs {
+			results: Some(results),
+			id,
+			jsonrpc,
+			write,
+			timeout: None,
+		}
+	}
+
+	/// Ends the stream with given error if it's not finished when `timeout` fires.
+	pub fn with_timeout(mut self, timeout: Timeout, error: core::Error) -> Self {
+		self.timeout = Some((timeout, error));
+		self
+	}
+
+	/// Returns the timeout error once the timeout fired.
+	fn poll_timeout(&mut self) -> Option<core::Error> {
+		let fired = match self.timeout {
+			Some((ref mut timeout, _)) => match timeout.poll() {
+				Ok(Async::NotReady) => false,
+				Ok(Async::Ready(())) | Err(_) => true,
+			},
+			None => false,
+		};
+		if fired {
+			self.timeout.take().map(|(_, error)| error)
+		} else {
+			None
+		}
+	}
+}
+
+impl Stream for Outputs {
+	type Item = Result<hyper::Chunk, hyper::Error>;
+	type Error = mpsc::SendError<Self::Item>;
+
+	fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+		if self.results.is_none() {
+			return Ok(Async::Ready(None));
+		}
+		let polled = match self.poll_timeout() {
+			Some(error) => Err(error),
+			None => self.results.as_mut().expect("Results are checked above; qed").poll(),
+		};
+		let result = match polled {
+			Ok(Async::NotReady) => return Ok(Async::NotReady),
+			Ok(Async::Ready(None)) => {
+				self.results = None;
+				return Ok(Async::Ready(None));
+			},
+			Ok(Async::Ready(Some(value))) => Ok(value),
+			Err(error) => {
+				self.results = None;
+				Err(error)
+			},
+		};
+
+		let output = (self.write)(core::Output::from(result, self.id.clone(), self.jsonrpc));
+		Ok(Async::Ready(Some(Ok(format!("{}\n", output).into()))))
+	}
+}
@@ -1,11 +1,15 @@
 extern crate jsonrpc_core;
 
-use std::str::Lines;
-use std::net::TcpStream;
+use std::str::{self, Lines};
+use std::net::{SocketAddr, TcpStream};
 use std::io::{Read, Write};
-use self::jsonrpc_core::{IoHandler, Params, Value, Error};
+use self::jsonrpc_core::{Compatibility, IoHandler, MetaIoHandler, SharedIoHandler, Params, Value, Error, ErrorCode};
 
 use self::jsonrpc_core::futures::{self, Future};
+use self::jsonrpc_core::serde_json;
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use rmp_serde;
 use super::*;
 
 fn serve_hosts(hosts: Vec<Host>) -> Server {
@@ -77,9 +81,61 @@ fn read_block(lines: &mut Lines) -> String {
 	block
 }
 
-fn request(server: Server, request: &str) -> Response {
-	let mut req = TcpStream::connect(server.address()).unwrap();
-	req.write_all(request.as_bytes()).unwrap();
+/// Middleware answering every request with an error, without passing it to the handler.
+struct Rejecting;
+
+impl jsonrpc_core::Middleware<()> for Rejecting {
+	type Future = jsonrpc_core::FutureResponse;
+
+	fn on_request<F, X>(&self, _request: jsonrpc_core::Request, _meta: (), _next: F) -> Self::Future where
+		F: FnOnce(jsonrpc_core::Request, ()) -> X + Send,
+		X: Future<Item = Option<jsonrpc_core::Response>, Error = ()> + Send + 'static,
+	{
+		let error = Error {
+			code: ErrorCode::ServerError(-32010),
+			message: "Rejected".into(),
+			data: None,
+		};
+		Box::new(futures::finished(Some(jsonrpc_core::Response::from(error, Some(jsonrpc_core::Version::V2)))))
+	}
+}
+
+fn request<M: jsonrpc_core::Metadata, S: jsonrpc_core::Middleware<M>>(server: Server<M, S>, request: &str) -> Response {
+	request_to(server.address(), request.as_bytes())
+}
+
+fn request_raw(address: &SocketAddr, request: &[u8]) -> Vec<u8> {
+	let mut req = TcpStream::connect(address).unwrap();
+	req.write_all(request).unwrap();
+
+	let mut response = Vec::new();
+	req.read_to_end(&mut response).unwrap();
+	response
+}
+
+/// Splits raw response into headers and decoded chunks of the body.
+fn read_chunks(response: &[u8]) -> (String, Vec<Vec<u8>>) {
+	let headers_end = response.windows(4).position(|w| w == b"\r\n\r\n").unwrap();
+	let headers = String::from_utf8_lossy(&response[..headers_end]).into_owned();
+
+	let mut chunks = Vec::new();
+	let mut body = &response[headers_end + 4..];
+	loop {
+		let size_end = body.windows(2).position(|w| w == b"\r\n").unwrap();
+		let size = usize::from_str_radix(str::from_utf8(&body[..size_end]).unwrap(), 16).unwrap();
+		if size == 0 {
+			break;
+		}
+		chunks.push(body[size_end + 2..size_end + 2 + size].to_vec());
+		body = &body[size_end + 2 + size + 2..];
+	}
+
+	(headers, chunks)
+}
+
+fn request_to(address: &SocketAddr, request: &[u8]) -> Response {
+	let mut req = TcpStream::connect(address).unwrap();
+	req.write_all(request).unwrap();
 
 	let mut response = String::new();
 	req.read_to_string(&mut response).unwrap();
@@ -117,6 +173,105 @@ fn should_return_method_not_allowed_for_get() {
 	assert_eq!(response.body, "3D\nUsed HTTP Method is not allowed. POST or OPTIONS is required\n".to_owned());
 }
 
+#[test]
+fn should_respond_to_health_checks_when_configured() {
+	// given
+	let server = ServerBuilder::new(IoHandler::default())
+		.health_check_path(Some("/health".into()))
+		.start_http(&"127.0.0.1:0".parse().unwrap())
+		.unwrap();
+
+	// when
+	let response = request(server,
+		"\
+			GET /health HTTP/1.1\r\n\
+			Host: 127.0.0.1:8080\r\n\
+			Connection: close\r\n\
+			\r\n\
+		"
+	);
+
+	// then
+	assert_eq!(response.status, "HTTP/1.1 200 OK".to_owned());
+	assert_eq!(response.body, "10\n{\"status\":\"ok\"}\n".to_owned());
+}
+
+#[test]
+fn should_return_method_not_allowed_for_get_of_other_paths_with_health_checks() {
+	// given
+	let server = ServerBuilder::new(IoHandler::default())
+		.health_check_path(Some("/health".into()))
+		.start_http(&"127.0.0.1:0".parse().unwrap())
+		.unwrap();
+
+	// when
+	let response = request(server,
+		"\
+			GET / HTTP/1.1\r\n\
+			Host: 127.0.0.1:8080\r\n\
+			Connection: close\r\n\
+			\r\n\
+		"
+	);
+
+	// then
+	assert_eq!(response.status, "HTTP/1.1 405 Method Not Allowed".to_owned());
+	assert_eq!(response.body, "3D\nUsed HTTP Method is not allowed. POST or OPTIONS is required\n".to_owned());
+}
+
+#[test]
+fn should_return_http_errors_as_json_when_enabled() {
+	// given
+	let server = ServerBuilder::new(IoHandler::default())
+		.http_errors_as_json(true)
+		.start_http(&"127.0.0.1:0".parse().unwrap())
+		.unwrap();
+
+	// when
+	let response = request(server,
+		"\
+			GET / HTTP/1.1\r\n\
+			Host: 127.0.0.1:8080\r\n\
+			Connection: close\r\n\
+			\r\n\
+			I shouldn't be read.\r\n\
+		"
+	);
+
+	// then
+	assert_eq!(response.status, "HTTP/1.1 405 Method Not Allowed".to_owned());
+	assert!(response.headers.contains("Content-Type: application/json"), "Headers missing in {}", response.headers);
+	assert_eq!(
+		response.body,
+		"60\n{\"error\":{\"code\":405,\"message\":\"Used HTTP Method is not allowed. POST or OPTIONS is required\"}}\n".to_owned()
+	);
+}
+
+#[test]
+fn should_include_date_header() {
+	// given
+	let server = serve();
+
+	// when
+	let response = request(server,
+		"\
+			POST / HTTP/1.1\r\n\
+			Host: 127.0.0.1:8080\r\n\
+			Connection: close\r\n\
+			Content-Type: application/json\r\n\
+			Content-Length: 0\r\n\
+			\r\n\
+		"
+	);
+
+	// then
+	let date = response.headers.lines()
+		.find(|line| line.starts_with("Date: "))
+		.map(|line| line["Date: ".len()..].to_owned());
+	assert!(date.is_some(), "Date header missing in {}", response.headers);
+	assert!(date.unwrap().parse::<hyper::header::HttpDate>().is_ok());
+}
+
 #[test]
 fn should_return_unsupported_media_type_if_not_json() {
 	// given
@@ -210,6 +365,30 @@ fn should_return_empty_response_for_notification() {
 	assert_eq!(response.body, "0\n".to_owned());
 }
 
+#[test]
+fn should_return_empty_response_for_batch_of_notifications() {
+	// given
+	let server = serve();
+
+	// when
+	let req = r#"[{"jsonrpc":"2.0","method":"hello"},{"jsonrpc":"2.0","method":"x"}]"#;
+	let response = request(server,
+		&format!("\
+			POST / HTTP/1.1\r\n\
+			Host: 127.0.0.1:8080\r\n\
+			Connection: close\r\n\
+			Content-Type: application/json\r\n\
+			Content-Length: {}\r\n\
+			\r\n\
+			{}\r\n\
+		", req.as_bytes().len(), req)
+	);
+
+	// then
+	assert_eq!(response.status, "HTTP/1.1 200 OK".to_owned());
+	assert_eq!(response.body, "0\n".to_owned());
+}
+
 
 #[test]
 fn should_return_method_not_found() {
@@ -363,112 +542,142 @@ fn should_return_proper_headers_on_options() {
 }
 
 #[test]
-fn should_add_cors_header_for_null_origin() {
+fn should_allow_only_requested_and_permitted_headers_on_preflight() {
 	// given
-	let server = serve();
+	let server = ServerBuilder::new(IoHandler::default())
+		.cors(DomainsValidation::AllowOnly(vec![AccessControlAllowOrigin::Value("http://parity.io".into())]))
+		.cors_allow_headers(vec!["X-Api-Key"])
+		.cors_max_age(1_000)
+		.start_http(&"127.0.0.1:0".parse().unwrap())
+		.unwrap();
 
 	// when
-	let req = r#"{"jsonrpc":"2.0","id":1,"method":"x"}"#;
 	let response = request(server,
-		&format!("\
-			POST / HTTP/1.1\r\n\
+		"\
+			OPTIONS / HTTP/1.1\r\n\
 			Host: 127.0.0.1:8080\r\n\
-			Origin: null\r\n\
+			Origin: http://parity.io\r\n\
+			Access-Control-Request-Method: POST\r\n\
+			Access-Control-Request-Headers: content-type, x-api-key, x-unknown\r\n\
 			Connection: close\r\n\
-			Content-Type: application/json\r\n\
-			Content-Length: {}\r\n\
+			Content-Length: 0\r\n\
 			\r\n\
-			{}\r\n\
-		", req.as_bytes().len(), req)
+		"
 	);
 
 	// then
 	assert_eq!(response.status, "HTTP/1.1 200 OK".to_owned());
-	assert_eq!(response.body, method_not_found());
-	assert!(response.headers.contains("Access-Control-Allow-Origin: null"), "Headers missing in {}", response.headers);
+	assert!(response.headers.contains("Access-Control-Allow-Methods: OPTIONS, POST"), "Headers missing in {}", response.headers);
+	assert!(response.headers.contains("Access-Control-Allow-Headers: content-type, x-api-key\n"), "Headers missing in {}", response.headers);
+	assert!(response.headers.contains("Access-Control-Max-Age: 1000"), "Headers missing in {}", response.headers);
+	assert!(response.headers.contains("Access-Control-Allow-Origin: http://parity.io"), "Headers missing in {}", response.headers);
 }
 
 #[test]
-fn should_add_cors_header_for_null_origin_when_all() {
+fn should_return_configured_response_on_options() {
 	// given
-	let server = serve_rest(RestApi::Secure, true, None);
+	let mut io = IoHandler::default();
+	io.add_method("hello", |_params: Params| Ok(Value::String("world".into())));
+	let server = ServerBuilder::new(io)
+		.options_response(OptionsResponse::NoContent)
+		.start_http(&"127.0.0.1:0".parse().unwrap())
+		.unwrap();
 
 	// when
-	let req = r#"{"jsonrpc":"2.0","id":1,"method":"x"}"#;
 	let response = request(server,
-		&format!("\
-			POST / HTTP/1.1\r\n\
+		"\
+			OPTIONS / HTTP/1.1\r\n\
 			Host: 127.0.0.1:8080\r\n\
-			Origin: null\r\n\
 			Connection: close\r\n\
-			Content-Type: application/json\r\n\
-			Content-Length: {}\r\n\
+			Content-Length: 0\r\n\
 			\r\n\
-			{}\r\n\
-		", req.as_bytes().len(), req)
+		"
 	);
 
 	// then
-	assert_eq!(response.status, "HTTP/1.1 200 OK".to_owned());
-	assert_eq!(response.body, method_not_found());
-	assert!(response.headers.contains("Access-Control-Allow-Origin: null"), "Headers missing in {}", response.headers);
+	assert_eq!(response.status, "HTTP/1.1 204 No Content".to_owned());
+	assert!(response.headers.contains("Allow: OPTIONS, POST"), "Headers missing in {}", response.headers);
+	assert_eq!(response.body, "");
 }
 
 #[test]
-fn should_not_allow_request_larger_than_max() {
-	let server = ServerBuilder::new(IoHandler::default())
-		.max_request_body_size(7)
+fn should_list_methods_on_options_when_enabled() {
+	// given
+	let mut io = IoHandler::default();
+	io.add_method("hello", |_params: Params| Ok(Value::String("world".into())));
+	let server = ServerBuilder::new(io)
+		.advertise_methods_on_options(true)
 		.start_http(&"127.0.0.1:0".parse().unwrap())
 		.unwrap();
 
+	// when
 	let response = request(server,
 		"\
-			POST / HTTP/1.1\r\n\
+			OPTIONS /?methods HTTP/1.1\r\n\
 			Host: 127.0.0.1:8080\r\n\
 			Connection: close\r\n\
-			Content-Length: 8\r\n\
-			Content-Type: application/json\r\n\
+			Content-Length: 0\r\n\
 			\r\n\
-			12345678\r\n\
 		"
 	);
-	assert_eq!(response.status, "HTTP/1.1 413 Payload Too Large".to_owned());
+
+	// then
+	assert_eq!(response.status, "HTTP/1.1 200 OK".to_owned());
+	assert!(response.headers.contains("Allow: OPTIONS, POST"), "Headers missing in {}", response.headers);
+	assert_eq!(response.body, "A\n[\"hello\"]\n");
 }
 
 #[test]
-fn should_reject_invalid_hosts() {
+fn should_use_forwarded_proto_for_same_origin_check_when_trusted() {
 	// given
-	let server = serve_hosts(vec!["parity.io".into()]);
+	let server = ServerBuilder::new(IoHandler::default())
+		.cors(DomainsValidation::AllowOnly(vec![AccessControlAllowOrigin::Value("parity.io".into())]))
+		.trust_forwarded_proto(vec!["127.0.0.1".parse().unwrap()])
+		.start_http(&"127.0.0.1:0".parse().unwrap())
+		.unwrap();
+	let addr = server.address().clone();
 
 	// when
 	let req = r#"{"jsonrpc":"2.0","id":1,"method":"x"}"#;
-	let response = request(server,
-		&format!("\
+	let request_with_proto = |proto: &str| format!("\
 			POST / HTTP/1.1\r\n\
 			Host: 127.0.0.1:8080\r\n\
+			Origin: https://127.0.0.1:8080\r\n\
+			X-Forwarded-Proto: {}\r\n\
 			Connection: close\r\n\
 			Content-Type: application/json\r\n\
 			Content-Length: {}\r\n\
 			\r\n\
 			{}\r\n\
-		", req.as_bytes().len(), req)
-	);
+		", proto, req.as_bytes().len(), req);
+	let response1 = request_to(&addr, request_with_proto("https").as_bytes());
+	let response2 = request(server, &request_with_proto("http"));
 
 	// then
-	assert_eq!(response.status, "HTTP/1.1 403 Forbidden".to_owned());
-	assert_eq!(response.body, invalid_host());
+	assert_eq!(response1.status, "HTTP/1.1 200 OK".to_owned());
+	assert!(!response1.headers.contains("Access-Control-Allow-Origin"), "Unexpected CORS header in {}", response1.headers);
+	assert_eq!(response1.body, method_not_found());
+	assert_eq!(response2.status, "HTTP/1.1 403 Forbidden".to_owned());
+	assert_eq!(response2.body, cors_invalid());
 }
 
 #[test]
-fn should_reject_missing_host() {
+fn should_ignore_forwarded_proto_of_untrusted_peers() {
 	// given
-	let server = serve_hosts(vec!["parity.io".into()]);
+	let server = ServerBuilder::new(IoHandler::default())
+		.cors(DomainsValidation::AllowOnly(vec![AccessControlAllowOrigin::Value("parity.io".into())]))
+		.trust_forwarded_proto(vec!["10.0.0.1".parse().unwrap()])
+		.start_http(&"127.0.0.1:0".parse().unwrap())
+		.unwrap();
 
 	// when
 	let req = r#"{"jsonrpc":"2.0","id":1,"method":"x"}"#;
 	let response = request(server,
 		&format!("\
 			POST / HTTP/1.1\r\n\
+			Host: 127.0.0.1:8080\r\n\
+			Origin: https://127.0.0.1:8080\r\n\
+			X-Forwarded-Proto: http\r\n\
 			Connection: close\r\n\
 			Content-Type: application/json\r\n\
 			Content-Length: {}\r\n\
@@ -478,47 +687,39 @@ fn should_reject_missing_host() {
 	);
 
 	// then
-	assert_eq!(response.status, "HTTP/1.1 403 Forbidden".to_owned());
-	assert_eq!(response.body, invalid_host());
+	assert_eq!(response.status, "HTTP/1.1 200 OK".to_owned());
+	assert_eq!(response.body, method_not_found());
 }
 
 #[test]
-fn should_allow_if_host_is_valid() {
+fn should_expose_allowed_origins_and_hosts() {
 	// given
 	let server = serve_hosts(vec!["parity.io".into()]);
-
-	// when
-	let req = r#"{"jsonrpc":"2.0","id":1,"method":"x"}"#;
-	let response = request(server,
-		&format!("\
-			POST / HTTP/1.1\r\n\
-			Host: parity.io\r\n\
-			Connection: close\r\n\
-			Content-Type: application/json\r\n\
-			Content-Length: {}\r\n\
-			\r\n\
-			{}\r\n\
-		", req.as_bytes().len(), req)
-	);
+	let port = server.address().port();
 
 	// then
-	assert_eq!(response.status, "HTTP/1.1 200 OK".to_owned());
-	assert_eq!(response.body, method_not_found());
+	assert_eq!(server.allowed_origins(), vec!["parity.io".to_owned()]);
+	assert_eq!(server.allowed_hosts(), vec![
+		format!("127.0.0.1:{}", port),
+		format!("localhost:{}", port),
+		"parity.io".to_owned(),
+	]);
 }
 
 #[test]
-fn should_allow_application_json_utf8() {
+fn should_add_cors_header_for_null_origin() {
 	// given
-	let server = serve_hosts(vec!["parity.io".into()]);
+	let server = serve();
 
 	// when
 	let req = r#"{"jsonrpc":"2.0","id":1,"method":"x"}"#;
 	let response = request(server,
 		&format!("\
 			POST / HTTP/1.1\r\n\
-			Host: parity.io\r\n\
+			Host: 127.0.0.1:8080\r\n\
+			Origin: null\r\n\
 			Connection: close\r\n\
-			Content-Type: application/json; charset=utf-8\r\n\
+			Content-Type: application/json\r\n\
 			Content-Length: {}\r\n\
 			\r\n\
 			{}\r\n\
@@ -528,65 +729,2329 @@ fn should_allow_application_json_utf8() {
 	// then
 	assert_eq!(response.status, "HTTP/1.1 200 OK".to_owned());
 	assert_eq!(response.body, method_not_found());
+	assert!(response.headers.contains("Access-Control-Allow-Origin: null"), "Headers missing in {}", response.headers);
 }
 
 #[test]
-fn should_always_allow_the_bind_address() {
+fn should_add_cors_header_for_null_origin_when_all() {
 	// given
-	let server = serve_hosts(vec!["parity.io".into()]);
-	let addr = server.address().clone();
+	let server = serve_rest(RestApi::Secure, true, None);
 
 	// when
 	let req = r#"{"jsonrpc":"2.0","id":1,"method":"x"}"#;
 	let response = request(server,
 		&format!("\
 			POST / HTTP/1.1\r\n\
-			Host: {}\r\n\
+			Host: 127.0.0.1:8080\r\n\
+			Origin: null\r\n\
 			Connection: close\r\n\
 			Content-Type: application/json\r\n\
 			Content-Length: {}\r\n\
 			\r\n\
 			{}\r\n\
-		", addr, req.as_bytes().len(), req)
+		", req.as_bytes().len(), req)
 	);
 
 	// then
 	assert_eq!(response.status, "HTTP/1.1 200 OK".to_owned());
 	assert_eq!(response.body, method_not_found());
+	assert!(response.headers.contains("Access-Control-Allow-Origin: null"), "Headers missing in {}", response.headers);
 }
 
 #[test]
-fn should_always_allow_the_bind_address_as_localhost() {
-	// given
-	let server = serve_hosts(vec![]);
-	let addr = server.address().clone();
+fn should_not_allow_request_larger_than_max() {
+	let server = ServerBuilder::new(IoHandler::default())
+		.max_request_body_size(7)
+		.start_http(&"127.0.0.1:0".parse().unwrap())
+		.unwrap();
 
-	// when
-	let req = r#"{"jsonrpc":"2.0","id":1,"method":"x"}"#;
 	let response = request(server,
-		&format!("\
+		"\
 			POST / HTTP/1.1\r\n\
-			Host: localhost:{}\r\n\
+			Host: 127.0.0.1:8080\r\n\
 			Connection: close\r\n\
+			Content-Length: 8\r\n\
 			Content-Type: application/json\r\n\
-			Content-Length: {}\r\n\
+			\r\n\
+			12345678\r\n\
+		"
+	);
+	assert_eq!(response.status, "HTTP/1.1 413 Payload Too Large".to_owned());
+}
+
+#[test]
+fn should_reject_declared_body_larger_than_max_without_reading_it() {
+	// given
+	let server = ServerBuilder::new(IoHandler::default())
+		.max_request_body_size(7)
+		.start_http(&"127.0.0.1:0".parse().unwrap())
+		.unwrap();
+
+	// when
+	let response = request(server,
+		"\
+			POST / HTTP/1.1\r\n\
+			Host: 127.0.0.1:8080\r\n\
+			Content-Length: 100000\r\n\
+			Content-Type: application/json\r\n\
+			\r\n\
+		"
+	);
+
+	// then
+	assert_eq!(response.status, "HTTP/1.1 413 Payload Too Large".to_owned());
+	assert_eq!(response.body, "2B\nRequest body size exceeds allowed maximum.\n".to_owned());
+}
+
+#[test]
+fn should_not_allow_chunked_request_larger_than_max() {
+	// given
+	let server = ServerBuilder::new(IoHandler::default())
+		.max_request_body_size(7)
+		.start_http(&"127.0.0.1:0".parse().unwrap())
+		.unwrap();
+
+	// when
+	let response = request(server,
+		"\
+			POST / HTTP/1.1\r\n\
+			Host: 127.0.0.1:8080\r\n\
+			Connection: close\r\n\
+			Transfer-Encoding: chunked\r\n\
+			Content-Type: application/json\r\n\
+			\r\n\
+			4\r\n\
+			[1,2\r\n\
+			4\r\n\
+			,3,4\r\n\
+			1\r\n\
+			]\r\n\
+			0\r\n\
+			\r\n\
+		"
+	);
+
+	// then
+	assert_eq!(response.status, "HTTP/1.1 413 Payload Too Large".to_owned());
+	assert_eq!(response.body, "2B\nRequest body size exceeds allowed maximum.\n".to_owned());
+}
+
+#[test]
+fn should_reject_invalid_hosts() {
+	// given
+	let server = serve_hosts(vec!["parity.io".into()]);
+
+	// when
+	let req = r#"{"jsonrpc":"2.0","id":1,"method":"x"}"#;
+	let response = request(server,
+		&format!("\
+			POST / HTTP/1.1\r\n\
+			Host: 127.0.0.1:8080\r\n\
+			Connection: close\r\n\
+			Content-Type: application/json\r\n\
+			Content-Length: {}\r\n\
+			\r\n\
+			{}\r\n\
+		", req.as_bytes().len(), req)
+	);
+
+	// then
+	assert_eq!(response.status, "HTTP/1.1 403 Forbidden".to_owned());
+	assert_eq!(response.body, invalid_host());
+}
+
+#[test]
+fn should_reject_missing_host() {
+	// given
+	let server = serve_hosts(vec!["parity.io".into()]);
+
+	// when
+	let req = r#"{"jsonrpc":"2.0","id":1,"method":"x"}"#;
+	let response = request(server,
+		&format!("\
+			POST / HTTP/1.1\r\n\
+			Connection: close\r\n\
+			Content-Type: application/json\r\n\
+			Content-Length: {}\r\n\
+			\r\n\
+			{}\r\n\
+		", req.as_bytes().len(), req)
+	);
+
+	// then
+	assert_eq!(response.status, "HTTP/1.1 403 Forbidden".to_owned());
+	assert_eq!(response.body, invalid_host());
+}
+
+#[test]
+fn should_allow_if_host_is_valid() {
+	// given
+	let server = serve_hosts(vec!["parity.io".into()]);
+
+	// when
+	let req = r#"{"jsonrpc":"2.0","id":1,"method":"x"}"#;
+	let response = request(server,
+		&format!("\
+			POST / HTTP/1.1\r\n\
+			Host: parity.io\r\n\
+			Connection: close\r\n\
+			Content-Type: application/json\r\n\
+			Content-Length: {}\r\n\
+			\r\n\
+			{}\r\n\
+		", req.as_bytes().len(), req)
+	);
+
+	// then
+	assert_eq!(response.status, "HTTP/1.1 200 OK".to_owned());
+	assert_eq!(response.body, method_not_found());
+}
+
+#[test]
+fn should_allow_application_json_utf8() {
+	// given
+	let server = serve_hosts(vec!["parity.io".into()]);
+
+	// when
+	let req = r#"{"jsonrpc":"2.0","id":1,"method":"x"}"#;
+	let response = request(server,
+		&format!("\
+			POST / HTTP/1.1\r\n\
+			Host: parity.io\r\n\
+			Connection: close\r\n\
+			Content-Type: application/json; charset=utf-8\r\n\
+			Content-Length: {}\r\n\
+			\r\n\
+			{}\r\n\
+		", req.as_bytes().len(), req)
+	);
+
+	// then
+	assert_eq!(response.status, "HTTP/1.1 200 OK".to_owned());
+	assert_eq!(response.body, method_not_found());
+}
+
+#[test]
+fn should_always_allow_the_bind_address() {
+	// given
+	let server = serve_hosts(vec!["parity.io".into()]);
+	let addr = server.address().clone();
+
+	// when
+	let req = r#"{"jsonrpc":"2.0","id":1,"method":"x"}"#;
+	let response = request(server,
+		&format!("\
+			POST / HTTP/1.1\r\n\
+			Host: {}\r\n\
+			Connection: close\r\n\
+			Content-Type: application/json\r\n\
+			Content-Length: {}\r\n\
+			\r\n\
+			{}\r\n\
+		", addr, req.as_bytes().len(), req)
+	);
+
+	// then
+	assert_eq!(response.status, "HTTP/1.1 200 OK".to_owned());
+	assert_eq!(response.body, method_not_found());
+}
+
+#[test]
+fn should_always_allow_the_bind_address_as_localhost() {
+	// given
+	let server = serve_hosts(vec![]);
+	let addr = server.address().clone();
+
+	// when
+	let req = r#"{"jsonrpc":"2.0","id":1,"method":"x"}"#;
+	let response = request(server,
+		&format!("\
+			POST / HTTP/1.1\r\n\
+			Host: localhost:{}\r\n\
+			Connection: close\r\n\
+			Content-Type: application/json\r\n\
+			Content-Length: {}\r\n\
+			\r\n\
+			{}\r\n\
+		", addr.port(), req.as_bytes().len(), req)
+	);
+
+	// then
+	assert_eq!(response.status, "HTTP/1.1 200 OK".to_owned());
+	assert_eq!(response.body, method_not_found());
+}
+
+#[test]
+fn should_handle_sync_requests_correctly() {
+	// given
+	let server = serve();
+	let addr = server.address().clone();
+
+	// when
+	let req = r#"{"jsonrpc":"2.0","id":1,"method":"hello"}"#;
+	let response = request(server,
+		&format!("\
+			POST / HTTP/1.1\r\n\
+			Host: localhost:{}\r\n\
+			Connection: close\r\n\
+			Content-Type: application/json\r\n\
+			Content-Length: {}\r\n\
+			\r\n\
+			{}\r\n\
+		", addr.port(), req.as_bytes().len(), req)
+	);
+
+	// then
+	assert_eq!(response.status, "HTTP/1.1 200 OK".to_owned());
+	assert_eq!(response.body, world());
+}
+
+#[test]
+fn should_return_maintenance_error_when_enabled() {
+	// given
+	let server = serve();
+	let addr = server.address().clone();
+	let req = r#"{"jsonrpc":"2.0","id":1,"method":"hello"}"#;
+	let raw = format!("\
+		POST / HTTP/1.1\r\n\
+		Host: localhost:{}\r\n\
+		Connection: close\r\n\
+		Content-Type: application/json\r\n\
+		Content-Length: {}\r\n\
+		\r\n\
+		{}\r\n\
+	", addr.port(), req.as_bytes().len(), req);
+
+	// when
+	server.set_maintenance(Some(Error {
+		code: ErrorCode::ServerError(-32050),
+		message: "Under maintenance".into(),
+		data: None,
+	}));
+	let response = request_to(&addr, raw.as_bytes());
+
+	// then
+	assert_eq!(response.status, "HTTP/1.1 200 OK".to_owned());
+	assert_eq!(
+		response.body,
+		"4E\n{\"jsonrpc\":\"2.0\",\"error\":{\"code\":-32050,\"message\":\"Under maintenance\"},\"id\":1}\n"
+	);
+
+	// when
+	server.set_maintenance(None);
+	let response = request(server, &raw);
+
+	// then
+	assert_eq!(response.status, "HTTP/1.1 200 OK".to_owned());
+	assert_eq!(response.body, world());
+}
+
+#[test]
+fn should_fail_calls_of_tripped_methods_until_reset() {
+	// given
+	let server = serve();
+	let addr = server.address().clone();
+	let req = r#"{"jsonrpc":"2.0","id":1,"method":"hello"}"#;
+	let raw = format!("\
+		POST / HTTP/1.1\r\n\
+		Host: localhost:{}\r\n\
+		Connection: close\r\n\
+		Content-Type: application/json\r\n\
+		Content-Length: {}\r\n\
+		\r\n\
+		{}\r\n\
+	", addr.port(), req.as_bytes().len(), req);
+
+	// when
+	server.trip_method("hello", Error {
+		code: ErrorCode::ServerError(-32000),
+		message: "Dependency is failing".into(),
+		data: None,
+	});
+	let response = request_to(&addr, raw.as_bytes());
+
+	// then
+	assert_eq!(response.status, "HTTP/1.1 200 OK".to_owned());
+	assert_eq!(
+		response.body,
+		"53\n{\"jsonrpc\":\"2.0\",\"error\":{\"code\":-32000,\"message\":\"Dependency is failing\"},\"id\":1}\n"
+	);
+
+	// when
+	server.reset_method("hello");
+	let response = request(server, &raw);
+
+	// then
+	assert_eq!(response.status, "HTTP/1.1 200 OK".to_owned());
+	assert_eq!(response.body, world());
+}
+
+#[test]
+fn should_handle_gzip_encoded_requests() {
+	// given
+	let server = serve();
+	let addr = server.address().clone();
+
+	// when
+	let req = r#"{"jsonrpc":"2.0","id":1,"method":"hello"}"#;
+	let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+	encoder.write_all(req.as_bytes()).unwrap();
+	let body = encoder.finish().unwrap();
+	let mut raw = format!("\
+		POST / HTTP/1.1\r\n\
+		Host: localhost:{}\r\n\
+		Connection: close\r\n\
+		Content-Type: application/json\r\n\
+		Content-Encoding: gzip\r\n\
+		Content-Length: {}\r\n\
+		\r\n\
+	", addr.port(), body.len()).into_bytes();
+	raw.extend_from_slice(&body);
+	let response = request_to(&addr, &raw);
+
+	// then
+	assert_eq!(response.status, "HTTP/1.1 200 OK".to_owned());
+	assert_eq!(response.body, world());
+}
+
+#[test]
+fn should_return_too_large_for_gzip_encoded_requests_exceeding_limit_when_decompressed() {
+	// given
+	let server = ServerBuilder::new(IoHandler::default())
+		.max_request_body_size(1024)
+		.start_http(&"127.0.0.1:0".parse().unwrap())
+		.unwrap();
+	let addr = server.address().clone();
+
+	// when
+	let req = format!(r#"{{"jsonrpc":"2.0","id":1,"method":"{}"}}"#, "x".repeat(4096));
+	let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+	encoder.write_all(req.as_bytes()).unwrap();
+	let body = encoder.finish().unwrap();
+	assert!(body.len() < 1024);
+	let mut raw = format!("\
+		POST / HTTP/1.1\r\n\
+		Host: localhost:{}\r\n\
+		Connection: close\r\n\
+		Content-Type: application/json\r\n\
+		Content-Encoding: gzip\r\n\
+		Content-Length: {}\r\n\
+		\r\n\
+	", addr.port(), body.len()).into_bytes();
+	raw.extend_from_slice(&body);
+	let response = request_to(&addr, &raw);
+
+	// then
+	assert_eq!(response.status, "HTTP/1.1 413 Payload Too Large".to_owned());
+}
+
+#[test]
+fn should_return_msgpack_when_requested() {
+	// given
+	let server = serve();
+	let addr = server.address().clone();
+
+	// when
+	let req = r#"{"jsonrpc":"2.0","id":1,"method":"hello"}"#;
+	let response = request_raw(&addr, format!("\
+		POST / HTTP/1.1\r\n\
+		Host: localhost:{}\r\n\
+		Connection: close\r\n\
+		Content-Type: application/json\r\n\
+		Accept: application/msgpack\r\n\
+		Content-Length: {}\r\n\
+		\r\n\
+		{}\r\n\
+	", addr.port(), req.as_bytes().len(), req).as_bytes());
+
+	// then
+	let (headers, chunks) = read_chunks(&response);
+	assert!(headers.starts_with("HTTP/1.1 200 OK"), "Unexpected response: {}", headers);
+	assert!(headers.contains("Content-Type: application/msgpack"), "Headers missing in {}", headers);
+	let chunk = &chunks[0];
+	let decoded: Value = rmp_serde::from_slice(chunk).unwrap();
+	let expected: Value = serde_json::from_str(r#"{"jsonrpc":"2.0","result":"world","id":1}"#).unwrap();
+	assert_eq!(decoded, expected);
+}
+
+#[test]
+fn should_return_timeout_errors_for_pending_calls_of_a_batch() {
+	// given
+	let mut io = IoHandler::default();
+	io.add_method("hello", |_params: Params| Ok(Value::String("world".into())));
+	io.add_method("hang", |_params: Params| futures::empty());
+	let server = ServerBuilder::new(io)
+		.batch_timeout(::std::time::Duration::from_millis(50))
+		.start_http(&"127.0.0.1:0".parse().unwrap())
+		.unwrap();
+	let addr = server.address().clone();
+
+	// when
+	let req = r#"[{"jsonrpc":"2.0","id":1,"method":"hello"},{"jsonrpc":"2.0","id":2,"method":"hang"}]"#;
+	let response = request(server,
+		&format!("\
+			POST / HTTP/1.1\r\n\
+			Host: localhost:{}\r\n\
+			Connection: close\r\n\
+			Content-Type: application/json\r\n\
+			Content-Length: {}\r\n\
+			\r\n\
+			{}\r\n\
+		", addr.port(), req.as_bytes().len(), req)
+	);
+
+	// then
+	assert_eq!(response.status, "HTTP/1.1 200 OK".to_owned());
+	assert_eq!(
+		response.body,
+		"7A\n[{\"jsonrpc\":\"2.0\",\"result\":\"world\",\"id\":1},{\"jsonrpc\":\"2.0\",\"error\":{\"code\":-32001,\"message\":\"Request timed out\"},\"id\":2}]\n"
+	);
+}
+
+#[test]
+fn should_add_batch_index_to_invalid_calls_of_a_batch_with_timeout() {
+	// given
+	let mut io = IoHandler::default();
+	io.add_method("hello", |_params: Params| Ok(Value::String("world".into())));
+	let server = ServerBuilder::new(io)
+		.batch_timeout(::std::time::Duration::from_millis(50))
+		.start_http(&"127.0.0.1:0".parse().unwrap())
+		.unwrap();
+	let addr = server.address().clone();
+
+	// when
+	let req = r#"[{"jsonrpc":"2.0","id":1,"method":"hello"},{"jsonrpc":"2.0","id":2}]"#;
+	let response = request(server,
+		&format!("\
+			POST / HTTP/1.1\r\n\
+			Host: localhost:{}\r\n\
+			Connection: close\r\n\
+			Content-Type: application/json\r\n\
+			Content-Length: {}\r\n\
+			\r\n\
+			{}\r\n\
+		", addr.port(), req.as_bytes().len(), req)
+	);
+
+	// then
+	assert_eq!(response.status, "HTTP/1.1 200 OK".to_owned());
+	assert_eq!(
+		response.body,
+		"92\n[{\"jsonrpc\":\"2.0\",\"result\":\"world\",\"id\":1},{\"jsonrpc\":\"2.0\",\"error\":{\"code\":-32600,\"message\":\"Invalid request\",\"data\":{\"batch_index\":1}},\"id\":2}]\n"
+	);
+}
+
+#[test]
+fn should_add_batch_index_to_invalid_calls_of_a_rejected_batch() {
+	// given
+	let server = serve();
+	let addr = server.address().clone();
+	server.set_maintenance(Some(Error {
+		code: ErrorCode::ServerError(-32050),
+		message: "Under maintenance".into(),
+		data: None,
+	}));
+
+	// when
+	let req = r#"[{"jsonrpc":"2.0","id":1,"method":"hello"},{"jsonrpc":"2.0","id":2}]"#;
+	let response = request(server,
+		&format!("\
+			POST / HTTP/1.1\r\n\
+			Host: localhost:{}\r\n\
+			Connection: close\r\n\
+			Content-Type: application/json\r\n\
+			Content-Length: {}\r\n\
+			\r\n\
+			{}\r\n\
+		", addr.port(), req.as_bytes().len(), req)
+	);
+
+	// then
+	assert_eq!(response.status, "HTTP/1.1 200 OK".to_owned());
+	assert_eq!(
+		response.body,
+		"B7\n[{\"jsonrpc\":\"2.0\",\"error\":{\"code\":-32050,\"message\":\"Under maintenance\"},\"id\":1},{\"jsonrpc\":\"2.0\",\"error\":{\"code\":-32600,\"message\":\"Invalid request\",\"data\":{\"batch_index\":1}},\"id\":2}]\n"
+	);
+}
+
+#[test]
+fn should_return_timeout_error_for_calls_exceeding_request_timeout() {
+	use std::thread;
+	use std::time::Duration;
+	use self::jsonrpc_core::futures::sync::oneshot;
+
+	// given
+	let mut io = IoHandler::default();
+	io.add_method("hello", |_params: Params| Ok(Value::String("world".into())));
+	io.add_method("sleep", |_params: Params| {
+		let (tx, rx) = oneshot::channel();
+		thread::spawn(move || {
+			thread::sleep(Duration::from_secs(1));
+			let _ = tx.send(Value::String("done".into()));
+		});
+		rx.map_err(|_| Error::internal_error())
+	});
+	let server = ServerBuilder::new(io)
+		.request_timeout(Duration::from_millis(100))
+		.start_http(&"127.0.0.1:0".parse().unwrap())
+		.unwrap();
+	let addr = server.address().clone();
+	let post = |req: &str| format!("\
+		POST / HTTP/1.1\r\n\
+		Host: localhost:{}\r\n\
+		Connection: close\r\n\
+		Content-Type: application/json\r\n\
+		Content-Length: {}\r\n\
+		\r\n\
+		{}\r\n\
+	", addr.port(), req.as_bytes().len(), req);
+
+	// when
+	let fast = request_to(&addr, post(r#"{"jsonrpc":"2.0","id":1,"method":"hello"}"#).as_bytes());
+	let slow = request(server, &post(r#"{"jsonrpc":"2.0","id":1,"method":"sleep"}"#));
+
+	// then
+	assert_eq!(fast.status, "HTTP/1.1 200 OK".to_owned());
+	assert_eq!(fast.body, world());
+	assert_eq!(slow.status, "HTTP/1.1 200 OK".to_owned());
+	assert_eq!(slow.body, "4F\n{\"jsonrpc\":\"2.0\",\"error\":{\"code\":-32001,\"message\":\"Request timed out\"},\"id\":1}\n");
+}
+
+#[test]
+fn should_return_batch_outputs_in_completion_order_when_unordered() {
+	use std::thread;
+	use std::time::Duration;
+	use self::jsonrpc_core::futures::sync::oneshot;
+
+	// given
+	let mut io = IoHandler::default();
+	io.add_method("fast", |_params: Params| Ok(Value::String("fast".into())));
+	io.add_method("slow", |_params: Params| {
+		let (tx, rx) = oneshot::channel();
+		thread::spawn(move || {
+			thread::sleep(Duration::from_millis(100));
+			let _ = tx.send(Value::String("slow".into()));
+		});
+		rx.map_err(|_| Error::internal_error())
+	});
+	let server = ServerBuilder::new(io)
+		.batch_ordered(false)
+		.start_http(&"127.0.0.1:0".parse().unwrap())
+		.unwrap();
+	let addr = server.address().clone();
+
+	// when
+	let req = r#"[{"jsonrpc":"2.0","id":1,"method":"slow"},{"jsonrpc":"2.0","id":2,"method":"fast"}]"#;
+	let response = request(server,
+		&format!("\
+			POST / HTTP/1.1\r\n\
+			Host: localhost:{}\r\n\
+			Connection: close\r\n\
+			Content-Type: application/json\r\n\
+			Content-Length: {}\r\n\
+			\r\n\
+			{}\r\n\
+		", addr.port(), req.as_bytes().len(), req)
+	);
+
+	// then
+	assert_eq!(response.status, "HTTP/1.1 200 OK".to_owned());
+	assert_eq!(
+		response.body,
+		"54\n[{\"jsonrpc\":\"2.0\",\"result\":\"fast\",\"id\":2},{\"jsonrpc\":\"2.0\",\"result\":\"slow\",\"id\":1}]\n"
+	);
+}
+
+#[cfg(feature = "fault-injection")]
+#[test]
+fn should_fail_all_requests_with_full_error_rate() {
+	// given
+	let mut io = IoHandler::default();
+	io.add_method("hello", |_params: Params| Ok(Value::String("world".into())));
+	let server = ServerBuilder::new(io)
+		.fault_injection(FaultConfig { delay: None, error_rate: 1.0 })
+		.start_http(&"127.0.0.1:0".parse().unwrap())
+		.unwrap();
+	let addr = server.address().clone();
+
+	// when
+	let req = r#"{"jsonrpc":"2.0","id":1,"method":"hello"}"#;
+	let response = request(server,
+		&format!("\
+			POST / HTTP/1.1\r\n\
+			Host: localhost:{}\r\n\
+			Connection: close\r\n\
+			Content-Type: application/json\r\n\
+			Content-Length: {}\r\n\
+			\r\n\
+			{}\r\n\
+		", addr.port(), req.as_bytes().len(), req)
+	);
+
+	// then
+	assert_eq!(response.status, "HTTP/1.1 200 OK".to_owned());
+	assert_eq!(
+		response.body,
+		"4C\n{\"jsonrpc\":\"2.0\",\"error\":{\"code\":-32002,\"message\":\"Injected fault\"},\"id\":1}\n"
+	);
+}
+
+#[test]
+fn should_serve_methods_registered_at_runtime() {
+	// given
+	let shared = SharedIoHandler::<()>::default();
+	let server = ServerBuilder::new(MetaIoHandler::with_middleware(shared.clone()))
+		.start_http(&"127.0.0.1:0".parse().unwrap())
+		.unwrap();
+	let addr = server.address().clone();
+
+	// when
+	::std::thread::spawn(move || {
+		shared.add_method("hello", |_params: Params| Ok(Value::String("world".into())));
+	}).join().unwrap();
+	let req = r#"{"jsonrpc":"2.0","id":1,"method":"hello"}"#;
+	let response = request(server,
+		&format!("\
+			POST / HTTP/1.1\r\n\
+			Host: localhost:{}\r\n\
+			Connection: close\r\n\
+			Content-Type: application/json\r\n\
+			Content-Length: {}\r\n\
+			\r\n\
+			{}\r\n\
+		", addr.port(), req.as_bytes().len(), req)
+	);
+
+	// then
+	assert_eq!(response.status, "HTTP/1.1 200 OK".to_owned());
+	assert_eq!(response.body, world());
+}
+
+#[test]
+fn should_reject_body_not_starting_as_json_without_reading_it() {
+	// given
+	let server = serve();
+
+	// when
+	let response = request(server,
+		"\
+			POST / HTTP/1.1\r\n\
+			Host: 127.0.0.1:8080\r\n\
+			Content-Type: application/json\r\n\
+			Content-Length: 100000\r\n\
+			\r\n\
+			<html><body>\
+		"
+	);
+
+	// then
+	assert_eq!(response.status, "HTTP/1.1 200 OK".to_owned());
+	assert!(response.headers.contains("Connection: close"), "Headers missing in {}", response.headers);
+	assert_eq!(
+		response.body,
+		"80\n{\"jsonrpc\":\"2.0\",\"error\":{\"code\":-32700,\"message\":\"Parse error\",\"data\":\"Request body is not a JSON object or array\"},\"id\":null}\n"
+	);
+}
+
+#[test]
+fn should_return_parse_error_for_invalid_utf8_body() {
+	// given
+	let server = serve();
+	let addr = server.address().clone();
+
+	// when
+	let body = b"[\xff\xfe]";
+	let mut raw = format!("\
+		POST / HTTP/1.1\r\n\
+		Host: localhost:{}\r\n\
+		Connection: close\r\n\
+		Content-Type: application/json\r\n\
+		Content-Length: {}\r\n\
+		\r\n\
+	", addr.port(), body.len()).into_bytes();
+	raw.extend_from_slice(body);
+	let response = request_to(&addr, &raw);
+
+	// then
+	assert_eq!(response.status, "HTTP/1.1 200 OK".to_owned());
+	assert_eq!(
+		response.body,
+		"85\n{\"jsonrpc\":\"2.0\",\"error\":{\"code\":-32700,\"message\":\"Parse error\",\"data\":\"Invalid UTF-8 sequence at byte 1 in request body\"},\"id\":null}\n"
+	);
+}
+
+#[test]
+fn should_split_large_responses_into_chunks_of_configured_size() {
+	// given
+	let mut io = IoHandler::default();
+	io.add_method("hello", |_params: Params| Ok(Value::String("x".repeat(1000))));
+	let server = ServerBuilder::new(io)
+		.chunk_size(128)
+		.start_http(&"127.0.0.1:0".parse().unwrap())
+		.unwrap();
+	let addr = server.address().clone();
+
+	// when
+	let req = r#"{"jsonrpc":"2.0","id":1,"method":"hello"}"#;
+	let response = request_raw(&addr, format!("\
+		POST / HTTP/1.1\r\n\
+		Host: localhost:{}\r\n\
+		Connection: close\r\n\
+		Content-Type: application/json\r\n\
+		Content-Length: {}\r\n\
+		\r\n\
+		{}\r\n\
+	", addr.port(), req.as_bytes().len(), req).as_bytes());
+
+	// then
+	let (headers, chunks) = read_chunks(&response);
+	assert!(headers.starts_with("HTTP/1.1 200 OK"), "Unexpected response: {}", headers);
+	let (last, rest) = chunks.split_last().unwrap();
+	assert!(!rest.is_empty());
+	assert!(rest.iter().all(|chunk| chunk.len() == 128));
+	assert!(last.len() <= 128);
+	let body: Vec<u8> = chunks.concat();
+	let expected = format!("{{\"jsonrpc\":\"2.0\",\"result\":\"{}\",\"id\":1}}\n", "x".repeat(1000));
+	assert_eq!(str::from_utf8(&body).unwrap(), expected);
+}
+
+#[test]
+fn should_stream_results_of_subscription_methods() {
+	// given
+	let mut io = IoHandler::default();
+	io.add_subscription_method("count", |_params: Params| {
+		futures::stream::iter_ok::<_, Error>(vec![Value::from(1), Value::from(2), Value::from(3)])
+	});
+	let server = ServerBuilder::new(io)
+		.start_http(&"127.0.0.1:0".parse().unwrap())
+		.unwrap();
+	let addr = server.address().clone();
+
+	// when
+	let req = r#"{"jsonrpc":"2.0","id":1,"method":"count"}"#;
+	let response = request_raw(&addr, format!("\
+		POST / HTTP/1.1\r\n\
+		Host: localhost:{}\r\n\
+		Connection: close\r\n\
+		Content-Type: application/json\r\n\
+		Content-Length: {}\r\n\
+		\r\n\
+		{}\r\n\
+	", addr.port(), req.as_bytes().len(), req).as_bytes());
+
+	// then
+	let (headers, chunks) = read_chunks(&response);
+	assert!(headers.starts_with("HTTP/1.1 200 OK"), "Unexpected response: {}", headers);
+	assert!(headers.contains("Transfer-Encoding: chunked"), "Unexpected headers: {}", headers);
+	let body = String::from_utf8(chunks.concat()).unwrap();
+	assert_eq!(body.lines().collect::<Vec<_>>(), vec![
+		r#"{"jsonrpc":"2.0","result":1,"id":1}"#,
+		r#"{"jsonrpc":"2.0","result":2,"id":1}"#,
+		r#"{"jsonrpc":"2.0","result":3,"id":1}"#,
+	]);
+}
+
+#[test]
+fn should_process_each_streamed_output_like_regular_responses() {
+	// given
+	let mut io = IoHandler::default();
+	io.add_subscription_method("greet", |_params: Params| {
+		futures::stream::iter_result(vec![Ok(Value::String("żółw".into())), Err(Error::internal_error())])
+	});
+	let server = ServerBuilder::new(io)
+		.ascii_only_output(true)
+		.error_rewriter(|_meta: &(), mut error: Error| {
+			error.message = "Rewritten".into();
+			error
+		})
+		.start_http(&"127.0.0.1:0".parse().unwrap())
+		.unwrap();
+	let addr = server.address().clone();
+
+	// when
+	let req = r#"{"jsonrpc":"2.0","id":1,"method":"greet"}"#;
+	let response = request_raw(&addr, format!("\
+		POST / HTTP/1.1\r\n\
+		Host: localhost:{}\r\n\
+		Connection: close\r\n\
+		Content-Type: application/json\r\n\
+		Content-Length: {}\r\n\
+		\r\n\
+		{}\r\n\
+	", addr.port(), req.as_bytes().len(), req).as_bytes());
+
+	// then
+	let (headers, chunks) = read_chunks(&response);
+	assert!(headers.starts_with("HTTP/1.1 200 OK"), "Unexpected response: {}", headers);
+	let body = String::from_utf8(chunks.concat()).unwrap();
+	assert_eq!(body.lines().collect::<Vec<_>>(), vec![
+		r#"{"jsonrpc":"2.0","result":"\u017c\u00f3\u0142w","id":1}"#,
+		r#"{"jsonrpc":"2.0","error":{"code":-32603,"message":"Rewritten"},"id":1}"#,
+	]);
+}
+
+#[test]
+fn should_pass_streaming_calls_through_middleware() {
+	// given
+	let mut io = MetaIoHandler::with_middleware(Rejecting);
+	io.add_subscription_method("count", |_params: Params| {
+		futures::stream::iter_ok::<_, Error>(vec![Value::from(1), Value::from(2)])
+	});
+	let server = ServerBuilder::new(io)
+		.start_http(&"127.0.0.1:0".parse().unwrap())
+		.unwrap();
+	let addr = server.address().clone();
+
+	// when
+	let req = r#"{"jsonrpc":"2.0","id":1,"method":"count"}"#;
+	let response = request(server,
+		&format!("\
+			POST / HTTP/1.1\r\n\
+			Host: localhost:{}\r\n\
+			Connection: close\r\n\
+			Content-Type: application/json\r\n\
+			Content-Length: {}\r\n\
+			\r\n\
+			{}\r\n\
+		", addr.port(), req.as_bytes().len(), req)
+	);
+
+	// then
+	assert_eq!(response.status, "HTTP/1.1 200 OK".to_owned());
+	assert_eq!(
+		response.body,
+		"49\n{\"jsonrpc\":\"2.0\",\"error\":{\"code\":-32010,\"message\":\"Rejected\"},\"id\":null}\n"
+	);
+}
+
+#[test]
+fn should_end_streams_exceeding_request_timeout_with_timeout_error() {
+	use std::time::Duration;
+	use self::jsonrpc_core::futures::{future, stream, Stream};
+
+	// given
+	let mut io = IoHandler::default();
+	io.add_subscription_method("count", |_params: Params| {
+		stream::once::<_, Error>(Ok(Value::from(1))).chain(future::empty().into_stream())
+	});
+	let server = ServerBuilder::new(io)
+		.request_timeout(Duration::from_millis(100))
+		.start_http(&"127.0.0.1:0".parse().unwrap())
+		.unwrap();
+	let addr = server.address().clone();
+
+	// when
+	let req = r#"{"jsonrpc":"2.0","id":1,"method":"count"}"#;
+	let response = request_raw(&addr, format!("\
+		POST / HTTP/1.1\r\n\
+		Host: localhost:{}\r\n\
+		Connection: close\r\n\
+		Content-Type: application/json\r\n\
+		Content-Length: {}\r\n\
+		\r\n\
+		{}\r\n\
+	", addr.port(), req.as_bytes().len(), req).as_bytes());
+
+	// then
+	let (headers, chunks) = read_chunks(&response);
+	assert!(headers.starts_with("HTTP/1.1 200 OK"), "Unexpected response: {}", headers);
+	let body = String::from_utf8(chunks.concat()).unwrap();
+	assert_eq!(body.lines().collect::<Vec<_>>(), vec![
+		r#"{"jsonrpc":"2.0","result":1,"id":1}"#,
+		r#"{"jsonrpc":"2.0","error":{"code":-32001,"message":"Request timed out"},"id":1}"#,
+	]);
+}
+
+#[test]
+fn should_reject_non_compliant_requests_in_strict_mode() {
+	// given
+	let mut io = MetaIoHandler::<()>::with_compatibility(Compatibility::Both);
+	io.add_method("hello", |_params: Params| Ok(Value::String("world".into())));
+	let server = ServerBuilder::new(io)
+		.strict(true)
+		.start_http(&"127.0.0.1:0".parse().unwrap())
+		.unwrap();
+	let addr = server.address().clone();
+	let raw = |req: &str| format!("\
+		POST / HTTP/1.1\r\n\
+		Host: localhost:{}\r\n\
+		Connection: close\r\n\
+		Content-Type: application/json\r\n\
+		Content-Length: {}\r\n\
+		\r\n\
+		{}\r\n\
+	", addr.port(), req.as_bytes().len(), req);
+
+	// when
+	let extra_field = request_to(&addr, raw(r#"{"jsonrpc":"2.0","id":1,"method":"hello","foo":1}"#).as_bytes());
+	let no_version = request_to(&addr, raw(r#"{"id":1,"method":"hello"}"#).as_bytes());
+	let empty_batch = request_to(&addr, raw("[]").as_bytes());
+	let valid = request(server, &raw(r#"{"jsonrpc":"2.0","id":1,"method":"hello"}"#));
+
+	// then
+	let invalid = "4C\n{\"jsonrpc\":\"2.0\",\"error\":{\"code\":-32600,\"message\":\"Invalid request\"},\"id\":1}\n";
+	assert_eq!(extra_field.body, invalid);
+	assert_eq!(no_version.body, invalid);
+	assert_eq!(empty_batch.body, invalid_request());
+	assert_eq!(valid.body, world());
+}
+
+#[test]
+fn should_respond_in_version_of_each_request_when_compatible_with_both() {
+	// given
+	let mut io = MetaIoHandler::<()>::with_compatibility(Compatibility::Both);
+	io.add_method("hello", |_params: Params| Ok(Value::String("world".into())));
+	let server = ServerBuilder::new(io)
+		.start_http(&"127.0.0.1:0".parse().unwrap())
+		.unwrap();
+	let addr = server.address().clone();
+	let raw = |req: &str| format!("\
+		POST / HTTP/1.1\r\n\
+		Host: localhost:{}\r\n\
+		Connection: close\r\n\
+		Content-Type: application/json\r\n\
+		Content-Length: {}\r\n\
+		\r\n\
+		{}\r\n\
+	", addr.port(), req.as_bytes().len(), req);
+
+	// when
+	let v1 = request_to(&addr, raw(r#"{"id":1,"method":"hello","params":[]}"#).as_bytes());
+	let v2 = request(server, &raw(r#"{"jsonrpc":"2.0","id":1,"method":"hello"}"#));
+
+	// then
+	assert_eq!(v1.status, "HTTP/1.1 200 OK".to_owned());
+	assert_eq!(v1.body, "1A\n{\"result\":\"world\",\"id\":1}\n".to_owned());
+	assert_eq!(v2.status, "HTTP/1.1 200 OK".to_owned());
+	assert_eq!(v2.body, world());
+}
+
+struct TraceIdExtractor;
+impl MetaExtractor<()> for TraceIdExtractor {
+	fn read_metadata(&self, _: &hyper::server::Request) {}
+
+	fn read_error_context(&self, request: &hyper::server::Request) -> Option<Value> {
+		request.headers().get_raw("X-Trace-Id")
+			.and_then(|value| value.one())
+			.map(|value| Value::String(String::from_utf8_lossy(value).into_owned()))
+	}
+}
+
+#[test]
+fn should_include_error_context_in_error_data() {
+	// given
+	let mut io = IoHandler::default();
+	io.add_method("fail", |_params: Params| Err(Error::internal_error()));
+	let server = ServerBuilder::new(io)
+		.meta_extractor(TraceIdExtractor)
+		.start_http(&"127.0.0.1:0".parse().unwrap())
+		.unwrap();
+	let addr = server.address().clone();
+
+	// when
+	let req = r#"{"jsonrpc":"2.0","id":1,"method":"fail"}"#;
+	let response = request(server,
+		&format!("\
+			POST / HTTP/1.1\r\n\
+			Host: localhost:{}\r\n\
+			Connection: close\r\n\
+			Content-Type: application/json\r\n\
+			X-Trace-Id: abc123\r\n\
+			Content-Length: {}\r\n\
+			\r\n\
+			{}\r\n\
+		", addr.port(), req.as_bytes().len(), req)
+	);
+
+	// then
+	assert_eq!(response.status, "HTTP/1.1 200 OK".to_owned());
+	assert_eq!(response.body, "5B\n{\"jsonrpc\":\"2.0\",\"error\":{\"code\":-32603,\"message\":\"Internal error\",\"data\":\"abc123\"},\"id\":1}\n");
+}
+
+#[test]
+fn should_return_cached_response_for_repeated_idempotency_key() {
+	use std::sync::Arc;
+	use std::sync::atomic::{AtomicUsize, Ordering};
+	use std::time::Duration;
+
+	// given
+	let calls = Arc::new(AtomicUsize::new(0));
+	let counter = calls.clone();
+	let mut io = IoHandler::default();
+	io.add_method("increment", move |_params: Params| {
+		Ok(Value::from(counter.fetch_add(1, Ordering::SeqCst) + 1))
+	});
+	let server = ServerBuilder::new(io)
+		.idempotency(16, Duration::from_secs(60))
+		.start_http(&"127.0.0.1:0".parse().unwrap())
+		.unwrap();
+	let addr = server.address().clone();
+	let req = r#"{"jsonrpc":"2.0","id":1,"method":"increment"}"#;
+	let raw = |key: &str| format!("\
+		POST / HTTP/1.1\r\n\
+		Host: localhost:{}\r\n\
+		Connection: close\r\n\
+		Content-Type: application/json\r\n\
+		Idempotency-Key: {}\r\n\
+		Content-Length: {}\r\n\
+		\r\n\
+		{}\r\n\
+	", addr.port(), key, req.as_bytes().len(), req);
+
+	// when
+	let first = request_to(&addr, raw("a").as_bytes());
+	let retry = request_to(&addr, raw("a").as_bytes());
+	let other = request(server, &raw("b"));
+
+	// then
+	assert_eq!(first.status, "HTTP/1.1 200 OK".to_owned());
+	assert_eq!(first.body, "24\n{\"jsonrpc\":\"2.0\",\"result\":1,\"id\":1}\n");
+	assert_eq!(retry.body, first.body);
+	assert_eq!(other.body, "24\n{\"jsonrpc\":\"2.0\",\"result\":2,\"id\":1}\n");
+	assert_eq!(calls.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn should_reject_idempotency_key_reused_with_different_body() {
+	use std::time::Duration;
+
+	// given
+	let server = ServerBuilder::new(IoHandler::default())
+		.idempotency(16, Duration::from_secs(60))
+		.start_http(&"127.0.0.1:0".parse().unwrap())
+		.unwrap();
+	let addr = server.address().clone();
+	let raw = |req: &str| format!("\
+		POST / HTTP/1.1\r\n\
+		Host: localhost:{}\r\n\
+		Connection: close\r\n\
+		Content-Type: application/json\r\n\
+		Idempotency-Key: a\r\n\
+		Content-Length: {}\r\n\
+		\r\n\
+		{}\r\n\
+	", addr.port(), req.as_bytes().len(), req);
+
+	// when
+	let first = request_to(&addr, raw(r#"{"jsonrpc":"2.0","id":1,"method":"hello"}"#).as_bytes());
+	let other = request(server, &raw(r#"{"jsonrpc":"2.0","id":1,"method":"hello","params":[1]}"#));
+
+	// then
+	assert_eq!(first.status, "HTTP/1.1 200 OK".to_owned());
+	assert_eq!(other.status, "HTTP/1.1 422 Unprocessable Entity".to_owned());
+	assert_eq!(other.body, "3A\nIdempotency key was already used for a different request.\n");
+}
+
+#[test]
+fn should_bound_concurrent_handler_executions_by_pool_size() {
+	use std::sync::{Arc, Mutex};
+	use std::thread;
+	use std::time::Duration;
+
+	// given
+	let concurrency = Arc::new(Mutex::new((0usize, 0usize)));
+	let counter = concurrency.clone();
+	let mut io = IoHandler::default();
+	io.add_method("heavy", move |_params: Params| {
+		{
+			let mut counter = counter.lock().unwrap();
+			counter.0 += 1;
+			counter.1 = ::std::cmp::max(counter.0, counter.1);
+		}
+		thread::sleep(Duration::from_millis(100));
+		counter.lock().unwrap().0 -= 1;
+		Ok(Value::String("done".into()))
+	});
+	let server = ServerBuilder::new(io)
+		.handler_pool_size(2)
+		.start_http(&"127.0.0.1:0".parse().unwrap())
+		.unwrap();
+	let addr = server.address().clone();
+
+	// when
+	let req = r#"{"jsonrpc":"2.0","id":1,"method":"heavy"}"#;
+	let raw = format!("\
+		POST / HTTP/1.1\r\n\
+		Host: localhost:{}\r\n\
+		Connection: close\r\n\
+		Content-Type: application/json\r\n\
+		Content-Length: {}\r\n\
+		\r\n\
+		{}\r\n\
+	", addr.port(), req.as_bytes().len(), req);
+	let clients: Vec<_> = (0..6).map(|_| {
+		let raw = raw.clone();
+		thread::spawn(move || request_to(&addr, raw.as_bytes()))
+	}).collect();
+
+	// then
+	for client in clients {
+		let response = client.join().unwrap();
+		assert_eq!(response.status, "HTTP/1.1 200 OK".to_owned());
+	}
+	assert_eq!(concurrency.lock().unwrap().1, 2);
+}
+
+#[test]
+fn should_report_queued_duration_of_requests_waiting_for_pool() {
+	use std::sync::{Arc, Mutex};
+	use std::thread;
+	use std::time::Duration;
+
+	// given
+	let timings = Arc::new(Mutex::new(Vec::new()));
+	let reported = timings.clone();
+	let mut io = IoHandler::default();
+	io.add_method("heavy", |_params: Params| {
+		thread::sleep(Duration::from_millis(100));
+		Ok(Value::String("done".into()))
+	});
+	let server = ServerBuilder::new(io)
+		.handler_pool_size(1)
+		.request_timings(move |timings| reported.lock().unwrap().push(*timings))
+		.start_http(&"127.0.0.1:0".parse().unwrap())
+		.unwrap();
+	let addr = server.address().clone();
+
+	// when
+	let req = r#"{"jsonrpc":"2.0","id":1,"method":"heavy"}"#;
+	let raw = format!("\
+		POST / HTTP/1.1\r\n\
+		Host: localhost:{}\r\n\
+		Connection: close\r\n\
+		Content-Type: application/json\r\n\
+		Content-Length: {}\r\n\
+		\r\n\
+		{}\r\n\
+	", addr.port(), req.as_bytes().len(), req);
+	let clients: Vec<_> = (0..2).map(|_| {
+		let raw = raw.clone();
+		thread::spawn(move || request_to(&addr, raw.as_bytes()))
+	}).collect();
+	for client in clients {
+		assert_eq!(client.join().unwrap().status, "HTTP/1.1 200 OK".to_owned());
+	}
+
+	// then
+	let timings = timings.lock().unwrap();
+	assert_eq!(timings.len(), 2);
+	assert!(timings.iter().all(|t| t.handler_duration >= Duration::from_millis(100)), "Timings: {:?}", *timings);
+	assert!(timings.iter().any(|t| t.queued_duration >= Duration::from_millis(50)), "Timings: {:?}", *timings);
+}
+
+#[test]
+fn should_log_one_record_for_each_request() {
+	use std::sync::{Arc, Mutex};
+	use self::jsonrpc_core::Id;
+
+	#[derive(Default)]
+	struct MockLogger(Mutex<Vec<RequestRecord>>);
+	impl RequestLogger for MockLogger {
+		fn log(&self, record: RequestRecord) {
+			self.0.lock().unwrap().push(record);
+		}
+	}
+
+	// given
+	let logger = Arc::new(MockLogger::default());
+	let mut io = IoHandler::default();
+	io.add_method("hello", |_params: Params| Ok(Value::String("world".into())));
+	let server = ServerBuilder::new(io)
+		.request_logger(logger.clone())
+		.start_http(&"127.0.0.1:0".parse().unwrap())
+		.unwrap();
+	let addr = server.address().clone();
+
+	// when
+	let req = r#"[{"jsonrpc":"2.0","id":1,"method":"hello"},{"jsonrpc":"2.0","method":"notify"}]"#;
+	let response = request(server,
+		&format!("\
+			POST / HTTP/1.1\r\n\
+			Host: localhost:{}\r\n\
+			Connection: close\r\n\
+			Content-Type: application/json\r\n\
+			Content-Length: {}\r\n\
+			\r\n\
+			{}\r\n\
+		", addr.port(), req.as_bytes().len(), req)
+	);
+
+	// then
+	assert_eq!(response.status, "HTTP/1.1 200 OK".to_owned());
+	let records = logger.0.lock().unwrap();
+	assert_eq!(records.len(), 1);
+	let record = &records[0];
+	assert_eq!(record.calls, vec![("hello".to_owned(), Some(Id::Num(1))), ("notify".to_owned(), None)]);
+	assert_eq!(record.status, 200);
+	assert_eq!(record.request_bytes, req.len());
+	assert_eq!(record.response_bytes, r#"[{"jsonrpc":"2.0","result":"world","id":1}]"#.len() + 1);
+	assert!(record.remote_addr.map_or(false, |addr| addr.ip().is_loopback()), "Unexpected address: {:?}", record.remote_addr);
+}
+
+#[test]
+fn should_answer_not_modified_for_matching_etag_of_cacheable_method() {
+	// given
+	let mut io = IoHandler::default();
+	io.add_cached_method("hello", ::std::time::Duration::from_secs(60), 16, |_params: Params| Ok(Value::String("world".into())));
+	let server = ServerBuilder::new(io)
+		.cacheable_methods(vec!["hello".into()])
+		.start_http(&"127.0.0.1:0".parse().unwrap())
+		.unwrap();
+	let addr = server.address().clone();
+	let req = r#"{"jsonrpc":"2.0","id":1,"method":"hello"}"#;
+	let raw = |if_none_match: &str| format!("\
+		POST / HTTP/1.1\r\n\
+		Host: localhost:{}\r\n\
+		Connection: close\r\n\
+		Content-Type: application/json\r\n\
+		{}\
+		Content-Length: {}\r\n\
+		\r\n\
+		{}\r\n\
+	", addr.port(), if_none_match, req.as_bytes().len(), req);
+
+	// when
+	let first = request_to(&addr, raw("").as_bytes());
+	let etag = first.headers.lines()
+		.find(|line| line.starts_with("ETag: "))
+		.map(|line| line["ETag: ".len()..].to_owned())
+		.expect("ETag header is missing");
+	let cached = request_to(&addr, raw(&format!("If-None-Match: {}\r\n", etag)).as_bytes());
+	let stale = request(server, &raw("If-None-Match: \"other\"\r\n"));
+
+	// then
+	assert_eq!(first.status, "HTTP/1.1 200 OK".to_owned());
+	assert_eq!(first.body, world());
+	assert_eq!(cached.status, "HTTP/1.1 304 Not Modified".to_owned());
+	assert!(cached.headers.contains(&format!("ETag: {}", etag)), "Headers missing in {}", cached.headers);
+	assert_eq!(cached.body, "");
+	assert_eq!(stale.status, "HTTP/1.1 200 OK".to_owned());
+	assert_eq!(stale.body, world());
+}
+
+#[test]
+fn should_reject_requests_when_handler_pool_is_saturated() {
+	use std::thread;
+	use std::time::Duration;
+
+	// given
+	let mut io = IoHandler::default();
+	io.add_method("heavy", |_params: Params| {
+		thread::sleep(Duration::from_millis(300));
+		Ok(Value::String("done".into()))
+	});
+	let server = ServerBuilder::new(io)
+		.handler_pool_size(1)
+		.reject_when_saturated(Duration::from_secs(5))
+		.start_http(&"127.0.0.1:0".parse().unwrap())
+		.unwrap();
+	let addr = server.address().clone();
+	let req = r#"{"jsonrpc":"2.0","id":1,"method":"heavy"}"#;
+	let raw = format!("\
+		POST / HTTP/1.1\r\n\
+		Host: localhost:{}\r\n\
+		Connection: close\r\n\
+		Content-Type: application/json\r\n\
+		Content-Length: {}\r\n\
+		\r\n\
+		{}\r\n\
+	", addr.port(), req.as_bytes().len(), req);
+
+	// when
+	let busy = {
+		let raw = raw.clone();
+		thread::spawn(move || request_to(&addr, raw.as_bytes()))
+	};
+	thread::sleep(Duration::from_millis(100));
+	let rejected = request_to(&addr, raw.as_bytes());
+
+	// then
+	assert_eq!(rejected.status, "HTTP/1.1 503 Service Unavailable".to_owned());
+	assert!(rejected.headers.contains("Retry-After: 5"), "Headers missing in {}", rejected.headers);
+	assert_eq!(rejected.body, "21\nServer is busy, try again later.\n".to_owned());
+	assert_eq!(busy.join().unwrap().status, "HTTP/1.1 200 OK".to_owned());
+}
+
+#[test]
+fn should_pause_reading_bodies_over_total_buffer_limit() {
+	use std::thread;
+	use std::time::Duration;
+
+	// given
+	let mut io = IoHandler::default();
+	io.add_method("hello", |_params: Params| Ok(Value::String("world".into())));
+	let server = ServerBuilder::new(io)
+		.max_total_buffered_bytes(100)
+		.start_http(&"127.0.0.1:0".parse().unwrap())
+		.unwrap();
+	let addr = server.address().clone();
+	let req = format!("{:<60}", r#"{"jsonrpc":"2.0","id":1,"method":"hello"}"#);
+	let headers = format!("\
+		POST / HTTP/1.1\r\n\
+		Host: localhost:{}\r\n\
+		Connection: close\r\n\
+		Content-Type: application/json\r\n\
+		Content-Length: {}\r\n\
+		\r\n\
+	", addr.port(), req.len());
+
+	// when
+	let mut streams = (0..3).map(|_| {
+		let mut stream = TcpStream::connect(&addr).unwrap();
+		stream.write_all(headers.as_bytes()).unwrap();
+		stream.write_all(req[..10].as_bytes()).unwrap();
+		stream
+	}).collect::<Vec<_>>();
+	thread::sleep(Duration::from_millis(100));
+	let buffered = server.buffered_bytes();
+	let responses = streams.iter_mut().map(|stream| {
+		stream.write_all(req[10..].as_bytes()).unwrap();
+		let mut response = String::new();
+		stream.read_to_string(&mut response).unwrap();
+		response
+	}).collect::<Vec<_>>();
+
+	// then
+	assert_eq!(buffered, 60);
+	for response in responses {
+		assert!(response.starts_with("HTTP/1.1 200 OK"), "Unexpected response: {}", response);
+	}
+	assert_eq!(server.buffered_bytes(), 0);
+}
+
+#[test]
+fn should_reserve_buffers_of_chunked_bodies_as_chunks_arrive() {
+	use std::thread;
+	use std::time::Duration;
+
+	// given
+	let mut io = IoHandler::default();
+	io.add_method("hello", |_params: Params| Ok(Value::String("world".into())));
+	let server = ServerBuilder::new(io)
+		.max_total_buffered_bytes(100)
+		.start_http(&"127.0.0.1:0".parse().unwrap())
+		.unwrap();
+	let addr = server.address().clone();
+	let req = r#"{"jsonrpc":"2.0","id":1,"method":"hello"}"#;
+	let headers = format!("\
+		POST / HTTP/1.1\r\n\
+		Host: localhost:{}\r\n\
+		Connection: close\r\n\
+		Content-Type: application/json\r\n\
+		Transfer-Encoding: chunked\r\n\
+		\r\n\
+	", addr.port());
+
+	// when
+	let mut stream = TcpStream::connect(&addr).unwrap();
+	stream.write_all(headers.as_bytes()).unwrap();
+	stream.write_all(format!("{:x}\r\n{}\r\n", 10, &req[..10]).as_bytes()).unwrap();
+	thread::sleep(Duration::from_millis(100));
+	let buffered = server.buffered_bytes();
+	stream.write_all(format!("{:x}\r\n{}\r\n0\r\n\r\n", req.len() - 10, &req[10..]).as_bytes()).unwrap();
+	let mut response = String::new();
+	stream.read_to_string(&mut response).unwrap();
+
+	// then
+	assert_eq!(buffered, 10);
+	assert!(response.starts_with("HTTP/1.1 200 OK"), "Unexpected response: {}", response);
+	assert_eq!(server.buffered_bytes(), 0);
+}
+
+#[test]
+#[cfg(unix)]
+fn should_serve_requests_with_configured_number_of_threads() {
+	// given
+	let server = ServerBuilder::new(IoHandler::default())
+		.threads(3)
+		.start_http(&"127.0.0.1:0".parse().unwrap())
+		.unwrap();
+	let addr = server.address().clone();
+
+	// when
+	let req = r#"{"jsonrpc":"2.0","id":1,"method":"x"}"#;
+	let response = request_to(&addr, format!("\
+		POST / HTTP/1.1\r\n\
+		Host: localhost:{}\r\n\
+		Connection: close\r\n\
+		Content-Type: application/json\r\n\
+		Content-Length: {}\r\n\
+		\r\n\
+		{}\r\n\
+	", addr.port(), req.as_bytes().len(), req).as_bytes());
+
+	// then
+	assert_eq!(server.threads(), 3);
+	assert_eq!(response.status, "HTTP/1.1 200 OK".to_owned());
+	assert_eq!(response.body, method_not_found());
+}
+
+#[test]
+#[should_panic(expected = "at least 1 thread")]
+fn should_panic_when_configured_with_no_threads() {
+	let _ = ServerBuilder::new(IoHandler::default()).threads(0);
+}
+
+#[test]
+fn should_escape_non_ascii_output_when_enabled() {
+	// given
+	let mut io = IoHandler::default();
+	io.add_method("hello", |_params: Params| Ok(Value::String("café 😀".into())));
+	let server = ServerBuilder::new(io)
+		.ascii_only_output(true)
+		.start_http(&"127.0.0.1:0".parse().unwrap())
+		.unwrap();
+
+	// when
+	let req = r#"{"jsonrpc":"2.0","id":1,"method":"hello"}"#;
+	let response = request(server,
+		&format!("\
+			POST / HTTP/1.1\r\n\
+			Host: 127.0.0.1:8080\r\n\
+			Connection: close\r\n\
+			Content-Type: application/json\r\n\
+			Content-Length: {}\r\n\
+			\r\n\
+			{}\r\n\
+		", req.as_bytes().len(), req)
+	);
+
+	// then
+	assert_eq!(response.status, "HTTP/1.1 200 OK".to_owned());
+	assert_eq!(response.body, "3B\n{\"jsonrpc\":\"2.0\",\"result\":\"caf\\u00e9 \\ud83d\\ude00\",\"id\":1}\n".to_owned());
+}
+
+#[test]
+fn should_pass_remote_address_and_selected_headers_to_handlers() {
+	// given
+	let mut io = MetaIoHandler::default();
+	io.add_method_with_meta("whoami", |_params: Params, meta: RequestMetadata| {
+		let ip = meta.remote_addr.map(|addr| addr.ip().to_string()).unwrap_or_default();
+		let token = meta.headers.get("X-Auth-Token").cloned().unwrap_or_default();
+		let secret = meta.headers.contains_key("X-Secret");
+		Ok(Value::String(format!("{} {} {}", ip, token, secret)))
+	});
+	let server = ServerBuilder::new(io)
+		.meta_extractor(RequestMetadataExtractor::new(vec!["X-Auth-Token"]))
+		.start_http(&"127.0.0.1:0".parse().unwrap())
+		.unwrap();
+	let addr = server.address().clone();
+
+	// when
+	let req = r#"{"jsonrpc":"2.0","id":1,"method":"whoami"}"#;
+	let response = request(server,
+		&format!("\
+			POST / HTTP/1.1\r\n\
+			Host: localhost:{}\r\n\
+			Connection: close\r\n\
+			Content-Type: application/json\r\n\
+			X-Auth-Token: abc\r\n\
+			X-Secret: xyz\r\n\
+			Content-Length: {}\r\n\
+			\r\n\
+			{}\r\n\
+		", addr.port(), req.as_bytes().len(), req)
+	);
+
+	// then
+	assert_eq!(response.status, "HTTP/1.1 200 OK".to_owned());
+	assert_eq!(response.body, "38\n{\"jsonrpc\":\"2.0\",\"result\":\"127.0.0.1 abc false\",\"id\":1}\n".to_owned());
+}
+
+#[test]
+fn should_use_source_address_from_proxy_protocol_header() {
+	#[derive(Clone, Default)]
+	struct Peer(Option<SocketAddr>);
+	impl jsonrpc_core::Metadata for Peer {}
+
+	// given
+	let mut io = MetaIoHandler::default();
+	io.add_method_with_meta("peer", |_params: Params, meta: Peer| {
+		Ok(Value::String(meta.0.map(|addr| addr.to_string()).unwrap_or_default()))
+	});
+	let server = ServerBuilder::new(io)
+		.meta_extractor(|request: &hyper::server::Request| Peer(request.remote_addr()))
+		.accept_proxy_protocol(true)
+		.start_http(&"127.0.0.1:0".parse().unwrap())
+		.unwrap();
+
+	// when
+	let req = r#"{"jsonrpc":"2.0","id":1,"method":"peer"}"#;
+	let response = request(server,
+		&format!("\
+			PROXY TCP4 203.0.113.7 127.0.0.1 56324 80\r\n\
+			POST / HTTP/1.1\r\n\
+			Host: 127.0.0.1:8080\r\n\
+			Connection: close\r\n\
+			Content-Type: application/json\r\n\
+			Content-Length: {}\r\n\
+			\r\n\
+			{}\r\n\
+		", req.as_bytes().len(), req)
+	);
+
+	// then
+	assert_eq!(response.status, "HTTP/1.1 200 OK".to_owned());
+	assert_eq!(response.body, "36\n{\"jsonrpc\":\"2.0\",\"result\":\"203.0.113.7:56324\",\"id\":1}\n".to_owned());
+}
+
+#[test]
+fn should_add_success_flag_to_outputs_when_configured() {
+	// given
+	let mut io = IoHandler::default();
+	io.add_method("hello", |_params: Params| Ok(Value::String("world".into())));
+	let server = ServerBuilder::new(io)
+		.error_envelope(ErrorEnvelope::SuccessFlag)
+		.start_http(&"127.0.0.1:0".parse().unwrap())
+		.unwrap();
+	let addr = server.address().clone();
+	let raw = |method: &str| {
+		let req = format!(r#"{{"jsonrpc":"2.0","id":1,"method":"{}"}}"#, method);
+		format!("\
+			POST / HTTP/1.1\r\n\
+			Host: localhost:{}\r\n\
+			Connection: close\r\n\
+			Content-Type: application/json\r\n\
+			Content-Length: {}\r\n\
+			\r\n\
+			{}\r\n\
+		", addr.port(), req.as_bytes().len(), req)
+	};
+
+	// when
+	let failure = request_to(&addr, raw("x").as_bytes());
+	let success = request_to(&addr, raw("hello").as_bytes());
+
+	// then
+	assert_eq!(failure.status, "HTTP/1.1 200 OK".to_owned());
+	assert_eq!(
+		failure.body,
+		"5E\n{\"success\":false,\"jsonrpc\":\"2.0\",\"error\":{\"code\":-32601,\"message\":\"Method not found\"},\"id\":1}\n".to_owned()
+	);
+	assert_eq!(success.body, "39\n{\"success\":true,\"jsonrpc\":\"2.0\",\"result\":\"world\",\"id\":1}\n".to_owned());
+}
+
+#[test]
+fn should_map_errors_onto_http_statuses_when_configured() {
+	// given
+	let mut io = IoHandler::default();
+	io.add_method("hello", |_params: Params| Ok(Value::String("world".into())));
+	let server = ServerBuilder::new(io)
+		.error_status(ErrorStatus::Mapped)
+		.start_http(&"127.0.0.1:0".parse().unwrap())
+		.unwrap();
+	let addr = server.address().clone();
+	let raw = |req: &str| {
+		format!("\
+			POST / HTTP/1.1\r\n\
+			Host: localhost:{}\r\n\
+			Connection: close\r\n\
+			Content-Type: application/json\r\n\
+			Content-Length: {}\r\n\
+			\r\n\
+			{}\r\n\
+		", addr.port(), req.as_bytes().len(), req)
+	};
+
+	// when
+	let parse_error = request_to(&addr, raw(r#"{"jsonrpc":"2.0","#).as_bytes());
+	let invalid = request_to(&addr, raw(r#"{"jsonrpc":"3.0","method":"x"}"#).as_bytes());
+	let not_found = request_to(&addr, raw(r#"{"jsonrpc":"2.0","id":1,"method":"x"}"#).as_bytes());
+	let success = request_to(&addr, raw(r#"{"jsonrpc":"2.0","id":1,"method":"hello"}"#).as_bytes());
+
+	// then
+	assert_eq!(parse_error.status, "HTTP/1.1 400 Bad Request".to_owned());
+	assert_eq!(
+		parse_error.body,
+		"4C\n{\"jsonrpc\":\"2.0\",\"error\":{\"code\":-32700,\"message\":\"Parse error\"},\"id\":null}\n".to_owned()
+	);
+	assert_eq!(invalid.status, "HTTP/1.1 400 Bad Request".to_owned());
+	assert_eq!(invalid.body, invalid_request());
+	assert_eq!(not_found.status, "HTTP/1.1 404 Not Found".to_owned());
+	assert_eq!(not_found.body, method_not_found());
+	assert_eq!(success.status, "HTTP/1.1 200 OK".to_owned());
+	assert_eq!(success.body, world());
+}
+
+#[test]
+fn should_include_method_in_response_when_enabled() {
+	// given
+	let mut io = IoHandler::default();
+	io.add_method("hello", |_params: Params| Ok(Value::String("world".into())));
+	let server = ServerBuilder::new(io)
+		.include_method_in_response(true)
+		.start_http(&"127.0.0.1:0".parse().unwrap())
+		.unwrap();
+	let addr = server.address().clone();
+
+	// when
+	let req = r#"[{"jsonrpc":"2.0","id":1,"method":"hello"},{"jsonrpc":"2.0","id":2,"method":"x"}]"#;
+	let response = request(server,
+		&format!("\
+			POST / HTTP/1.1\r\n\
+			Host: localhost:{}\r\n\
+			Connection: close\r\n\
+			Content-Type: application/json\r\n\
+			Content-Length: {}\r\n\
+			\r\n\
+			{}\r\n\
+		", addr.port(), req.as_bytes().len(), req)
+	);
+
+	// then
+	assert_eq!(response.status, "HTTP/1.1 200 OK".to_owned());
+	assert_eq!(
+		response.body,
+		"98\n[{\"method\":\"hello\",\"jsonrpc\":\"2.0\",\"result\":\"world\",\"id\":1},\
+		{\"method\":\"x\",\"jsonrpc\":\"2.0\",\"error\":{\"code\":-32601,\"message\":\"Method not found\"},\"id\":2}]\n".to_owned()
+	);
+}
+
+#[test]
+fn should_rewrite_errors_for_configured_client_version() {
+	#[derive(Clone, Default)]
+	struct ClientVersion(Option<String>);
+	impl jsonrpc_core::Metadata for ClientVersion {}
+
+	// given
+	let server = ServerBuilder::new(MetaIoHandler::<ClientVersion>::default())
+		.meta_extractor(|request: &hyper::server::Request| ClientVersion(
+			request.headers().get_raw("X-Client-Version")
+				.and_then(|value| value.one())
+				.map(|value| String::from_utf8_lossy(value).into_owned())
+		))
+		.error_rewriter(|meta: &ClientVersion, mut error: Error| {
+			if meta.0.as_ref().map(String::as_str) == Some("1") && error.code == ErrorCode::MethodNotFound {
+				error.code = ErrorCode::ServerError(-32000);
+			}
+			error
+		})
+		.start_http(&"127.0.0.1:0".parse().unwrap())
+		.unwrap();
+	let addr = server.address().clone();
+	let req = r#"{"jsonrpc":"2.0","id":1,"method":"x"}"#;
+	let raw = |version: &str| format!("\
+		POST / HTTP/1.1\r\n\
+		Host: localhost:{}\r\n\
+		Connection: close\r\n\
+		Content-Type: application/json\r\n\
+		X-Client-Version: {}\r\n\
+		Content-Length: {}\r\n\
+		\r\n\
+		{}\r\n\
+	", addr.port(), version, req.as_bytes().len(), req);
+
+	// when
+	let old = request_to(&addr, raw("1").as_bytes());
+	let new = request_to(&addr, raw("2").as_bytes());
+
+	// then
+	assert_eq!(old.status, "HTTP/1.1 200 OK".to_owned());
+	assert_eq!(old.body, "4E\n{\"jsonrpc\":\"2.0\",\"error\":{\"code\":-32000,\"message\":\"Method not found\"},\"id\":1}\n".to_owned());
+	assert_eq!(new.body, method_not_found());
+}
+
+#[test]
+fn should_add_server_timing_header_when_enabled() {
+	use std::thread;
+
+	// given
+	let mut io = IoHandler::default();
+	io.add_method("hello_async2", |_params: Params| {
+		let (c, p) = futures::oneshot();
+		thread::spawn(move || {
+			thread::sleep(::std::time::Duration::from_millis(10));
+			c.send(Value::String("world".into())).unwrap();
+		});
+		p.map_err(|_| Error::invalid_request())
+	});
+	let server = ServerBuilder::new(io)
+		.server_timing(true)
+		.start_http(&"127.0.0.1:0".parse().unwrap())
+		.unwrap();
+	let addr = server.address().clone();
+
+	// when
+	let req = r#"{"jsonrpc":"2.0","id":1,"method":"hello_async2"}"#;
+	let response = request(server,
+		&format!("\
+			POST / HTTP/1.1\r\n\
+			Host: localhost:{}\r\n\
+			Connection: close\r\n\
+			Content-Type: application/json\r\n\
+			Content-Length: {}\r\n\
+			\r\n\
+			{}\r\n\
+		", addr.port(), req.as_bytes().len(), req)
+	);
+
+	// then
+	assert_eq!(response.status, "HTTP/1.1 200 OK".to_owned());
+	assert_eq!(response.body, world());
+	let duration = response.headers.lines()
+		.find(|line| line.starts_with("Server-Timing: handler;dur="))
+		.map(|line| line["Server-Timing: handler;dur=".len()..].parse::<f64>().unwrap())
+		.expect(&format!("Server-Timing header missing in {}", response.headers));
+	assert!(duration >= 10.0, "Expected handler duration of at least 10ms, got: {}", duration);
+}
+
+#[test]
+fn should_finish_requests_in_flight_when_closing() {
+	use std::thread;
+	use std::time::Duration;
+
+	// given
+	let mut io = IoHandler::default();
+	io.add_method("hello_slow", |_params: Params| {
+		let (c, p) = futures::oneshot();
+		thread::spawn(move || {
+			thread::sleep(Duration::from_millis(200));
+			c.send(Value::String("world".into())).unwrap();
+		});
+		p.map_err(|_| Error::invalid_request())
+	});
+	let server = ServerBuilder::new(io)
+		.shutdown_timeout(Duration::from_secs(5))
+		.start_http(&"127.0.0.1:0".parse().unwrap())
+		.unwrap();
+	let addr = server.address().clone();
+	let req = r#"{"jsonrpc":"2.0","id":1,"method":"hello_slow"}"#;
+	let raw = format!("\
+		POST / HTTP/1.1\r\n\
+		Host: localhost:{}\r\n\
+		Connection: close\r\n\
+		Content-Type: application/json\r\n\
+		Content-Length: {}\r\n\
+		\r\n\
+		{}\r\n\
+	", addr.port(), req.as_bytes().len(), req);
+	let client = thread::spawn(move || request_to(&addr, raw.as_bytes()));
+	while server.requests_in_flight() == 0 {
+		thread::sleep(Duration::from_millis(5));
+	}
+
+	// when
+	server.close();
+
+	// then
+	let response = client.join().unwrap();
+	assert_eq!(response.status, "HTTP/1.1 200 OK".to_owned());
+	assert_eq!(response.body, world());
+	assert!(TcpStream::connect(&addr).is_err(), "Server should not accept new connections after close.");
+}
+
+#[test]
+fn should_use_swapped_handler_for_new_requests() {
+	// given
+	let server = serve();
+	let addr = server.address().clone();
+	let req = r#"{"jsonrpc":"2.0","id":1,"method":"hello"}"#;
+	let raw = format!("\
+		POST / HTTP/1.1\r\n\
+		Host: localhost:{}\r\n\
+		Connection: close\r\n\
+		Content-Type: application/json\r\n\
+		Content-Length: {}\r\n\
+		\r\n\
+		{}\r\n\
+	", addr.port(), req.as_bytes().len(), req);
+	let before = request_to(&addr, raw.as_bytes());
+
+	// when
+	let mut io = IoHandler::default();
+	io.add_method("hello", |_params: Params| Ok(Value::String("swapped".into())));
+	server.swap_handler(io);
+	let after = request_to(&addr, raw.as_bytes());
+
+	// then
+	assert_eq!(before.body, world());
+	assert_eq!(after.status, "HTTP/1.1 200 OK".to_owned());
+	assert_eq!(after.body, "2C\n{\"jsonrpc\":\"2.0\",\"result\":\"swapped\",\"id\":1}\n".to_owned());
+}
+
+#[test]
+fn should_only_serve_gated_methods_when_enabled() {
+	#[derive(Clone, Default)]
+	struct Features { beta: bool }
+	impl jsonrpc_core::Metadata for Features {}
+
+	// given
+	let mut io = MetaIoHandler::default();
+	io.add_method_gated("beta_hello", |meta: &Features| meta.beta, |_params: Params| Ok(Value::String("world".into())));
+	let server = ServerBuilder::new(io)
+		.meta_extractor(|request: &hyper::server::Request| Features {
+			beta: request.headers().get_raw("X-Beta").and_then(|value| value.one()) == Some(&b"1"[..]),
+		})
+		.start_http(&"127.0.0.1:0".parse().unwrap())
+		.unwrap();
+	let addr = server.address().clone();
+	let req = r#"{"jsonrpc":"2.0","id":1,"method":"beta_hello"}"#;
+	let raw = |beta: &str| format!("\
+		POST / HTTP/1.1\r\n\
+		Host: localhost:{}\r\n\
+		Connection: close\r\n\
+		Content-Type: application/json\r\n\
+		X-Beta: {}\r\n\
+		Content-Length: {}\r\n\
+		\r\n\
+		{}\r\n\
+	", addr.port(), beta, req.as_bytes().len(), req);
+
+	// when
+	let enabled = request_to(&addr, raw("1").as_bytes());
+	let disabled = request(server, &raw("0"));
+
+	// then
+	assert_eq!(enabled.body, world());
+	assert_eq!(disabled.body, method_not_found());
+}
+
+#[derive(Clone, Default)]
+struct ApiVersion(u32);
+impl jsonrpc_core::Metadata for ApiVersion {}
+
+struct ApiVersionExtractor;
+impl MetaExtractor<ApiVersion> for ApiVersionExtractor {
+	fn read_metadata(&self, _: &hyper::server::Request) -> ApiVersion {
+		ApiVersion::default()
+	}
+
+	fn read_versioned_metadata(&self, _: &hyper::server::Request, version: u32) -> ApiVersion {
+		ApiVersion(version)
+	}
+}
+
+#[test]
+fn should_read_api_version_from_path() {
+	// given
+	let mut io = MetaIoHandler::default();
+	io.add_method_with_meta("version", |_params: Params, meta: ApiVersion| Ok(Value::Number(meta.0.into())));
+	let server = ServerBuilder::new(io)
+		.meta_extractor(ApiVersionExtractor)
+		.path_version_prefix("/v", vec![1, 2])
+		.start_http(&"127.0.0.1:0".parse().unwrap())
+		.unwrap();
+	let addr = server.address().clone();
+	let req = r#"{"jsonrpc":"2.0","id":1,"method":"version"}"#;
+	let raw = |path: &str| format!("\
+		POST {} HTTP/1.1\r\n\
+		Host: localhost:{}\r\n\
+		Connection: close\r\n\
+		Content-Type: application/json\r\n\
+		Content-Length: {}\r\n\
+		\r\n\
+		{}\r\n\
+	", path, addr.port(), req.as_bytes().len(), req);
+
+	// when
+	let supported = request_to(&addr, raw("/v2").as_bytes());
+	let unsupported = request(server, &raw("/v99"));
+
+	// then
+	assert_eq!(supported.status, "HTTP/1.1 200 OK".to_owned());
+	assert_eq!(supported.body, "23\n{\"jsonrpc\":\"2.0\",\"result\":2,\"id\":1}\n");
+	assert_eq!(unsupported.status, "HTTP/1.1 404 Not Found".to_owned());
+}
+
+#[test]
+fn should_serve_routes_with_their_own_handlers() {
+	// given
+	let mut public = IoHandler::default();
+	public.add_method("hello", |_params: Params| Ok(Value::String("world".into())));
+	let mut admin = IoHandler::default();
+	admin.add_method("shutdown", |_params: Params| Ok(Value::String("done".into())));
+	let server = ServerBuilder::new(IoHandler::default())
+		.route("/public", public, RouteOptions::new())
+		.route("/admin", admin, RouteOptions::new().authorize(|request| {
+			request.headers().get_raw("Authorization").and_then(|value| value.one()) == Some(&b"secret"[..])
+		}))
+		.start_http(&"127.0.0.1:0".parse().unwrap())
+		.unwrap();
+	let addr = server.address().clone();
+	let raw = |path: &str, method: &str, authorization: &str| {
+		let req = format!(r#"{{"jsonrpc":"2.0","id":1,"method":"{}"}}"#, method);
+		format!("\
+			POST {} HTTP/1.1\r\n\
+			Host: localhost:{}\r\n\
+			Connection: close\r\n\
+			Content-Type: application/json\r\n\
+			Authorization: {}\r\n\
+			Content-Length: {}\r\n\
+			\r\n\
+			{}\r\n\
+		", path, addr.port(), authorization, req.as_bytes().len(), req)
+	};
+
+	// when
+	let public_hello = request_to(&addr, raw("/public", "hello", "").as_bytes());
+	let public_shutdown = request_to(&addr, raw("/public", "shutdown", "").as_bytes());
+	let admin_shutdown = request_to(&addr, raw("/admin", "shutdown", "secret").as_bytes());
+	let admin_hello = request_to(&addr, raw("/admin", "hello", "secret").as_bytes());
+	let admin_unauthorized = request_to(&addr, raw("/admin", "shutdown", "wrong").as_bytes());
+	let unknown = request(server, &raw("/other", "hello", ""));
+
+	// then
+	assert_eq!(public_hello.status, "HTTP/1.1 200 OK".to_owned());
+	assert_eq!(public_hello.body, world());
+	assert_eq!(public_shutdown.body, method_not_found());
+	assert_eq!(admin_shutdown.status, "HTTP/1.1 200 OK".to_owned());
+	assert_eq!(admin_shutdown.body, "29\n{\"jsonrpc\":\"2.0\",\"result\":\"done\",\"id\":1}\n");
+	assert_eq!(admin_hello.body, method_not_found());
+	assert_eq!(admin_unauthorized.status, "HTTP/1.1 401 Unauthorized".to_owned());
+	assert_eq!(unknown.status, "HTTP/1.1 404 Not Found".to_owned());
+}
+
+#[test]
+fn should_cache_idempotent_responses_of_each_route_separately() {
+	use std::time::Duration;
+
+	// given
+	let mut public = IoHandler::default();
+	public.add_method("ping", |_params: Params| Ok(Value::String("public".into())));
+	let mut admin = IoHandler::default();
+	admin.add_method("ping", |_params: Params| Ok(Value::String("admin".into())));
+	let server = ServerBuilder::new(IoHandler::default())
+		.route("/public", public, RouteOptions::new())
+		.route("/admin", admin, RouteOptions::new())
+		.idempotency(16, Duration::from_secs(60))
+		.start_http(&"127.0.0.1:0".parse().unwrap())
+		.unwrap();
+	let addr = server.address().clone();
+	let req = r#"{"jsonrpc":"2.0","id":1,"method":"ping"}"#;
+	let raw = |path: &str| format!("\
+		POST {} HTTP/1.1\r\n\
+		Host: localhost:{}\r\n\
+		Connection: close\r\n\
+		Content-Type: application/json\r\n\
+		Idempotency-Key: a\r\n\
+		Content-Length: {}\r\n\
+		\r\n\
+		{}\r\n\
+	", path, addr.port(), req.as_bytes().len(), req);
+
+	// when
+	let admin = request_to(&addr, raw("/admin").as_bytes());
+	let public = request(server, &raw("/public"));
+
+	// then
+	assert_eq!(admin.body, "2A\n{\"jsonrpc\":\"2.0\",\"result\":\"admin\",\"id\":1}\n");
+	assert_eq!(public.body, "2B\n{\"jsonrpc\":\"2.0\",\"result\":\"public\",\"id\":1}\n");
+}
+
+#[test]
+fn should_return_configured_response_for_unknown_path() {
+	// given
+	let server = ServerBuilder::new(IoHandler::default())
+		.path_version_prefix("/v", vec![1])
+		.not_found_response(hyper::StatusCode::NotFound, r#"{"error":"Unknown path"}"#, hyper::header::ContentType::json())
+		.start_http(&"127.0.0.1:0".parse().unwrap())
+		.unwrap();
+
+	// when
+	let req = r#"{"jsonrpc":"2.0","id":1,"method":"x"}"#;
+	let response = request(server,
+		&format!("\
+			POST /v99 HTTP/1.1\r\n\
+			Host: 127.0.0.1:8080\r\n\
+			Connection: close\r\n\
+			Content-Type: application/json\r\n\
+			Content-Length: {}\r\n\
+			\r\n\
+			{}\r\n\
+		", req.as_bytes().len(), req)
+	);
+
+	// then
+	assert_eq!(response.status, "HTTP/1.1 404 Not Found".to_owned());
+	assert!(response.headers.contains("Content-Type: application/json"), "Headers missing in {}", response.headers);
+	assert_eq!(response.body, "18\n{\"error\":\"Unknown path\"}\n".to_owned());
+}
+
+#[test]
+fn should_reject_batch_with_duplicate_ids_when_configured() {
+	// given
+	let mut io = IoHandler::default();
+	io.add_method("hello", |_params: Params| Ok(Value::String("world".into())));
+	let server = ServerBuilder::new(io)
+		.duplicate_batch_id_policy(DuplicateBatchIdPolicy::Reject)
+		.start_http(&"127.0.0.1:0".parse().unwrap())
+		.unwrap();
+
+	// when
+	let req = r#"[{"jsonrpc":"2.0","id":1,"method":"hello"},{"jsonrpc":"2.0","id":1,"method":"hello"}]"#;
+	let response = request(server,
+		&format!("\
+			POST / HTTP/1.1\r\n\
+			Host: 127.0.0.1:8080\r\n\
+			Connection: close\r\n\
+			Content-Type: application/json\r\n\
+			Content-Length: {}\r\n\
+			\r\n\
+			{}\r\n\
+		", req.as_bytes().len(), req)
+	);
+
+	// then
+	assert_eq!(response.status, "HTTP/1.1 200 OK".to_owned());
+	assert_eq!(response.body, invalid_request());
+}
+
+#[test]
+fn should_verify_signature_of_request_body() {
+	// given
+	let mut io = IoHandler::default();
+	io.add_method("hello", |_params: Params| Ok(Value::String("world".into())));
+	let server = ServerBuilder::new(io)
+		.verify_signature("secret", "X-Signature")
+		.start_http(&"127.0.0.1:0".parse().unwrap())
+		.unwrap();
+	let addr = server.address().clone();
+	let req = r#"{"jsonrpc":"2.0","id":1,"method":"hello"}"#;
+	let raw = |signature: &str| format!("\
+		POST / HTTP/1.1\r\n\
+		Host: localhost:{}\r\n\
+		Connection: close\r\n\
+		Content-Type: application/json\r\n\
+		X-Signature: {}\r\n\
+		Content-Length: {}\r\n\
+		\r\n\
+		{}\r\n\
+	", addr.port(), signature, req.as_bytes().len(), req);
+
+	// when
+	let signed = request_to(&addr, raw("57f98347386892d9de9abb20dd8e5ce0123a394185a97ee8844424b738d8725d").as_bytes());
+	let invalid = request(server, &raw("00f98347386892d9de9abb20dd8e5ce0123a394185a97ee8844424b738d8725d"));
+
+	// then
+	assert_eq!(signed.status, "HTTP/1.1 200 OK".to_owned());
+	assert_eq!(signed.body, world());
+	assert_eq!(invalid.status, "HTTP/1.1 401 Unauthorized".to_owned());
+	assert_eq!(invalid.body, "29\nRequest signature is missing or invalid.\n".to_owned());
+}
+
+#[test]
+fn should_verify_signature_before_returning_cached_response() {
+	use std::time::Duration;
+
+	// given
+	let mut io = IoHandler::default();
+	io.add_method("hello", |_params: Params| Ok(Value::String("world".into())));
+	let server = ServerBuilder::new(io)
+		.verify_signature("secret", "X-Signature")
+		.idempotency(16, Duration::from_secs(60))
+		.start_http(&"127.0.0.1:0".parse().unwrap())
+		.unwrap();
+	let addr = server.address().clone();
+	let req = r#"{"jsonrpc":"2.0","id":1,"method":"hello"}"#;
+	let raw = |signature: &str| format!("\
+		POST / HTTP/1.1\r\n\
+		Host: localhost:{}\r\n\
+		Connection: close\r\n\
+		Content-Type: application/json\r\n\
+		Idempotency-Key: a\r\n\
+		X-Signature: {}\r\n\
+		Content-Length: {}\r\n\
+		\r\n\
+		{}\r\n\
+	", addr.port(), signature, req.as_bytes().len(), req);
+
+	// when
+	let signed = request_to(&addr, raw("57f98347386892d9de9abb20dd8e5ce0123a394185a97ee8844424b738d8725d").as_bytes());
+	let invalid = request(server, &raw("00f98347386892d9de9abb20dd8e5ce0123a394185a97ee8844424b738d8725d"));
+
+	// then
+	assert_eq!(signed.body, world());
+	assert_eq!(invalid.status, "HTTP/1.1 401 Unauthorized".to_owned());
+	assert_eq!(invalid.body, "29\nRequest signature is missing or invalid.\n".to_owned());
+}
+
+#[test]
+fn should_compress_only_responses_above_threshold() {
+	use flate2::read::GzDecoder;
+
+	// given
+	let mut io = IoHandler::default();
+	io.add_method("hello", |_params: Params| Ok(Value::String("world".into())));
+	io.add_method("large", |_params: Params| Ok(Value::String("a".repeat(200))));
+	let server = ServerBuilder::new(io)
+		.compression(CompressionMode::OnlyLarge(100))
+		.start_http(&"127.0.0.1:0".parse().unwrap())
+		.unwrap();
+	let addr = server.address().clone();
+	let raw = |method: &str| {
+		let req = format!(r#"{{"jsonrpc":"2.0","id":1,"method":"{}"}}"#, method);
+		format!("\
+			POST / HTTP/1.1\r\n\
+			Host: localhost:{}\r\n\
+			Connection: close\r\n\
+			Content-Type: application/json\r\n\
+			Accept-Encoding: gzip\r\n\
+			Content-Length: {}\r\n\
+			\r\n\
+			{}\r\n\
+		", addr.port(), req.as_bytes().len(), req)
+	};
+
+	// when
+	let small = request_to(&addr, raw("hello").as_bytes());
+	let (headers, chunks) = read_chunks(&request_raw(&addr, raw("large").as_bytes()));
+	let mut large = String::new();
+	GzDecoder::new(&*chunks.concat()).read_to_string(&mut large).unwrap();
+
+	// then
+	assert_eq!(small.status, "HTTP/1.1 200 OK".to_owned());
+	assert!(!small.headers.contains("Content-Encoding"), "Unexpected encoding in {}", small.headers);
+	assert_eq!(small.body, world());
+	assert!(headers.contains("Content-Encoding: gzip"), "Headers missing in {}", headers);
+	assert_eq!(large, format!("{{\"jsonrpc\":\"2.0\",\"result\":\"{}\",\"id\":1}}\n", "a".repeat(200)));
+}
+
+#[test]
+fn should_not_compress_responses_when_client_opts_out() {
+	// given
+	let mut io = IoHandler::default();
+	io.add_method("hello", |_params: Params| Ok(Value::String("world".into())));
+	let server = ServerBuilder::new(io)
+		.compression(CompressionMode::Always)
+		.start_http(&"127.0.0.1:0".parse().unwrap())
+		.unwrap();
+
+	// when
+	let req = r#"{"jsonrpc":"2.0","id":1,"method":"hello"}"#;
+	let response = request(server,
+		&format!("\
+			POST / HTTP/1.1\r\n\
+			Host: 127.0.0.1:8080\r\n\
+			Connection: close\r\n\
+			Content-Type: application/json\r\n\
+			Accept-Encoding: gzip\r\n\
+			X-No-Compression: true\r\n\
+			Content-Length: {}\r\n\
+			\r\n\
+			{}\r\n\
+		", req.as_bytes().len(), req)
+	);
+
+	// then
+	assert_eq!(response.status, "HTTP/1.1 200 OK".to_owned());
+	assert!(!response.headers.contains("Content-Encoding"), "Unexpected encoding in {}", response.headers);
+	assert_eq!(response.body, world());
+}
+
+#[test]
+fn should_not_compress_responses_by_default() {
+	// given
+	let server = serve();
+
+	// when
+	let req = r#"{"jsonrpc":"2.0","id":1,"method":"hello"}"#;
+	let response = request(server,
+		&format!("\
+			POST / HTTP/1.1\r\n\
+			Host: 127.0.0.1:8080\r\n\
+			Connection: close\r\n\
+			Content-Type: application/json\r\n\
+			Accept-Encoding: gzip\r\n\
+			Content-Length: {}\r\n\
 			\r\n\
 			{}\r\n\
-		", addr.port(), req.as_bytes().len(), req)
+		", req.as_bytes().len(), req)
 	);
 
 	// then
 	assert_eq!(response.status, "HTTP/1.1 200 OK".to_owned());
-	assert_eq!(response.body, method_not_found());
+	assert!(!response.headers.contains("Content-Encoding"), "Unexpected encoding in {}", response.headers);
+	assert_eq!(response.body, world());
 }
 
 #[test]
-fn should_handle_sync_requests_correctly() {
+fn should_report_reset_connections() {
+	use std::sync::{mpsc, Mutex};
+	use std::thread;
+	use std::time::Duration;
+	use net2::TcpStreamExt;
+
 	// given
-	let server = serve();
+	let (tx, rx) = mpsc::channel();
+	let tx = Mutex::new(tx);
+	let server = ServerBuilder::new(IoHandler::default())
+		.on_connection_error(move |error| {
+			let _ = tx.lock().unwrap().send(error);
+		})
+		.start_http(&"127.0.0.1:0".parse().unwrap())
+		.unwrap();
+
+	// when
+	let mut stream = TcpStream::connect(server.address()).unwrap();
+	stream.write_all(b"POST / HTTP/1.1\r\nHost: 127.0.0.1:8080\r\n").unwrap();
+	thread::sleep(Duration::from_millis(100));
+	// Closing the socket without lingering sends RST to the server.
+	stream.set_linger(Some(Duration::from_secs(0))).unwrap();
+	let peer = stream.local_addr().unwrap();
+	drop(stream);
+
+	// then
+	let error = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+	assert_eq!(error.kind, ConnectionErrorKind::Reset);
+	assert_eq!(error.peer, peer);
+}
+
+#[test]
+fn should_report_failed_writes_and_recover() {
+	use std::sync::{mpsc, Mutex};
+	use std::thread;
+	use std::time::Duration;
+	use net2::TcpStreamExt;
+
+	// given
+	let (tx, rx) = mpsc::channel();
+	let tx = Mutex::new(tx);
+	let mut io = IoHandler::default();
+	io.add_method("hello", |_params: Params| Ok(Value::String("world".into())));
+	io.add_method("large", |_params: Params| Ok(Value::String(::std::iter::repeat('x').take(32 * 1024 * 1024).collect())));
+	let server = ServerBuilder::new(io)
+		.on_connection_error(move |error| {
+			let _ = tx.lock().unwrap().send(error);
+		})
+		.start_http(&"127.0.0.1:0".parse().unwrap())
+		.unwrap();
 	let addr = server.address().clone();
 
 	// when
+	let req = r#"{"jsonrpc":"2.0","id":1,"method":"large"}"#;
+	let mut stream = TcpStream::connect(&addr).unwrap();
+	stream.write_all(format!("\
+		POST / HTTP/1.1\r\n\
+		Host: localhost:{}\r\n\
+		Content-Type: application/json\r\n\
+		Content-Length: {}\r\n\
+		\r\n\
+		{}\
+	", addr.port(), req.as_bytes().len(), req).as_bytes()).unwrap();
+	// Read the beginning of the response only and close the socket with RST.
+	let mut buf = [0u8; 1024];
+	stream.read_exact(&mut buf).unwrap();
+	stream.set_linger(Some(Duration::from_secs(0))).unwrap();
+	let peer = stream.local_addr().unwrap();
+	drop(stream);
+
+	// then
+	let error = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+	assert_eq!(error.kind, ConnectionErrorKind::WriteFailed);
+	assert_eq!(error.peer, peer);
+
+	// the handler of the dropped response is released
+	let mut in_flight = server.requests_in_flight();
+	for _ in 0..100 {
+		if in_flight == 0 {
+			break;
+		}
+		thread::sleep(Duration::from_millis(10));
+		in_flight = server.requests_in_flight();
+	}
+	assert_eq!(in_flight, 0);
+
+	// and the server keeps responding
 	let req = r#"{"jsonrpc":"2.0","id":1,"method":"hello"}"#;
 	let response = request(server,
 		&format!("\
@@ -599,10 +3064,233 @@ fn should_handle_sync_requests_correctly() {
 			{}\r\n\
 		", addr.port(), req.as_bytes().len(), req)
 	);
+	assert_eq!(response.status, "HTTP/1.1 200 OK".to_owned());
+	assert_eq!(response.body, world());
+}
+
+#[test]
+fn should_close_connections_over_per_ip_limit() {
+	use std::thread;
+	use std::time::Duration;
+
+	// given
+	let server = ServerBuilder::new(IoHandler::default())
+		.max_connections_per_ip(2)
+		.start_http(&"127.0.0.1:0".parse().unwrap())
+		.unwrap();
+	let addr = server.address().clone();
+	let req = r#"{"jsonrpc":"2.0","id":1,"method":"x"}"#;
+	let raw = format!("\
+		POST / HTTP/1.1\r\n\
+		Host: localhost:{}\r\n\
+		Connection: close\r\n\
+		Content-Type: application/json\r\n\
+		Content-Length: {}\r\n\
+		\r\n\
+		{}\r\n\
+	", addr.port(), req.as_bytes().len(), req);
+
+	// when
+	let first = TcpStream::connect(&addr).unwrap();
+	let _second = TcpStream::connect(&addr).unwrap();
+	let mut excess = TcpStream::connect(&addr).unwrap();
+	excess.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+	let mut buf = [0; 1];
+	let excess_read = excess.read(&mut buf);
+	drop(first);
+	thread::sleep(Duration::from_millis(100));
+	let response = request_to(&addr, raw.as_bytes());
 
 	// then
+	assert!(excess_read.map(|read| read == 0).unwrap_or(true), "Excess connection was not closed");
 	assert_eq!(response.status, "HTTP/1.1 200 OK".to_owned());
-	assert_eq!(response.body, world());
+	assert_eq!(response.body, method_not_found());
+}
+
+#[test]
+fn should_echo_keep_alive_for_http10_requests() {
+	// given
+	let server = serve();
+	let addr = server.address().clone();
+
+	// when
+	let req = r#"{"jsonrpc":"2.0","id":1,"method":"hello"}"#;
+	let mut stream = TcpStream::connect(&addr).unwrap();
+	stream.set_read_timeout(Some(::std::time::Duration::from_secs(5))).unwrap();
+	stream.write_all(format!("\
+		POST / HTTP/1.0\r\n\
+		Host: localhost:{}\r\n\
+		Connection: keep-alive\r\n\
+		Content-Type: application/json\r\n\
+		Content-Length: {}\r\n\
+		\r\n\
+		{}\
+	", addr.port(), req.as_bytes().len(), req).as_bytes()).unwrap();
+
+	// the connection stays open, so read only as much as the response announces
+	let expected_body = "{\"jsonrpc\":\"2.0\",\"result\":\"world\",\"id\":1}\n";
+	let mut response = Vec::new();
+	let mut buf = [0u8; 1024];
+	while !String::from_utf8_lossy(&response).ends_with(expected_body) {
+		let read = stream.read(&mut buf).unwrap();
+		assert!(read > 0, "Connection closed: {}", String::from_utf8_lossy(&response));
+		response.extend_from_slice(&buf[..read]);
+	}
+
+	// then
+	let response = String::from_utf8(response).unwrap();
+	assert!(response.starts_with("HTTP/1."), "Unexpected response: {}", response);
+	assert!(response.lines().next().unwrap().ends_with("200 OK"), "Unexpected response: {}", response);
+	assert!(response.contains("Connection: keep-alive"), "Headers missing in {}", response);
+	assert!(response.contains(&format!("Content-Length: {}", expected_body.len())), "Headers missing in {}", response);
+}
+
+#[test]
+fn should_respond_to_sequential_requests_on_keep_alive_connection() {
+	// given
+	let mut io = IoHandler::default();
+	io.add_method("hello", |_params: Params| Ok(Value::String("world".into())));
+	let server = ServerBuilder::new(io)
+		.idle_timeout(Duration::from_millis(200))
+		.start_http(&"127.0.0.1:0".parse().unwrap())
+		.unwrap();
+	let addr = server.address().clone();
+	let mut stream = TcpStream::connect(&addr).unwrap();
+	stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+
+	// when
+	let mut responses = Vec::new();
+	for id in 1..3 {
+		let req = format!(r#"{{"jsonrpc":"2.0","id":{},"method":"hello"}}"#, id);
+		stream.write_all(format!("\
+			POST / HTTP/1.1\r\n\
+			Host: localhost:{}\r\n\
+			Content-Type: application/json\r\n\
+			Content-Length: {}\r\n\
+			\r\n\
+			{}\
+		", addr.port(), req.as_bytes().len(), req).as_bytes()).unwrap();
+
+		// the connection stays open, so read only as much as the response announces
+		let expected_body = format!("{{\"jsonrpc\":\"2.0\",\"result\":\"world\",\"id\":{}}}\n", id);
+		let mut response = Vec::new();
+		let mut buf = [0u8; 1024];
+		while !String::from_utf8_lossy(&response).ends_with(&expected_body) {
+			let read = stream.read(&mut buf).unwrap();
+			assert!(read > 0, "Connection closed: {}", String::from_utf8_lossy(&response));
+			response.extend_from_slice(&buf[..read]);
+		}
+		responses.push(String::from_utf8(response).unwrap());
+	}
+	// idle connection gets closed after the timeout
+	let mut buf = [0u8; 1024];
+	let read_after_idle = stream.read(&mut buf);
+
+	// then
+	assert_eq!(responses.len(), 2);
+	for response in &responses {
+		assert!(response.starts_with("HTTP/1.1 200 OK"), "Unexpected response: {}", response);
+	}
+	let closed = match read_after_idle {
+		Ok(read) => read == 0,
+		Err(err) => err.kind() != ::std::io::ErrorKind::WouldBlock && err.kind() != ::std::io::ErrorKind::TimedOut,
+	};
+	assert!(closed, "Idle connection was not closed");
+}
+
+#[test]
+fn should_close_keep_alive_connections_after_max_age() {
+	use std::time::Instant;
+
+	// given
+	let mut io = IoHandler::default();
+	io.add_method("hello", |_params: Params| Ok(Value::String("world".into())));
+	let server = ServerBuilder::new(io)
+		.max_connection_age(Duration::from_millis(300))
+		.start_http(&"127.0.0.1:0".parse().unwrap())
+		.unwrap();
+	let addr = server.address().clone();
+	let mut stream = TcpStream::connect(&addr).unwrap();
+	stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+	let connected_at = Instant::now();
+
+	// when
+	let req = r#"{"jsonrpc":"2.0","id":1,"method":"hello"}"#;
+	stream.write_all(format!("\
+		POST / HTTP/1.1\r\n\
+		Host: localhost:{}\r\n\
+		Content-Type: application/json\r\n\
+		Content-Length: {}\r\n\
+		\r\n\
+		{}\
+	", addr.port(), req.as_bytes().len(), req).as_bytes()).unwrap();
+
+	// the connection stays open, so read only as much as the response announces
+	let expected_body = "{\"jsonrpc\":\"2.0\",\"result\":\"world\",\"id\":1}\n";
+	let mut response = Vec::new();
+	let mut buf = [0u8; 1024];
+	while !String::from_utf8_lossy(&response).ends_with(expected_body) {
+		let read = stream.read(&mut buf).unwrap();
+		assert!(read > 0, "Connection closed: {}", String::from_utf8_lossy(&response));
+		response.extend_from_slice(&buf[..read]);
+	}
+	let read_after_age = stream.read(&mut buf);
+
+	// then
+	let response = String::from_utf8(response).unwrap();
+	assert!(response.starts_with("HTTP/1.1 200 OK"), "Unexpected response: {}", response);
+	assert_eq!(read_after_age.unwrap(), 0);
+	assert!(connected_at.elapsed() >= Duration::from_millis(300));
+}
+
+#[test]
+fn should_replace_too_large_responses_with_error() {
+	// given
+	let mut io = IoHandler::default();
+	io.add_method("hello", |_params: Params| Ok(Value::String("world".into())));
+	io.add_method("large", |_params: Params| Ok(Value::Array(vec![Value::String("world".into()); 1024])));
+	let server = ServerBuilder::new(io)
+		.max_response_size(128)
+		.start_http(&"127.0.0.1:0".parse().unwrap())
+		.unwrap();
+	let addr = server.address().clone();
+	let raw = |req: &str| format!("\
+		POST / HTTP/1.1\r\n\
+		Host: localhost:{}\r\n\
+		Connection: close\r\n\
+		Content-Type: application/json\r\n\
+		Content-Length: {}\r\n\
+		\r\n\
+		{}\r\n\
+	", addr.port(), req.as_bytes().len(), req);
+
+	// when
+	let large = request_to(&addr, raw(r#"{"jsonrpc":"2.0","id":1,"method":"large"}"#).as_bytes());
+	let small = request(server, &raw(r#"{"jsonrpc":"2.0","id":1,"method":"hello"}"#));
+
+	// then
+	assert_eq!(large.status, "HTTP/1.1 200 OK".to_owned());
+	assert_eq!(
+		large.body,
+		"50\n{\"jsonrpc\":\"2.0\",\"error\":{\"code\":-32000,\"message\":\"Response too large\"},\"id\":1}\n"
+	);
+	assert_eq!(small.body, world());
+}
+
+#[test]
+fn should_serialize_errors_the_same_way_as_the_server() {
+	// given
+	let error = Error::method_not_found();
+
+	// when
+	let json = error.to_response_json(jsonrpc_core::Id::Num(1));
+
+	// then
+	assert_eq!(format!("4E\n{}\n", json), method_not_found());
+	assert_eq!(
+		format!("50\n{}\n", Error::invalid_request().to_response_json(jsonrpc_core::Id::Null)),
+		invalid_request()
+	);
 }
 
 #[test]
@@ -655,6 +3343,127 @@ fn should_handle_async_requests_correctly() {
 	assert_eq!(response.body, world());
 }
 
+#[test]
+fn should_respond_to_request_followed_by_half_close() {
+	use std::net::Shutdown;
+
+	// given
+	let server = serve();
+	let addr = server.address().clone();
+	let req = r#"{"jsonrpc":"2.0","id":1,"method":"hello_async2"}"#;
+	let mut stream = TcpStream::connect(&addr).unwrap();
+
+	// when
+	stream.write_all(format!("\
+		POST / HTTP/1.1\r\n\
+		Host: localhost:{}\r\n\
+		Content-Type: application/json\r\n\
+		Content-Length: {}\r\n\
+		\r\n\
+		{}\
+	", addr.port(), req.as_bytes().len(), req).as_bytes()).unwrap();
+	stream.shutdown(Shutdown::Write).unwrap();
+	let mut response = String::new();
+	stream.read_to_string(&mut response).unwrap();
+
+	// then
+	let mut lines = response.lines();
+	assert_eq!(lines.next(), Some("HTTP/1.1 200 OK"));
+	read_block(&mut lines);
+	assert_eq!(read_block(&mut lines), world());
+}
+
+#[test]
+fn should_call_methods_through_http_client() {
+	// given
+	let server = serve();
+	let client = HttpClient::new(&format!("http://{}", server.address())).unwrap();
+
+	// when
+	let result = client.call_method("hello", Params::Array(vec![Value::from(5)])).wait();
+	let error = client.call_method("nope", Params::None).wait();
+	let batch = client.call_batch(vec![
+		("hello_async".into(), Params::None),
+		("nope".into(), Params::None),
+	]).wait();
+
+	// then
+	assert_eq!(result, Ok(Value::String("world: 5".into())));
+	assert_eq!(error, Err(RpcError::Server(Error::method_not_found())));
+	assert_eq!(batch, Ok(vec![Ok(Value::String("world".into())), Err(RpcError::Server(Error::method_not_found()))]));
+}
+
+#[test]
+fn should_read_errors_sent_with_error_status_through_http_client() {
+	// given
+	let mut io = IoHandler::default();
+	io.add_method("hello", |_params: Params| Ok(Value::String("world".into())));
+	let server = ServerBuilder::new(io)
+		.error_status(ErrorStatus::Mapped)
+		.start_http(&"127.0.0.1:0".parse().unwrap())
+		.unwrap();
+	let client = HttpClient::new(&format!("http://{}", server.address())).unwrap();
+
+	// when
+	let error = client.call_method("nope", Params::None).wait();
+
+	// then
+	assert_eq!(error, Err(RpcError::Server(Error::method_not_found())));
+}
+
+#[test]
+fn should_reject_outputs_with_other_id_through_http_client() {
+	use std::net::TcpListener;
+	use std::thread;
+
+	// given
+	let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+	let client = HttpClient::new(&format!("http://{}", listener.local_addr().unwrap())).unwrap();
+	let server = thread::spawn(move || {
+		let (mut stream, _) = listener.accept().unwrap();
+		let mut request = [0u8; 1024];
+		let _ = stream.read(&mut request).unwrap();
+		let body = r#"{"jsonrpc":"2.0","result":"world","id":42}"#;
+		stream.write_all(format!(
+			"HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+			body.len(),
+			body,
+		).as_bytes()).unwrap();
+	});
+
+	// when
+	let result = client.call_method("hello", Params::None).wait();
+
+	// then
+	server.join().unwrap();
+	assert_eq!(result, Err(RpcError::InvalidResponse("id of the output doesn't match the call".into())));
+}
+
+#[test]
+fn should_send_batches_with_notifications_through_http_client() {
+	// given
+	let server = serve();
+	let client = HttpClient::new(&format!("http://{}", server.address())).unwrap();
+
+	// when
+	let batch = client.batch()
+		.call("hello", Params::None)
+		.notify("hello", Params::None)
+		.call("hello", Params::None)
+		.send()
+		.wait();
+	let notifications = client.batch()
+		.notify("hello", Params::None)
+		.send()
+		.wait();
+	let notification = client.notify("hello", Params::None).wait();
+
+	// then
+	assert_eq!(batch, Ok(vec![Ok(Value::String("world".into())), Ok(Value::String("world".into()))]));
+	assert_eq!(notifications, Ok(vec![]));
+	assert_eq!(notification, Ok(()));
+}
+
 #[test]
 fn should_handle_sync_batch_requests_correctly() {
 	// given
@@ -680,6 +3489,50 @@ fn should_handle_sync_batch_requests_correctly() {
 	assert_eq!(response.body, world_batch());
 }
 
+#[test]
+fn should_respond_to_each_call_of_mixed_batch() {
+	// given
+	let server = serve();
+	let addr = server.address().clone();
+
+	// when
+	let req = r#"[
+		{"jsonrpc":"2.0","id":1,"method":"hello"},
+		{"jsonrpc":"2.0","method":"hello"},
+		{"jsonrpc":"2.0","id":3,"method":"x"},
+		{"jsonrpc":"2.0","id":4,"params":"invalid"}
+	]"#;
+	let response = request(server,
+		&format!("\
+			POST / HTTP/1.1\r\n\
+			Host: localhost:{}\r\n\
+			Connection: close\r\n\
+			Content-Type: application/json\r\n\
+			Content-Length: {}\r\n\
+			\r\n\
+			{}\r\n\
+		", addr.port(), req.as_bytes().len(), req)
+	);
+
+	// then
+	assert_eq!(response.status, "HTTP/1.1 200 OK".to_owned());
+	let body = response.body.lines().nth(1).unwrap();
+	let outputs = match serde_json::from_str::<Value>(body).unwrap() {
+		Value::Array(outputs) => outputs,
+		other => panic!("Expected batch response, got: {:?}", other),
+	};
+	let mut outputs: Vec<_> = outputs.into_iter().map(|output| {
+		let code = output.pointer("/error/code").cloned();
+		(output["id"].clone(), output.get("result").cloned(), code)
+	}).collect();
+	outputs.sort_by_key(|output| output.0.as_u64());
+	assert_eq!(outputs, vec![
+		(Value::from(1), Some(Value::String("world".into())), None),
+		(Value::from(3), None, Some(Value::from(ErrorCode::MethodNotFound.code()))),
+		(Value::from(4), None, Some(Value::from(ErrorCode::InvalidRequest.code()))),
+	]);
+}
+
 #[test]
 fn should_handle_rest_request_with_params() {
 	// given
@@ -729,6 +3582,74 @@ fn should_return_error_in_case_of_unsecure_rest_and_no_method() {
 	assert_eq!(&response.body, "51\nSupplied content type is not allowed. Content-Type: application/json is required\n");
 }
 
+fn passthrough_io<S: jsonrpc_core::Middleware<()>>(middleware: S) -> MetaIoHandler<(), S> {
+	use self::jsonrpc_core::HandlerResult;
+
+	let mut io = MetaIoHandler::with_middleware(middleware);
+	io.add_passthrough_method_with_meta("proxy", |_params: Params, _meta: ()| {
+		Ok(HandlerResult::Passthrough(r#"{"id":1, "result": {"b": 1, "a": 2}, "jsonrpc": "2.0"}"#.into()))
+	});
+	io
+}
+
+#[test]
+fn should_pass_passthrough_calls_through_middleware() {
+	// given
+	let server = ServerBuilder::new(passthrough_io(Rejecting))
+		.start_http(&"127.0.0.1:0".parse().unwrap())
+		.unwrap();
+	let addr = server.address().clone();
+
+	// when
+	let req = r#"{"jsonrpc":"2.0","id":1,"method":"proxy"}"#;
+	let response = request(server,
+		&format!("\
+			POST / HTTP/1.1\r\n\
+			Host: localhost:{}\r\n\
+			Connection: close\r\n\
+			Content-Type: application/json\r\n\
+			Content-Length: {}\r\n\
+			\r\n\
+			{}\r\n\
+		", addr.port(), req.as_bytes().len(), req)
+	);
+
+	// then
+	assert_eq!(response.status, "HTTP/1.1 200 OK".to_owned());
+	assert_eq!(
+		response.body,
+		"49\n{\"jsonrpc\":\"2.0\",\"error\":{\"code\":-32010,\"message\":\"Rejected\"},\"id\":null}\n"
+	);
+}
+
+#[test]
+fn should_return_passthrough_responses_verbatim_when_requests_are_parsed() {
+	// given
+	let server = ServerBuilder::new(passthrough_io(jsonrpc_core::NoopMiddleware))
+		.request_timeout(::std::time::Duration::from_secs(5))
+		.start_http(&"127.0.0.1:0".parse().unwrap())
+		.unwrap();
+	let addr = server.address().clone();
+
+	// when
+	let req = r#"{"jsonrpc":"2.0","id":1,"method":"proxy"}"#;
+	let response = request(server,
+		&format!("\
+			POST / HTTP/1.1\r\n\
+			Host: localhost:{}\r\n\
+			Connection: close\r\n\
+			Content-Type: application/json\r\n\
+			Content-Length: {}\r\n\
+			\r\n\
+			{}\r\n\
+		", addr.port(), req.as_bytes().len(), req)
+	);
+
+	// then
+	assert_eq!(response.status, "HTTP/1.1 200 OK".to_owned());
+	assert_eq!(response.body, "37\n{\"id\":1, \"result\": {\"b\": 1, \"a\": 2}, \"jsonrpc\": \"2.0\"}\n");
+}
+
 fn invalid_host() -> String {
 	"29\nProvided Host header is not whitelisted.\n".into()
 }
@@ -1,10 +1,12 @@
+use std::net::IpAddr;
+
 use hyper::{header, server};
 
 use server_utils::{cors, hosts};
 pub use server_utils::cors::CorsHeader;
 
 /// Extracts string value of a single header in request.
-fn read_header<'a>(req: &'a server::Request, header: &str) -> Option<&'a str> {
+pub fn read_header<'a>(req: &'a server::Request, header: &str) -> Option<&'a str> {
 	match req.headers().get_raw(header) {
 		Some(ref v) if v.len() == 1 => {
 			::std::str::from_utf8(&v[0]).ok()
@@ -22,11 +24,22 @@ pub fn is_host_allowed(
 }
 
 /// Returns a CORS header that should be returned with that request.
+///
+/// If the request comes from one of `trusted_proxies` the scheme of the request is read from `X-Forwarded-Proto`.
 pub fn cors_header(
 	request: &server::Request,
-	cors_domains: &Option<Vec<cors::AccessControlAllowOrigin>>
+	cors_domains: &Option<Vec<cors::AccessControlAllowOrigin>>,
+	trusted_proxies: &[IpAddr],
 ) -> CorsHeader<header::AccessControlAllowOrigin> {
-	cors::get_cors_header(read_header(request, "origin"), read_header(request, "host"), cors_domains).map(|origin| {
+	let is_trusted = request.remote_addr().map_or(false, |addr| trusted_proxies.contains(&addr.ip()));
+	let scheme = if is_trusted {
+		Some(read_header(request, "x-forwarded-proto").unwrap_or("http"))
+	} else {
+		None
+	};
+	let origin = read_header(request, "origin");
+	let host = read_header(request, "host");
+	cors::get_cors_header_for_scheme(origin, host, scheme, cors_domains).map(|origin| {
 		use self::cors::AccessControlAllowOrigin::*;
 		match origin {
 			Value(val) => header::AccessControlAllowOrigin::Value((*val).to_owned()),
@@ -35,3 +48,12 @@ pub fn cors_header(
 		}
 	})
 }
+
+/// Parses API version from the first segment of the path, e.g. `2` from `/v2` given `/v` prefix.
+pub fn path_version(path: &str, prefix: &str) -> Option<u32> {
+	if !path.starts_with(prefix) {
+		return None;
+	}
+
+	path[prefix.len()..].split('/').next().and_then(|version| version.parse().ok())
+}
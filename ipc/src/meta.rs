@@ -10,6 +10,8 @@ pub struct RequestContext<'a> {
 	pub endpoint_addr: &'a ::parity_tokio_ipc::RemoteId,
 	/// Direct pipe sender
 	pub sender: mpsc::Sender<String>,
+	/// Maximal number of subscriptions per connection, see `ServerBuilder::max_subscriptions_per_connection`.
+	pub max_subscriptions: Option<usize>,
 }
 
 /// Metadata extractor (per session)
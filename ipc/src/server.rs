@@ -1,4 +1,5 @@
 use std;
+use std::path::Path;
 use std::sync::Arc;
 
 use tokio_service::{self, Service as TokioService};
@@ -50,6 +51,7 @@ pub struct ServerBuilder<M: Metadata = (), S: Middleware<M> = NoopMiddleware> {
 	remote: reactor::UninitializedRemote,
 	incoming_separator: codecs::Separator,
 	outgoing_separator: codecs::Separator,
+	max_subscriptions: Option<usize>,
 }
 
 impl<M: Metadata + Default, S: Middleware<M>> ServerBuilder<M, S> {
@@ -74,6 +76,7 @@ impl<M: Metadata, S: Middleware<M>> ServerBuilder<M, S> {
 			remote: reactor::UninitializedRemote::Unspawned,
 			incoming_separator: codecs::Separator::Empty,
 			outgoing_separator: codecs::Separator::default(),
+			max_subscriptions: None,
 		}
 	}
 
@@ -104,15 +107,30 @@ impl<M: Metadata, S: Middleware<M>> ServerBuilder<M, S> {
 		self
 	}
 
+	/// Limits the number of subscriptions active at once on a single connection.
+	///
+	/// The limit is passed to metadata extractors in `RequestContext::max_subscriptions`,
+	/// to be applied to pub-sub sessions with `Session::max_subscriptions`.
+	pub fn max_subscriptions_per_connection(mut self, limit: usize) -> Self {
+		self.max_subscriptions = Some(limit);
+		self
+	}
+
 	/// Run server (in a separate thread)
-	pub fn start(self, path: &str) -> std::io::Result<Server> {
+	///
+	/// Fails with `InvalidInput` error if the path is not valid UTF-8.
+	pub fn start<P: AsRef<Path>>(self, path: P) -> std::io::Result<Server> {
+		let endpoint_addr = match path.as_ref().to_str() {
+			Some(path) => path.to_owned(),
+			None => return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, "IPC endpoint path is not valid UTF-8")),
+		};
 		let remote = self.remote.initialize()?;
 		let rpc_handler = self.handler;
-		let endpoint_addr = path.to_owned();
 		let meta_extractor = self.meta_extractor;
 		let session_stats = self.session_stats;
 		let incoming_separator = self.incoming_separator;
 		let outgoing_separator = self.outgoing_separator;
+		let max_subscriptions = self.max_subscriptions;
 		let (stop_signal, stop_receiver) = oneshot::channel();
 		let (start_signal, start_receiver) = oneshot::channel();
 		let (wait_signal, wait_receiver) = oneshot::channel();
@@ -151,6 +169,7 @@ impl<M: Metadata, S: Middleware<M>> ServerBuilder<M, S> {
 					endpoint_addr: &remote_id,
 					session_id,
 					sender,
+					max_subscriptions,
 				});
 				let service = Service::new(rpc_handler.clone(), meta);
 				let (writer, reader) = io_stream.framed(
@@ -355,6 +374,45 @@ mod tests {
 		UnixStream::connect(path).wait().expect("Socket should connect");
 	}
 
+	#[test]
+	fn request_to_io_handler_at_path() {
+		use std::path::Path;
+		use jsonrpc::IoHandler;
+
+		::logger::init_log();
+		let path = Path::new("/tmp/test-ipc-46000");
+		let mut io = IoHandler::new();
+		io.add_method("hello", |_params| Ok(Value::String("world".to_string())));
+		let server = ServerBuilder::new(io).start(path).expect("Server must run with no issues");
+
+		let result = dummy_request_str(
+			path.to_str().unwrap(),
+			"{\"jsonrpc\":\"2.0\",\"id\":1,\"method\":\"hello\"}",
+		);
+
+		assert_eq!(
+			result,
+			"{\"jsonrpc\":\"2.0\",\"result\":\"world\",\"id\":1}",
+			"Response does not exactly match the expected response",
+		);
+		server.close();
+	}
+
+	#[test]
+	fn start_at_non_utf8_path() {
+		use std::ffi::OsStr;
+		use std::io::ErrorKind;
+		use std::os::unix::ffi::OsStrExt;
+		use std::path::Path;
+
+		::logger::init_log();
+		let path = Path::new(OsStr::from_bytes(b"/tmp/test-ipc-\xff"));
+		let server = ServerBuilder::new(MetaIoHandler::<()>::default());
+
+		let error = server.start(path).err().expect("Server must not start at non UTF-8 path");
+		assert_eq!(error.kind(), ErrorKind::InvalidInput);
+	}
+
 	#[test]
 	fn request() {
 		::logger::init_log();
@@ -184,6 +184,7 @@ macro_rules! build_rpc_trait {
 		$del.add_method($name, move |base, params| {
 			$crate::WrapAsync::wrap_rpc(&(Self::$method as fn(&_ $(, $param)*) -> $result <$out $(, $error)*>), base, params)
 		});
+		$del.describe_method($name, &[$(stringify!($param)),*], stringify!($out));
 		$(
 			$(
 				$del.add_alias($alias, $name);
@@ -198,6 +199,7 @@ macro_rules! build_rpc_trait {
 		$del.add_method_with_meta($name, move |base, params, meta| {
 			$crate::WrapMeta::wrap_rpc(&(Self::$method as fn(&_, Self::Metadata $(, $param)*) -> $result <$out $(, $error)* >), base, params, meta)
 		});
+		$del.describe_method($name, &[$(stringify!($param)),*], stringify!($out));
 		$(
 			$(
 				$del.add_alias($alias, $name);
@@ -2,6 +2,7 @@ use std::sync::Arc;
 use std::collections::HashMap;
 
 use jsonrpc_core::{Params, Value, Error};
+use jsonrpc_core::serde_json::Map;
 use jsonrpc_core::{BoxFuture, Metadata, RemoteProcedure, RpcMethod, RpcNotification};
 use jsonrpc_core::futures::IntoFuture;
 
@@ -98,6 +99,24 @@ impl<T, F, I> jsonrpc_pubsub::UnsubscribeRpcMethod for DelegateUnsubscribe<T, F>
 	}
 }
 
+/// Parameter and result type names of a method, as declared in the RPC trait.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MethodDescription {
+	/// Names of parameter types, in order.
+	pub params: Vec<String>,
+	/// Name of the result type.
+	pub result: String,
+}
+
+impl MethodDescription {
+	fn to_value(&self) -> Value {
+		let mut map = Map::new();
+		map.insert("params".into(), Value::Array(self.params.iter().cloned().map(Value::String).collect()));
+		map.insert("result".into(), Value::String(self.result.clone()));
+		Value::Object(map)
+	}
+}
+
 /// A set of RPC methods and notifications tied to single `delegate` struct.
 pub struct IoDelegate<T, M = ()> where
 	T: Send + Sync + 'static,
@@ -105,6 +124,7 @@ pub struct IoDelegate<T, M = ()> where
 {
 	delegate: Arc<T>,
 	methods: HashMap<String, RemoteProcedure<M>>,
+	descriptions: HashMap<String, MethodDescription>,
 }
 
 impl<T, M> IoDelegate<T, M> where
@@ -116,7 +136,41 @@ impl<T, M> IoDelegate<T, M> where
 		IoDelegate {
 			delegate: delegate,
 			methods: HashMap::new(),
+			descriptions: HashMap::new(),
+		}
+	}
+
+	/// Records parameter and result type names of given method.
+	pub fn describe_method(&mut self, name: &str, params: &[&str], result: &str) {
+		self.descriptions.insert(name.into(), MethodDescription {
+			params: params.iter().map(|param| param.to_string()).collect(),
+			result: result.into(),
+		});
+	}
+
+	/// Returns descriptions of all described methods.
+	pub fn descriptions(&self) -> &HashMap<String, MethodDescription> {
+		&self.descriptions
+	}
+
+	/// Adds `system.describeMethod` returning description of a method given its name.
+	/// Only methods (and aliases) added to this delegate before calling this are described.
+	pub fn add_introspection(&mut self) {
+		let mut descriptions = self.descriptions.clone();
+		for (name, method) in &self.methods {
+			if let RemoteProcedure::Alias(ref to) = *method {
+				if let Some(description) = self.descriptions.get(to) {
+					descriptions.insert(name.clone(), description.clone());
+				}
+			}
 		}
+
+		self.add_method("system.describeMethod", move |_, params: Params| {
+			let (name, ): (String, ) = params.parse()?;
+			descriptions.get(&name)
+				.map(MethodDescription::to_value)
+				.ok_or_else(|| Error::invalid_params(format!("Unknown method: {}", name)))
+		});
 	}
 
 	/// Adds an alias to existing method.
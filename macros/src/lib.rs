@@ -66,5 +66,5 @@ pub use auto_args::{WrapAsync, WrapMeta, WrapSubscribe};
 pub use serde::{de::DeserializeOwned, Serialize};
 
 pub use auto_args::Trailing;
-pub use delegates::IoDelegate;
+pub use delegates::{IoDelegate, MethodDescription};
 pub use util::to_value;
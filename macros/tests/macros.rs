@@ -71,3 +71,29 @@ fn should_accept_empty_array_as_no_params() {
 	let result3: Response = serde_json::from_str(&res3.unwrap()).unwrap();
 	assert_eq!(expected, result3);
 }
+
+#[test]
+fn should_describe_param_and_result_types() {
+	let mut io = IoHandler::new();
+	let rpc = RpcImpl::default();
+	let mut delegate = rpc.to_delegate();
+	delegate.add_introspection();
+	io.extend_with(delegate);
+
+	// when
+	let req = r#"{"jsonrpc":"2.0","id":1,"method":"system.describeMethod","params":["add"]}"#;
+	let res = io.handle_request_sync(req);
+
+	// then
+	let expected = r#"{
+		"jsonrpc": "2.0",
+		"result": {
+			"params": ["u64", "u64"],
+			"result": "u64"
+		},
+		"id": 1
+	}"#;
+	let expected: Response = serde_json::from_str(expected).unwrap();
+	let result: Response = serde_json::from_str(&res.unwrap()).unwrap();
+	assert_eq!(expected, result);
+}
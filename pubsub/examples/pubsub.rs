@@ -59,7 +59,10 @@ fn main() {
 	);
 
 	let server = ServerBuilder::new(io)
-		.session_meta_extractor(|context: &RequestContext| Some(Arc::new(Session::new(context.sender.clone()))))
+		.session_meta_extractor(|context: &RequestContext| {
+			Some(Arc::new(Session::new(context.sender.clone()).max_subscriptions(context.max_subscriptions)))
+		})
+		.max_subscriptions_per_connection(16)
 		.start(&"127.0.0.1:3030".parse().unwrap())
 		.expect("Unable to start RPC server");
 
@@ -73,6 +73,33 @@ impl<T: PubSubMetadata, S: core::Middleware<T>> PubSubHandler<T, S> {
 		self.handler.add_method_with_meta(subscribe.0, sub);
 		self.handler.add_method_with_meta(unsubscribe.0, unsub);
 	}
+
+	/// Adds new subscription requiring authorization.
+	///
+	/// `authorize` is called with parameters and metadata of each subscribe call before the subscribe handler.
+	/// Subscriptions it returns an error for (e.g. `-32000` "Unauthorized topic") are rejected with that error.
+	pub fn add_authorized_subscription<F, G, A>(
+		&mut self,
+		notification: &str,
+		subscribe: (&str, F),
+		unsubscribe: (&str, G),
+		authorize: A,
+	) where
+		F: SubscribeRpcMethod<T>,
+		G: UnsubscribeRpcMethod,
+		A: Fn(&core::Params, &T) -> Result<(), core::Error> + Send + Sync + 'static,
+	{
+		let handler = subscribe.1;
+		let authorized = move |params: core::Params, meta: T, subscriber: Subscriber| {
+			match authorize(&params, &meta) {
+				Ok(()) => handler.call(params, meta, subscriber),
+				Err(error) => {
+					let _ = subscriber.reject(error);
+				},
+			}
+		};
+		self.add_subscription(notification, (subscribe.0, authorized), unsubscribe);
+	}
 }
 
 impl<T: PubSubMetadata, S: core::Middleware<T>> ::std::ops::Deref for PubSubHandler<T, S> {
@@ -149,4 +176,50 @@ mod tests {
 		assert_eq!(called.load(Ordering::SeqCst), true);
 	}
 
+	#[derive(Clone, Default)]
+	struct AuthMetadata {
+		is_admin: bool,
+	}
+	impl core::Metadata for AuthMetadata {}
+	impl PubSubMetadata for AuthMetadata {
+		fn session(&self) -> Option<Arc<Session>> {
+			let (tx, _rx) = mpsc::channel(1);
+			Some(Arc::new(Session::new(tx)))
+		}
+	}
+
+	#[test]
+	fn should_reject_unauthorized_subscriptions() {
+		// given
+		let mut handler = PubSubHandler::default();
+		handler.add_authorized_subscription(
+			"hello",
+			("subscribe_hello", |_params, _meta, subscriber: Subscriber| {
+				let _sink = subscriber.assign_id(SubscriptionId::Number(5));
+			}),
+			("unsubscribe_hello", |_id| future::ok(core::Value::Bool(true))),
+			|params: &core::Params, meta: &AuthMetadata| {
+				let is_restricted = *params == core::Params::Array(vec![core::Value::String("admin".into())]);
+				if is_restricted && !meta.is_admin {
+					Err(core::Error {
+						code: core::ErrorCode::ServerError(-32000),
+						message: "Unauthorized topic".into(),
+						data: None,
+					})
+				} else {
+					Ok(())
+				}
+			},
+		);
+		let req = r#"{"jsonrpc":"2.0","id":1,"method":"subscribe_hello","params":["admin"]}"#;
+
+		// when
+		let unauthorized = handler.handle_request_sync(req, AuthMetadata { is_admin: false });
+		let authorized = handler.handle_request_sync(req, AuthMetadata { is_admin: true });
+
+		// then
+		let error = r#"{"jsonrpc":"2.0","error":{"code":-32000,"message":"Unauthorized topic"},"id":1}"#;
+		assert_eq!(unauthorized, Some(error.into()));
+		assert_eq!(authorized, Some(r#"{"jsonrpc":"2.0","result":5,"id":1}"#.into()));
+	}
 }
@@ -4,6 +4,11 @@
 
 extern crate jsonrpc_core as core;
 extern crate parking_lot;
+extern crate serde;
+
+#[cfg(test)]
+#[macro_use]
+extern crate serde_derive;
 
 #[macro_use]
 extern crate log;
@@ -13,5 +18,7 @@ mod subscription;
 mod types;
 
 pub use self::handler::{PubSubHandler, SubscribeRpcMethod, UnsubscribeRpcMethod};
-pub use self::subscription::{Session, Sink, Subscriber, new_subscription};
+pub use self::subscription::{
+	Session, Sink, Subscriber, new_subscription, BufferedSink, OverflowPolicy, Delivery, SinkClosed, DebouncedSink, TypedSink,
+};
 pub use self::types::{PubSubMetadata, SubscriptionId, TransportError, SinkResult};
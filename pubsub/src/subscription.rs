@@ -1,9 +1,13 @@
 //! Subscription primitives.
 
 use std::fmt;
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
+use std::marker::PhantomData;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
 use parking_lot::Mutex;
+use serde::Serialize;
 
 use core::{self, BoxFuture};
 use core::futures::{self, future, Sink as FuturesSink, Future};
@@ -18,6 +22,9 @@ pub struct Session {
 	active_subscriptions: Mutex<HashMap<(SubscriptionId, String), Box<Fn(SubscriptionId) + Send + 'static>>>,
 	transport: TransportSender,
 	on_drop: Mutex<Vec<Box<Fn() + Send>>>,
+	max_subscriptions: Option<usize>,
+	/// Number of subscriptions being created, counted against `max_subscriptions`.
+	pending_subscriptions: AtomicUsize,
 }
 
 impl fmt::Debug for Session {
@@ -37,9 +44,20 @@ impl Session {
 			active_subscriptions: Default::default(),
 			transport: sender,
 			on_drop: Default::default(),
+			max_subscriptions: None,
+			pending_subscriptions: Default::default(),
 		}
 	}
 
+	/// Limits the number of subscriptions active at once in this session, `None` means no limit.
+	/// Subscribe calls over the limit fail with `-32000` error.
+	///
+	/// Subscriptions still waiting for an id count against the limit as well.
+	pub fn max_subscriptions<T: Into<Option<usize>>>(mut self, limit: T) -> Self {
+		self.max_subscriptions = limit.into();
+		self
+	}
+
 	/// Returns transport write stream
 	pub fn sender(&self) -> TransportSender {
 		self.transport.clone()
@@ -65,6 +83,27 @@ impl Session {
 	fn remove_subscription(&self, name: &str, id: &SubscriptionId) {
 		self.active_subscriptions.lock().remove(&(id.clone(), name.into()));
 	}
+
+	/// Reserves a slot for a subscription being created, unless no more subscriptions are allowed.
+	fn reserve_subscription(session: &Arc<Session>) -> Option<Reservation> {
+		let active = session.active_subscriptions.lock();
+		let pending = session.pending_subscriptions.load(Ordering::SeqCst);
+		if session.max_subscriptions.map_or(false, |limit| active.len() + pending >= limit) {
+			return None;
+		}
+
+		session.pending_subscriptions.fetch_add(1, Ordering::SeqCst);
+		Some(Reservation(session.clone()))
+	}
+}
+
+/// A slot reserved for a subscription being created, released when dropped.
+struct Reservation(Arc<Session>);
+
+impl Drop for Reservation {
+	fn drop(&mut self) {
+		self.0.pending_subscriptions.fetch_sub(1, Ordering::SeqCst);
+	}
 }
 
 impl Drop for Session {
@@ -95,6 +134,42 @@ impl Sink {
 		self.transport.clone().send(val.0)
 	}
 
+	/// Wraps this sink with a queue holding up to `capacity` notifications
+	/// that couldn't be delivered immediately because the transport is busy.
+	///
+	/// When the queue is full given `policy` is applied.
+	pub fn buffered(self, capacity: usize, policy: OverflowPolicy) -> BufferedSink {
+		assert!(capacity > 0, "Buffer capacity has to be greater than zero.");
+		BufferedSink {
+			notification: self.notification,
+			capacity,
+			policy,
+			state: Arc::new(Mutex::new(BufferState {
+				transport: self.transport,
+				queue: VecDeque::with_capacity(capacity),
+				closed: false,
+			})),
+		}
+	}
+
+	/// Wraps this sink to skip notifications identical to the previous one
+	/// if it was sent less than `window` ago.
+	pub fn debounce(self, window: Duration) -> DebouncedSink {
+		DebouncedSink {
+			sink: self,
+			window,
+			last: Default::default(),
+		}
+	}
+
+	/// Wraps this sink to send notifications with parameters serialized from values of type `T`.
+	pub fn typed<T: Serialize>(self) -> TypedSink<T> {
+		TypedSink {
+			sink: self,
+			_data: PhantomData,
+		}
+	}
+
 	fn params_to_string(&self, val: core::Params) -> (String, core::Params) {
 		let notification = core::Notification {
 			jsonrpc: Some(core::Version::V2),
@@ -129,6 +204,184 @@ impl FuturesSink for Sink {
 	}
 }
 
+/// A `Sink` sending notifications with serialized values of type `T`.
+///
+/// Values serialized to objects or arrays are sent as by-name or positional parameters,
+/// any other value is sent as a single positional parameter.
+#[derive(Debug, Clone)]
+pub struct TypedSink<T> {
+	sink: Sink,
+	_data: PhantomData<T>,
+}
+
+impl<T: Serialize> TypedSink<T> {
+	/// Sends a notification to a client.
+	pub fn notify(&self, val: T) -> SinkResult {
+		let params = match core::to_value(val).expect("Expected always-serializable type.") {
+			core::Value::Object(map) => core::Params::Map(map),
+			core::Value::Array(vec) => core::Params::Array(vec),
+			value => core::Params::Array(vec![value]),
+		};
+		self.sink.notify(params)
+	}
+}
+
+/// A `Sink` coalescing consecutive identical notifications within a debounce window.
+#[derive(Debug, Clone)]
+pub struct DebouncedSink {
+	sink: Sink,
+	window: Duration,
+	last: Arc<Mutex<Option<(core::Params, Instant)>>>,
+}
+
+impl DebouncedSink {
+	/// Sends a notification to a client.
+	///
+	/// Returns `None` if the notification was skipped, because the same
+	/// notification was already sent within the debounce window.
+	pub fn notify(&self, val: core::Params) -> Option<SinkResult> {
+		let now = Instant::now();
+		let mut last = self.last.lock();
+		if let Some((ref params, sent)) = *last {
+			if *params == val && now.duration_since(sent) < self.window {
+				return None;
+			}
+		}
+
+		*last = Some((val.clone(), now));
+		Some(self.sink.notify(val))
+	}
+}
+
+/// Action taken when the queue of `BufferedSink` is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverflowPolicy {
+	/// Drop the oldest queued notification to make room for the new one.
+	DropOldest,
+	/// Drop the new notification.
+	DropNewest,
+	/// Drop all queued notifications and close the sink.
+	CloseSubscription,
+}
+
+/// Outcome of `BufferedSink::notify`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Delivery {
+	/// Notification was passed to the transport.
+	Sent,
+	/// Transport is busy, notification was queued and will be retried.
+	Queued,
+	/// Queue is full, notification was dropped (`OverflowPolicy::DropNewest`).
+	Dropped,
+}
+
+/// Error returned when sending through closed `BufferedSink`.
+///
+/// The sink is closed either by `OverflowPolicy::CloseSubscription` or when the transport is gone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SinkClosed;
+
+#[derive(Debug)]
+struct BufferState {
+	transport: TransportSender,
+	queue: VecDeque<String>,
+	closed: bool,
+}
+
+impl BufferState {
+	/// Passes queued notifications to the transport until it's busy.
+	fn flush(&mut self) -> Result<(), SinkClosed> {
+		if self.closed {
+			return Err(SinkClosed);
+		}
+
+		while let Some(notification) = self.queue.pop_front() {
+			match self.transport.try_send(notification) {
+				Ok(()) => {},
+				Err(e) => if e.is_full() {
+					self.queue.push_front(e.into_inner());
+					break;
+				} else {
+					self.close();
+					return Err(SinkClosed);
+				},
+			}
+		}
+
+		Ok(())
+	}
+
+	fn close(&mut self) {
+		self.closed = true;
+		self.queue.clear();
+	}
+}
+
+/// A `Sink` with bounded queue of pending notifications.
+///
+/// Notifications that can't be delivered immediately are queued and retried
+/// on subsequent `notify` or `flush` calls.
+#[derive(Debug, Clone)]
+pub struct BufferedSink {
+	notification: String,
+	capacity: usize,
+	policy: OverflowPolicy,
+	state: Arc<Mutex<BufferState>>,
+}
+
+impl BufferedSink {
+	/// Sends (or queues) a notification to a client.
+	pub fn notify(&self, val: core::Params) -> Result<Delivery, SinkClosed> {
+		let notification = core::to_string(&core::Notification {
+			jsonrpc: Some(core::Version::V2),
+			method: self.notification.clone(),
+			params: val,
+		}).expect("Notification serialization never fails.");
+
+		let mut state = self.state.lock();
+		state.flush()?;
+
+		if state.queue.is_empty() {
+			return match state.transport.try_send(notification) {
+				Ok(()) => Ok(Delivery::Sent),
+				Err(e) => if e.is_full() {
+					state.queue.push_back(e.into_inner());
+					Ok(Delivery::Queued)
+				} else {
+					state.close();
+					Err(SinkClosed)
+				},
+			};
+		}
+
+		if state.queue.len() >= self.capacity {
+			match self.policy {
+				OverflowPolicy::DropOldest => {
+					state.queue.pop_front();
+				},
+				OverflowPolicy::DropNewest => return Ok(Delivery::Dropped),
+				OverflowPolicy::CloseSubscription => {
+					state.close();
+					return Err(SinkClosed);
+				},
+			}
+		}
+
+		state.queue.push_back(notification);
+		Ok(Delivery::Queued)
+	}
+
+	/// Retries delivery of queued notifications.
+	pub fn flush(&self) -> Result<(), SinkClosed> {
+		self.state.lock().flush()
+	}
+
+	/// Returns number of queued notifications.
+	pub fn queued(&self) -> usize {
+		self.state.lock().queue.len()
+	}
+}
+
 /// Represents a subscribing client.
 /// Subscription handlers can either reject this subscription request or assign an unique id.
 #[derive(Debug)]
@@ -208,6 +461,14 @@ fn subscription_rejected() -> core::Error {
 	}
 }
 
+fn too_many_subscriptions() -> core::Error {
+	core::Error {
+		code: core::ErrorCode::ServerError(-32000),
+		message: "Too many subscriptions".into(),
+		data: None,
+	}
+}
+
 fn subscriptions_unavailable() -> core::Error {
 	core::Error {
 		code: core::ErrorCode::ServerError(-32090),
@@ -231,6 +492,11 @@ impl<M, F, G> core::RpcMethod<M> for Subscribe<F, G> where
 	fn call(&self, params: core::Params, meta: M) -> BoxFuture<core::Value> {
 		match meta.session() {
 			Some(session) => {
+				// Released once the subscription is assigned an id or rejected.
+				let reservation = match Session::reserve_subscription(&session) {
+					Some(reservation) => reservation,
+					None => return Box::new(future::err(too_many_subscriptions())),
+				};
 				let (tx, rx) = oneshot::channel();
 
 				// Register the subscription
@@ -246,6 +512,7 @@ impl<M, F, G> core::RpcMethod<M> for Subscribe<F, G> where
 				let subscribe_future = rx
 					.map_err(|_| subscription_rejected())
 					.and_then(move |result| {
+						let _reservation = reservation;
 						futures::done(match result {
 							Ok(id) => {
 								session.add_subscription(&notification, &id, move |id| {
@@ -294,14 +561,14 @@ impl<M, G> core::RpcMethod<M> for Unsubscribe<G> where
 #[cfg(test)]
 mod tests {
 	use std::sync::Arc;
-	use std::sync::atomic::{AtomicBool, Ordering};
+	use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 	use core;
 	use core::RpcMethod;
 	use core::futures::{Async, Future, Stream};
 	use core::futures::sync::{mpsc, oneshot};
 	use types::{SubscriptionId, PubSubMetadata};
 
-	use super::{Session, Sink, Subscriber, new_subscription};
+	use super::{Session, Sink, Subscriber, new_subscription, OverflowPolicy, Delivery, SinkClosed};
 
 	fn session() -> (Session, mpsc::Receiver<String>) {
 		let (tx, rx) = mpsc::channel(1);
@@ -385,6 +652,113 @@ mod tests {
 		);
 	}
 
+	fn flood(policy: OverflowPolicy) -> (Vec<Result<Delivery, SinkClosed>>, Vec<String>) {
+		core::futures::lazy(move || {
+			// given
+			let (tx, mut rx) = mpsc::channel(0);
+			let sink = Sink {
+				notification: "test".into(),
+				transport: tx,
+			}.buffered(2, policy);
+
+			// when
+			let results = (1..5)
+				.map(|i| sink.notify(core::Params::Array(vec![core::Value::Number(i.into())])))
+				.collect();
+			let mut received = Vec::new();
+			while let Async::Ready(Some(notification)) = rx.poll().unwrap() {
+				received.push(notification);
+				let _ = sink.flush();
+			}
+
+			Ok::<_, ()>((results, received))
+		}).wait().unwrap()
+	}
+
+	fn notification(i: u64) -> String {
+		format!(r#"{{"jsonrpc":"2.0","method":"test","params":[{}]}}"#, i)
+	}
+
+	#[test]
+	fn should_drop_oldest_notification_on_overflow() {
+		// when
+		let (results, received) = flood(OverflowPolicy::DropOldest);
+
+		// then
+		assert_eq!(results, vec![Ok(Delivery::Sent), Ok(Delivery::Queued), Ok(Delivery::Queued), Ok(Delivery::Queued)]);
+		assert_eq!(received, vec![notification(1), notification(3), notification(4)]);
+	}
+
+	#[test]
+	fn should_drop_newest_notification_on_overflow() {
+		// when
+		let (results, received) = flood(OverflowPolicy::DropNewest);
+
+		// then
+		assert_eq!(results, vec![Ok(Delivery::Sent), Ok(Delivery::Queued), Ok(Delivery::Queued), Ok(Delivery::Dropped)]);
+		assert_eq!(received, vec![notification(1), notification(2), notification(3)]);
+	}
+
+	#[test]
+	fn should_close_subscription_on_overflow() {
+		// when
+		let (results, received) = flood(OverflowPolicy::CloseSubscription);
+
+		// then
+		assert_eq!(results, vec![Ok(Delivery::Sent), Ok(Delivery::Queued), Ok(Delivery::Queued), Err(SinkClosed)]);
+		assert_eq!(received, vec![notification(1)]);
+	}
+
+	#[test]
+	fn should_skip_identical_notifications_within_debounce_window() {
+		use std::time::Duration;
+
+		// given
+		let (tx, rx) = mpsc::channel(8);
+		let sink = Sink {
+			notification: "test".into(),
+			transport: tx,
+		}.debounce(Duration::from_secs(60));
+
+		// when
+		let sent: Vec<_> = (0..3)
+			.map(|_| sink.notify(core::Params::Array(vec![core::Value::Number(1.into())])))
+			.map(|result| result.map(|send| send.wait().unwrap()).is_some())
+			.collect();
+		sink.notify(core::Params::Array(vec![core::Value::Number(2.into())])).unwrap().wait().unwrap();
+		drop(sink);
+
+		// then
+		assert_eq!(sent, vec![true, false, false]);
+		assert_eq!(rx.collect().wait().unwrap(), vec![notification(1), notification(2)]);
+	}
+
+	#[test]
+	fn should_send_serialized_values_through_typed_sink() {
+		#[derive(Serialize)]
+		struct Event {
+			block: u64,
+			hash: &'static str,
+		}
+
+		// given
+		let (tx, rx) = mpsc::channel(8);
+		let sink = Sink {
+			notification: "test".into(),
+			transport: tx,
+		}.typed::<Event>();
+
+		// when
+		sink.notify(Event { block: 5, hash: "0x01" }).wait().unwrap();
+		drop(sink);
+
+		// then
+		assert_eq!(
+			rx.collect().wait().unwrap(),
+			vec![r#"{"jsonrpc":"2.0","method":"test","params":{"block":5,"hash":"0x01"}}"#.to_owned()]
+		);
+	}
+
 	#[test]
 	fn should_assign_id() {
 		// given
@@ -468,4 +842,76 @@ mod tests {
 			data: None,
 		}));
 	}
+
+	#[derive(Clone)]
+	struct SessionMetadata(Arc<Session>);
+	impl core::Metadata for SessionMetadata {}
+	impl PubSubMetadata for SessionMetadata {
+		fn session(&self) -> Option<Arc<Session>> {
+			Some(self.0.clone())
+		}
+	}
+
+	#[test]
+	fn should_reject_subscriptions_over_session_limit() {
+		// given
+		let next_id = AtomicUsize::new(1);
+		let (subscribe, _) = new_subscription(
+			"test".into(),
+			move |_params, _meta, subscriber: Subscriber| {
+				let id = next_id.fetch_add(1, Ordering::SeqCst) as u64;
+				subscriber.assign_id(SubscriptionId::Number(id)).unwrap();
+			},
+			|_id| Ok(core::Value::Bool(true)),
+		);
+		let meta = SessionMetadata(Arc::new(session().0.max_subscriptions(2)));
+
+		// when
+		let results: Vec<_> = (0..3).map(|_| subscribe.call(core::Params::None, meta.clone()).wait()).collect();
+
+		// then
+		assert_eq!(results, vec![
+			Ok(core::Value::Number(1.into())),
+			Ok(core::Value::Number(2.into())),
+			Err(core::Error {
+				code: core::ErrorCode::ServerError(-32000),
+				message: "Too many subscriptions".into(),
+				data: None,
+			}),
+		]);
+	}
+
+	#[test]
+	fn should_count_pending_subscriptions_against_session_limit() {
+		// given
+		let subscribers = Arc::new(::std::sync::Mutex::new(Vec::new()));
+		let (subscribe, _) = {
+			let subscribers = subscribers.clone();
+			new_subscription(
+				"test".into(),
+				move |_params, _meta, subscriber: Subscriber| subscribers.lock().unwrap().push(subscriber),
+				|_id| Ok(core::Value::Bool(true)),
+			)
+		};
+		let session = Arc::new(session().0.max_subscriptions(1));
+		let meta = SessionMetadata(session.clone());
+
+		// when
+		let pending = subscribe.call(core::Params::None, meta.clone());
+		let over_limit = subscribe.call(core::Params::None, meta.clone()).wait();
+		let subscriber = subscribers.lock().unwrap().pop().unwrap();
+		subscriber.reject(core::Error::internal_error()).unwrap();
+		let _ = pending.wait();
+		let after_reject = subscribe.call(core::Params::None, meta.clone());
+
+		// then
+		assert_eq!(over_limit, Err(core::Error {
+			code: core::ErrorCode::ServerError(-32000),
+			message: "Too many subscriptions".into(),
+			data: None,
+		}));
+		assert_eq!(subscribers.lock().unwrap().len(), 1);
+		drop(after_reject);
+		assert_eq!(session.pending_subscriptions.load(Ordering::SeqCst), 0);
+	}
 }
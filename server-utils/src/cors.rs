@@ -1,6 +1,8 @@
 //! CORS handling utility functions
 
-use std::{fmt, ops};
+use std::{error, fmt, ops};
+use std::str::FromStr;
+use std::net::Ipv6Addr;
 use hosts::{Host, Port};
 use matcher::{Matcher, Pattern};
 
@@ -15,6 +17,17 @@ pub enum OriginProtocol {
 	Custom(String),
 }
 
+impl OriginProtocol {
+	/// Parses protocol given the scheme, e.g. `https`.
+	pub fn parse(scheme: &str) -> Self {
+		match scheme.to_lowercase() {
+			ref p if p == "http" => OriginProtocol::Http,
+			ref p if p == "https" => OriginProtocol::Https,
+			other => OriginProtocol::Custom(other),
+		}
+	}
+}
+
 /// Request Origin
 #[derive(Clone, PartialEq, Eq, Debug, Hash)]
 pub struct Origin {
@@ -61,15 +74,8 @@ impl Origin {
 			Some(hostname) => (Some(proto), hostname),
 		};
 
-		let proto = proto.map(str::to_lowercase);
 		let hostname = Host::parse(hostname);
-
-		let protocol = match proto {
-			None => OriginProtocol::Http,
-			Some(ref p) if p == "http" => OriginProtocol::Http,
-			Some(ref p) if p == "https" => OriginProtocol::Https,
-			Some(other) => OriginProtocol::Custom(other),
-		};
+		let protocol = proto.map_or(OriginProtocol::Http, OriginProtocol::parse);
 
 		Origin::with_host(protocol, hostname)
 	}
@@ -89,7 +95,14 @@ impl Origin {
 
 impl Pattern for Origin {
 	fn matches<T: AsRef<str>>(&self, other: T) -> bool {
-		self.matcher.matches(other)
+		let other = other.as_ref();
+		if self.matcher.matches(other) {
+			return true;
+		}
+
+		// Wildcard port (`parity.io:*`) matches origins using the default port as well.
+		let has_port = other.rsplit("://").next().map_or(false, |authority| authority.contains(':'));
+		!has_port && self.as_string.ends_with(":*") && self.matcher.matches(format!("{}:", other))
 	}
 }
 
@@ -131,6 +144,76 @@ impl<T: Into<String>> From<T> for AccessControlAllowOrigin {
 	}
 }
 
+impl FromStr for AccessControlAllowOrigin {
+	type Err = OriginParseError;
+
+	/// Parses and validates an origin.
+	///
+	/// Accepts `*` (or `all`, `any`), `null` and origins in `scheme://host[:port]` format.
+	/// Unlike the `From` implementation, malformed origins are rejected.
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"all" | "*" | "any" => return Ok(AccessControlAllowOrigin::Any),
+			"null" => return Ok(AccessControlAllowOrigin::Null),
+			_ => {},
+		}
+
+		let err = || OriginParseError(s.to_owned());
+		let mut it = s.splitn(2, "://");
+		let scheme = it.next().expect("split always returns non-empty iterator.");
+		let authority = it.next().ok_or_else(&err)?;
+
+		let is_valid_scheme = scheme.chars().next().map_or(false, |c| c.is_ascii_alphabetic())
+			&& scheme.chars().all(|c| c.is_ascii_alphanumeric() || c == '+' || c == '-' || c == '.');
+		if !is_valid_scheme {
+			return Err(err());
+		}
+
+		// IPv6 hosts are enclosed in brackets, since they contain colons themselves.
+		let (host, port) = if authority.starts_with('[') {
+			let end = authority.find(']').ok_or_else(&err)?;
+			let port = match &authority[end + 1..] {
+				"" => None,
+				rest if rest.starts_with(':') => Some(&rest[1..]),
+				_ => return Err(err()),
+			};
+			(&authority[..end + 1], port)
+		} else {
+			match authority.rfind(':') {
+				Some(idx) => (&authority[..idx], Some(&authority[idx + 1..])),
+				None => (authority, None),
+			}
+		};
+		let is_valid_host = if host.starts_with('[') {
+			host[1..host.len() - 1].parse::<Ipv6Addr>().is_ok()
+		} else {
+			!host.is_empty() && host.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.' || c == '*')
+		};
+		let is_valid_port = port.map_or(true, |port| port == "*" || port.parse::<u16>().is_ok());
+		if !is_valid_host || !is_valid_port {
+			return Err(err());
+		}
+
+		Ok(AccessControlAllowOrigin::Value(s.into()))
+	}
+}
+
+/// Error returned when parsing a malformed `AccessControlAllowOrigin`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OriginParseError(String);
+
+impl fmt::Display for OriginParseError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "Invalid origin: {}. Expected `scheme://host[:port]`, `*` or `null`.", self.0)
+	}
+}
+
+impl error::Error for OriginParseError {
+	fn description(&self) -> &str {
+		"invalid origin"
+	}
+}
+
 /// CORS Header Result.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum CorsHeader<T = AccessControlAllowOrigin> {
@@ -170,6 +253,18 @@ impl<T> Into<Option<T>> for CorsHeader<T> {
 
 /// Returns correct CORS header (if any) given list of allowed origins and current origin.
 pub fn get_cors_header(origin: Option<&str>, host: Option<&str>, allowed: &Option<Vec<AccessControlAllowOrigin>>) -> CorsHeader {
+	get_cors_header_for_scheme(origin, host, None, allowed)
+}
+
+/// Returns correct CORS header (if any) given list of allowed origins, current origin and the scheme of the request.
+///
+/// If the scheme is given, the request is considered same-origin only if `origin` uses the same scheme.
+pub fn get_cors_header_for_scheme(
+	origin: Option<&str>,
+	host: Option<&str>,
+	scheme: Option<&str>,
+	allowed: &Option<Vec<AccessControlAllowOrigin>>,
+) -> CorsHeader {
 	match origin {
 		None => CorsHeader::NotRequired,
 		Some(ref origin) => {
@@ -178,7 +273,8 @@ pub fn get_cors_header(origin: Option<&str>, host: Option<&str>, allowed: &Optio
 				if origin.ends_with(host) {
 					// Additional check
 					let origin = Origin::parse(origin);
-					if &*origin.host == host {
+					let same_scheme = scheme.map_or(true, |scheme| origin.protocol == OriginProtocol::parse(scheme));
+					if &*origin.host == host && same_scheme {
 						return CorsHeader::NotRequired;
 					}
 				}
@@ -212,7 +308,7 @@ pub fn get_cors_header(origin: Option<&str>, host: Option<&str>, allowed: &Optio
 #[cfg(test)]
 mod tests {
 	use hosts::Host;
-	use super::{get_cors_header, CorsHeader, AccessControlAllowOrigin, Origin, OriginProtocol};
+	use super::{get_cors_header, get_cors_header_for_scheme, CorsHeader, AccessControlAllowOrigin, Origin, OriginProtocol, OriginParseError};
 
 	#[test]
 	fn should_parse_origin() {
@@ -225,6 +321,32 @@ mod tests {
 		assert_eq!(Origin::parse("127.0.0.1:8545/somepath"), Origin::new(Http, "127.0.0.1", Some(8545)));
 	}
 
+	#[test]
+	fn should_parse_and_validate_allowed_origins() {
+		use self::AccessControlAllowOrigin::*;
+
+		assert_eq!("*".parse(), Ok(Any));
+		assert_eq!("null".parse(), Ok(Null));
+		assert_eq!("http://parity.io".parse(), Ok(Value("http://parity.io".into())));
+		assert_eq!("https://parity.io:8443".parse(), Ok(Value("https://parity.io:8443".into())));
+		assert_eq!("http://*.parity.io:*".parse(), Ok(Value("http://*.parity.io:*".into())));
+		assert_eq!("chrome-extension://abcdef".parse(), Ok(Value("chrome-extension://abcdef".into())));
+		assert_eq!("http://[::1]:8545".parse(), Ok(Value("http://[::1]:8545".into())));
+		assert_eq!("https://[2001:db8::1]".parse(), Ok(Value("https://[2001:db8::1]".into())));
+
+		let invalid = |s: &str| Err::<AccessControlAllowOrigin, _>(OriginParseError(s.into()));
+		assert_eq!("parity.io".parse(), invalid("parity.io"));
+		assert_eq!("http://".parse(), invalid("http://"));
+		assert_eq!("://parity.io".parse(), invalid("://parity.io"));
+		assert_eq!("http://parity.io/somepath".parse(), invalid("http://parity.io/somepath"));
+		assert_eq!("http://parity.io:99999".parse(), invalid("http://parity.io:99999"));
+		assert_eq!("http://parity .io".parse(), invalid("http://parity .io"));
+		assert_eq!("http://[::1".parse(), invalid("http://[::1"));
+		assert_eq!("http://[::1]8545".parse(), invalid("http://[::1]8545"));
+		assert_eq!("http://[parity.io]:8545".parse(), invalid("http://[parity.io]:8545"));
+		assert_eq!("http://::1:8545".parse(), invalid("http://::1:8545"));
+	}
+
 	#[test]
 	fn should_not_allow_partially_matching_origin() {
 		// given
@@ -261,6 +383,25 @@ mod tests {
 		assert_eq!(res, CorsHeader::NotRequired);
 	}
 
+	#[test]
+	fn should_require_matching_scheme_for_same_origin_if_scheme_is_known() {
+		// given
+		let origin = Origin::parse("https://127.0.0.1:8080");
+		let host = Host::parse("http://127.0.0.1:8080");
+
+		let origin = Some(&*origin);
+		let host = Some(&*host);
+		let allowed = Some(vec![]);
+
+		// when
+		let res1 = get_cors_header_for_scheme(origin, host, Some("https"), &allowed);
+		let res2 = get_cors_header_for_scheme(origin, host, Some("http"), &allowed);
+
+		// then
+		assert_eq!(res1, CorsHeader::NotRequired);
+		assert_eq!(res2, CorsHeader::Invalid);
+	}
+
 	#[test]
 	fn should_return_none_when_there_are_no_cors_domains_and_no_origin() {
 		// given
@@ -420,4 +561,25 @@ mod tests {
 		assert_eq!(res2, CorsHeader::Invalid);
 		assert_eq!(res3, CorsHeader::Ok(AccessControlAllowOrigin::Value("chrome-extension://test".into())));
 	}
+
+	#[test]
+	fn should_support_wildcard_ports() {
+		// given
+		let host = None;
+		let allowed = Some(vec![
+			AccessControlAllowOrigin::Value("ethcore.io:*".into()),
+		]);
+
+		// when
+		let res1 = get_cors_header(Some("http://ethcore.io"), host, &allowed);
+		let res2 = get_cors_header(Some("http://ethcore.io:8080"), host, &allowed);
+		let res3 = get_cors_header(Some("http://ethcore.io.evil.com:8080"), host, &allowed);
+		let res4 = get_cors_header(Some("https://ethcore.io:3000"), host, &allowed);
+
+		// then
+		assert_eq!(res1, CorsHeader::Ok(AccessControlAllowOrigin::Value("http://ethcore.io".into())));
+		assert_eq!(res2, CorsHeader::Ok(AccessControlAllowOrigin::Value("http://ethcore.io:8080".into())));
+		assert_eq!(res3, CorsHeader::Invalid);
+		assert_eq!(res4, CorsHeader::Invalid);
+	}
 }
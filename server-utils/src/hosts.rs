@@ -108,7 +108,18 @@ impl Host {
 
 impl Pattern for Host {
 	fn matches<T: AsRef<str>>(&self, other: T) -> bool {
-		self.matcher.matches(other)
+		let other = other.as_ref();
+		if !self.matcher.matches(other) {
+			return false;
+		}
+
+		// Wildcard subdomain (`*.parity.io`) matches only a single level of subdomains.
+		if self.hostname.starts_with("*.") {
+			let hostname = other.split(':').next().expect(SPLIT_PROOF);
+			return hostname.split('.').count() == self.hostname.split('.').count();
+		}
+
+		true
 	}
 }
 
@@ -228,4 +239,15 @@ mod tests {
 		);
 		assert_eq!(valid, true);
 	}
+
+	#[test]
+	fn should_match_single_level_of_wildcard_subdomains() {
+		let hosts = Some(vec!["*.ethcore.io".into()]);
+
+		assert_eq!(is_host_valid(Some("api.ethcore.io"), &hosts), true);
+		assert_eq!(is_host_valid(Some("RPC.Ethcore.IO"), &hosts), true);
+		assert_eq!(is_host_valid(Some("ethcore.io"), &hosts), false);
+		assert_eq!(is_host_valid(Some("a.rpc.ethcore.io"), &hosts), false);
+		assert_eq!(is_host_valid(Some("api.ethcore.io.evil.com"), &hosts), false);
+	}
 }
@@ -9,6 +9,8 @@ pub struct RequestContext {
 	pub peer_addr: SocketAddr,
 	/// Peer Sender channel
 	pub sender: mpsc::Sender<String>,
+	/// Maximal number of subscriptions per connection, see `ServerBuilder::max_subscriptions_per_connection`.
+	pub max_subscriptions: Option<usize>,
 }
 
 /// Metadata extractor (per session)
@@ -22,6 +22,7 @@ pub struct ServerBuilder<M: Metadata = (), S: Middleware<M> = NoopMiddleware> {
 	channels: Arc<SenderChannels>,
 	incoming_separator: codecs::Separator,
 	outgoing_separator: codecs::Separator,
+	max_subscriptions: Option<usize>,
 }
 
 impl<M: Metadata + Default, S: Middleware<M> + 'static> ServerBuilder<M, S> {
@@ -46,6 +47,7 @@ impl<M: Metadata, S: Middleware<M> + 'static> ServerBuilder<M, S> {
 			channels: Default::default(),
 			incoming_separator: Default::default(),
 			outgoing_separator: Default::default(),
+			max_subscriptions: None,
 		}
 	}
 
@@ -68,6 +70,15 @@ impl<M: Metadata, S: Middleware<M> + 'static> ServerBuilder<M, S> {
 		self
 	}
 
+	/// Limits the number of subscriptions active at once on a single connection.
+	///
+	/// The limit is passed to metadata extractors in `RequestContext::max_subscriptions`,
+	/// to be applied to pub-sub sessions with `Session::max_subscriptions`.
+	pub fn max_subscriptions_per_connection(mut self, limit: usize) -> Self {
+		self.max_subscriptions = Some(limit);
+		self
+	}
+
 	/// Starts a new server
 	pub fn start(self, addr: &SocketAddr) -> std::io::Result<Server> {
 		let meta_extractor = self.meta_extractor.clone();
@@ -75,6 +86,7 @@ impl<M: Metadata, S: Middleware<M> + 'static> ServerBuilder<M, S> {
 		let channels = self.channels.clone();
 		let incoming_separator = self.incoming_separator;
 		let outgoing_separator = self.outgoing_separator;
+		let max_subscriptions = self.max_subscriptions;
 		let address = addr.to_owned();
 		let (tx, rx) = std::sync::mpsc::channel();
 		let (signal, stop) = oneshot::channel();
@@ -93,6 +105,7 @@ impl<M: Metadata, S: Middleware<M> + 'static> ServerBuilder<M, S> {
 					let context = RequestContext {
 						peer_addr: peer_addr,
 						sender: sender.clone(),
+						max_subscriptions,
 					};
 
 					let meta = meta_extractor.extract(&context);
@@ -119,7 +132,9 @@ impl<M: Metadata, S: Middleware<M> + 'static> ServerBuilder<M, S> {
 								future::ok(response_data)
 							}
 						})
-					);
+					)
+					// Requests without a response (e.g. notifications) don't produce any line.
+					.filter(|response| !response.is_empty());
 
 					let peer_message_queue = {
 						let mut channels = channels.lock();
@@ -116,6 +116,31 @@ fn doc_test_handle() {
 		);
 }
 
+#[test]
+fn multiple_requests_on_one_connection() {
+	use std::io::{BufRead, BufReader, Write};
+
+	::logger::init_log();
+	let addr: SocketAddr = "127.0.0.1:17795".parse().unwrap();
+	let server = casual_server();
+	let _server = server.start(&addr).expect("Server must run with no issues");
+
+	let mut stream = ::std::net::TcpStream::connect(&addr).expect("Connection should be established");
+	stream.write_all(b"\
+		{\"jsonrpc\": \"2.0\", \"method\": \"say_hello\", \"id\": 1}\n\
+		{\"jsonrpc\": \"2.0\", \"method\": \"say_hello\"}\n\
+		{\"jsonrpc\": \"2.0\", \"method\": \"say_hello\", \"id\": 2}\n\
+	").expect("Requests should be written");
+	let mut reader = BufReader::new(stream);
+	let mut first = String::new();
+	let mut second = String::new();
+	reader.read_line(&mut first).expect("First response should be read");
+	reader.read_line(&mut second).expect("Second response should be read");
+
+	assert_eq!(first, "{\"jsonrpc\":\"2.0\",\"result\":\"hello\",\"id\":1}\n");
+	assert_eq!(second, "{\"jsonrpc\":\"2.0\",\"result\":\"hello\",\"id\":2}\n");
+}
+
 #[test]
 fn req_parallel() {
 	use std::thread;
@@ -211,6 +236,32 @@ fn peer_meta() {
 		);
 }
 
+#[derive(Clone, Default)]
+struct SubscriptionsMetadata(Option<usize>);
+impl Metadata for SubscriptionsMetadata {}
+
+#[test]
+fn should_pass_max_subscriptions_to_meta_extractor() {
+	::logger::init_log();
+	let addr: SocketAddr = "127.0.0.1:17786".parse().unwrap();
+	let mut io = MetaIoHandler::<SubscriptionsMetadata>::default();
+	io.add_method_with_meta("max_subscriptions", |_params, meta: SubscriptionsMetadata| {
+		future::ok(meta.0.map(Value::from).unwrap_or(Value::Null))
+	});
+	let _server = ServerBuilder::new(io)
+		.session_meta_extractor(|context: &RequestContext| SubscriptionsMetadata(context.max_subscriptions))
+		.max_subscriptions_per_connection(8)
+		.start(&addr)
+		.expect("Server must run with no issues");
+
+	let result = dummy_request_str(
+		&addr,
+		b"{\"jsonrpc\": \"2.0\", \"method\": \"max_subscriptions\", \"id\": 1}\n"
+	);
+
+	assert_eq!(result, "{\"jsonrpc\":\"2.0\",\"result\":8,\"id\":1}\n");
+}
+
 #[derive(Default)]
 pub struct PeerListMetaExtractor {
 	peers: Arc<Mutex<Vec<SocketAddr>>>,
@@ -75,6 +75,8 @@ pub struct RequestContext {
 	pub out: Sender,
 	/// Remote to underlying event loop.
 	pub remote: Remote,
+	/// Maximal number of subscriptions per connection, see `ServerBuilder::max_subscriptions_per_connection`.
+	pub max_subscriptions: Option<usize>,
 }
 
 impl RequestContext {
@@ -94,6 +96,7 @@ impl fmt::Debug for RequestContext {
 			.field("session_id", &self.session_id)
 			.field("origin", &self.origin)
 			.field("protocols", &self.protocols)
+			.field("max_subscriptions", &self.max_subscriptions)
 			.finish()
 	}
 }
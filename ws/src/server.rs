@@ -50,6 +50,7 @@ impl Server {
 		stats: Option<Arc<SessionStats>>,
 		remote: UninitializedRemote,
 		max_connections: usize,
+		max_subscriptions: Option<usize>,
 	) -> Result<Server> {
 		let config = {
 			let mut config = ws::Settings::default();
@@ -76,7 +77,7 @@ impl Server {
 
 		// Create WebSocket
 		let ws = ws::Builder::new().with_settings(config).build(session::Factory::new(
-			handler, meta_extractor, allowed_origins, allowed_hosts, request_middleware, stats, remote
+			handler, meta_extractor, allowed_origins, allowed_hosts, request_middleware, stats, remote, max_subscriptions
 		))?;
 		let broadcaster = ws.broadcaster();
 
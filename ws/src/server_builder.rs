@@ -23,6 +23,7 @@ pub struct ServerBuilder<M: core::Metadata, S: core::Middleware<M>> {
 	session_stats: Option<Arc<SessionStats>>,
 	remote: UninitializedRemote,
 	max_connections: usize,
+	max_subscriptions: Option<usize>,
 }
 
 impl<M: core::Metadata + Default, S: core::Middleware<M>> ServerBuilder<M, S> {
@@ -49,6 +50,7 @@ impl<M: core::Metadata, S: core::Middleware<M>> ServerBuilder<M, S> {
 			session_stats: None,
 			remote: UninitializedRemote::Unspawned,
 			max_connections: 100,
+			max_subscriptions: None,
 		}
 	}
 
@@ -96,6 +98,15 @@ impl<M: core::Metadata, S: core::Middleware<M>> ServerBuilder<M, S> {
 		self
 	}
 
+	/// Limits the number of subscriptions active at once on a single connection.
+	///
+	/// The limit is passed to metadata extractors in `RequestContext::max_subscriptions`,
+	/// to be applied to pub-sub sessions with `Session::max_subscriptions`.
+	pub fn max_subscriptions_per_connection(mut self, limit: usize) -> Self {
+		self.max_subscriptions = Some(limit);
+		self
+	}
+
 	/// Starts a new `WebSocket` server in separate thread.
 	/// Returns a `Server` handle which closes the server when droped.
 	pub fn start(self, addr: &SocketAddr) -> Result<Server> {
@@ -109,6 +120,7 @@ impl<M: core::Metadata, S: core::Middleware<M>> ServerBuilder<M, S> {
 			self.session_stats,
 			self.remote,
 			self.max_connections,
+			self.max_subscriptions,
 		)
 	}
 
@@ -218,6 +218,13 @@ impl<M: core::Metadata, S: core::Middleware<M>> ws::Handler for Session<M, S> {
 			}
 		}
 
+		// Reject plain HTTP requests that are not handled by the middleware.
+		if let MiddlewareAction::Proceed = action {
+			if !is_upgrade_request(req) {
+				return Ok(upgrade_required());
+			}
+		}
+
 		self.context.origin = origin.and_then(|origin| ::std::str::from_utf8(origin).ok()).map(Into::into);
 		self.context.protocols = req.protocols().ok()
 			.map(|protos| protos.into_iter().map(Into::into).collect())
@@ -283,6 +290,7 @@ pub struct Factory<M: core::Metadata, S: core::Middleware<M>> {
 	request_middleware: Option<Arc<RequestMiddleware>>,
 	stats: Option<Arc<SessionStats>>,
 	remote: Remote,
+	max_subscriptions: Option<usize>,
 }
 
 impl<M: core::Metadata, S: core::Middleware<M>> Factory<M, S> {
@@ -294,6 +302,7 @@ impl<M: core::Metadata, S: core::Middleware<M>> Factory<M, S> {
 		request_middleware: Option<Arc<RequestMiddleware>>,
 		stats: Option<Arc<SessionStats>>,
 		remote: Remote,
+		max_subscriptions: Option<usize>,
 	) -> Self {
 		Factory {
 			session_id: 0,
@@ -304,6 +313,7 @@ impl<M: core::Metadata, S: core::Middleware<M>> Factory<M, S> {
 			request_middleware: request_middleware,
 			stats: stats,
 			remote: remote,
+			max_subscriptions: max_subscriptions,
 		}
 	}
 }
@@ -324,6 +334,7 @@ impl<M: core::Metadata, S: core::Middleware<M>> ws::Factory for Factory<M, S> {
 				protocols: Vec::new(),
 				out: metadata::Sender::new(sender, active),
 				remote: self.remote.clone(),
+				max_subscriptions: self.max_subscriptions,
 			},
 			handler: self.handler.clone(),
 			meta_extractor: self.meta_extractor.clone(),
@@ -374,3 +385,22 @@ fn forbidden(title: &str, message: &str) -> ws::Response {
 	}
 	forbidden
 }
+
+fn is_upgrade_request(req: &ws::Request) -> bool {
+	req.header("upgrade")
+		.and_then(|upgrade| std::str::from_utf8(upgrade).ok())
+		.map_or(false, |upgrade| upgrade.trim().eq_ignore_ascii_case("websocket"))
+}
+
+fn upgrade_required() -> ws::Response {
+	let mut upgrade_required = ws::Response::new(426, "Upgrade Required");
+	upgrade_required.set_body(
+		"Upgrade Required\nThis endpoint only accepts WebSocket connections.\n".as_bytes()
+	);
+	{
+		let headers = upgrade_required.headers_mut();
+		headers.push(("Upgrade".to_owned(), "websocket".as_bytes().to_vec()));
+		headers.push(("Connection".to_owned(), "Upgrade, close".as_bytes().to_vec()));
+	}
+	upgrade_required
+}
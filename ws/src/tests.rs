@@ -168,7 +168,7 @@ fn should_allow_whitelisted_origins() {
 	);
 
 	// then
-	assert_eq!(response.status, "HTTP/1.1 400 Bad Request".to_owned());
+	assert_eq!(response.status, "HTTP/1.1 426 Upgrade Required".to_owned());
 }
 
 #[test]
@@ -193,6 +193,53 @@ fn should_intercept_in_middleware() {
 	assert_eq!(response.body, "Hello World!\n".to_owned());
 }
 
+#[test]
+fn should_require_upgrade_for_plain_http_requests() {
+	// given
+	let (server, _) = serve(30006);
+
+	// when
+	let response = request(server,
+		"\
+			POST / HTTP/1.1\r\n\
+			Host: 127.0.0.1:30006\r\n\
+			Connection: close\r\n\
+			Content-Type: application/json\r\n\
+			Content-Length: 41\r\n\
+			\r\n\
+			{\"jsonrpc\":\"2.0\",\"id\":1,\"method\":\"hello\"}\r\n\
+		"
+	);
+
+	// then
+	assert_eq!(response.status, "HTTP/1.1 426 Upgrade Required".to_owned());
+	assert_eq!(response.body, "Upgrade Required\nThis endpoint only accepts WebSocket connections.\n".to_owned());
+}
+
+#[test]
+fn should_respond_to_calls_over_websocket() {
+	use ws::{connect, CloseCode};
+
+	// given
+	let (_server, _) = serve(30007);
+	let (tx, rx) = mpsc::channel();
+
+	// when
+	connect("ws://127.0.0.1:30007", |out| {
+		out.send(r#"{"jsonrpc":"2.0","id":1,"method":"hello"}"#).unwrap();
+
+		let tx = tx.clone();
+		move |message: ws::Message| {
+			tx.send(message.into_text().unwrap()).unwrap();
+			out.close(CloseCode::Normal)
+		}
+	}).unwrap();
+
+	// then
+	let response = rx.recv_timeout(Duration::from_secs(1)).unwrap();
+	assert_eq!(response, r#"{"jsonrpc":"2.0","result":"world","id":1}"#.to_owned());
+}
+
 #[test]
 fn drop_session_should_cancel() {
 	use ws::{connect, CloseCode};